@@ -0,0 +1,44 @@
+// chunk vertices, for the voxel and shadow shaders. goes after common.glsl
+
+uniform vec2 atlas_size; // in tiles
+// x - left/right/bottom/top tiles, y - back/front tiles, z - flags (see BlockRegistry::shader_data)
+uniform uvec4 block_data[MAX_BLOCKS];
+
+// the origin of the chunk each page of the chunk arena belongs to, see ChunkArena
+uniform isamplerBuffer chunk_origins;
+const int PAGE_VERTICES = 1024; // has to match PAGE_VERTICES in arena.rs
+
+// bottom left corner of the face's tile in the atlas
+vec2 get_tile(int normal, int block_id) {
+    uvec4 data = block_data[block_id];
+    uint tile = normal < 4 ? (data.x >> (normal * 8)) & 255u : (data.y >> ((normal - 4) * 8)) & 255u;
+    vec2 tile_pos = vec2(float(tile % uint(atlas_size.x)), float(tile / uint(atlas_size.x)));
+
+    return vec2(tile_pos.x, atlas_size.y - tile_pos.y - 1.0) / atlas_size;
+}
+
+// in tiles and taken from the position instead of the vertex's corner, so the texture
+// repeats across merged faces instead of stretching over them (voxel.frag wraps it)
+vec2 get_uv(int normal, vec3 pos) {
+    if (normal == 0) return vec2(pos.z, pos.y);
+    if (normal == 1) return vec2(-pos.z, pos.y);
+    if (normal == 2) return vec2(-pos.x, -pos.z);
+    if (normal == 3) return vec2(-pos.x, pos.z);
+    if (normal == 4) return vec2(-pos.x, pos.y);
+    return vec2(pos.x, pos.y);
+}
+
+// x, y and z in sixteenths of a block, 10 bits each
+vec3 unpack_pos(uint position) {
+    return vec3(position & 1023u, (position >> 10) & 1023u, (position >> 20) & 1023u) / 16.0;
+}
+
+// fluid surfaces sit below the top of the block
+float fluid_drop(uint vertex_data) {
+    return float((vertex_data >> 11) & 7u) / 8.0;
+}
+
+// gl_VertexID includes the draw's base vertex, so it's the vertex's place in the arena
+vec3 chunk_origin() {
+    return vec3(texelFetch(chunk_origins, gl_VertexID / PAGE_VERTICES).xyz);
+}
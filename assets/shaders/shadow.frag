@@ -0,0 +1,14 @@
+#version 330 core
+
+in vec2 v_uv; // in tiles
+
+flat in vec2 v_tile;
+
+uniform sampler2D tex;
+uniform vec2 atlas_size; // in tiles
+
+void main() {
+    // light gets through the holes in leaves and plants, same cut out as voxel.frag
+    vec2 uv = v_tile + fract(v_uv) / atlas_size;
+    if (textureGrad(tex, uv, dFdx(v_uv) / atlas_size, dFdy(v_uv) / atlas_size).a < 0.5) discard;
+}
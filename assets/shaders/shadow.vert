@@ -0,0 +1,22 @@
+#version 330 core
+
+#include common.glsl
+#include voxel.glsl
+
+layout(location = 0) in uint position;
+layout(location = 1) in uint vertex_data; // see VoxelVertex
+
+out vec2 v_uv;
+flat out vec2 v_tile;
+
+uniform mat4 light_space; // the cascade's view and projection
+
+void main() {
+    vec3 pos = unpack_pos(position);
+    int normal = int(vertex_data & 7u);
+    v_uv = get_uv(normal, pos);
+    v_tile = get_tile(normal, int((vertex_data >> 5) & 63u));
+    pos.y -= fluid_drop(vertex_data);
+
+    gl_Position = light_space * vec4(chunk_origin() + pos, 1.0);
+}
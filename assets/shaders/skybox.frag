@@ -2,13 +2,72 @@
 
 #include common.glsl
 
+in vec3 v_dir;
+in vec3 v_stars;
 in vec3 v_uv;
 
 out vec4 color;
 
 uniform samplerCube skybox;
 uniform float time;
+uniform float overlay; // how much of the cubemap is drawn over the sky, 0-1
+
+// see SkyColors
+uniform vec3 sun_dir;
+uniform vec3 zenith;
+uniform vec3 horizon;
+uniform vec3 sunset;
+uniform float daylight;
+
+const float sun_size = 0.9995; // cos of the discs' angular radius
+const float moon_size = 0.9993;
+const vec3 sun_color = vec3(1.0, 0.95, 0.8);
+const vec3 moon_color = vec3(0.8, 0.85, 0.95);
+
+float hash(vec3 p) {
+    p = fract(p * vec3(443.897, 441.423, 437.195));
+    p += dot(p, p.yzx + 19.19);
+    return fract((p.x + p.y) * p.z);
+}
+
+// a few stars per cell of a grid around the sky, twinkling a bit
+float stars(vec3 dir) {
+    vec3 cell = floor(dir * 150.0);
+    float star = hash(cell);
+    if (star < 0.997) return 0.0;
+    // dimmer the further from the cell's center
+    vec3 center = (cell + 0.5 + (vec3(hash(cell + 1.0), hash(cell + 2.0), hash(cell + 3.0)) - 0.5) * 0.6) / 150.0;
+    float brightness = max(1.0 - length(dir - center) * 300.0, 0.0);
+    float twinkle = 0.75 + 0.25 * sin(time * 0.05 + star * 1000.0);
+    return brightness * twinkle * (star - 0.997) / 0.003;
+}
 
 void main() {
-    color = mix(mix(vec4(0.0,0.0,0.0,1.0), vec4(1.0,0.37,0.0,1.0), day_factor(time)), texture(skybox, v_uv), day_factor(time));
-}
\ No newline at end of file
+    vec3 dir = normalize(v_dir);
+    float height = max(dir.y, 0.0);
+
+    vec3 sky = mix(horizon, zenith, pow(height, 0.5));
+
+    // sunrises and sunsets light up the sky around the sun, most of all near the horizon
+    float towards_sun = max(dot(dir, sun_dir), 0.0);
+    sky += sunset * pow(towards_sun, 6.0) * (1.0 - height);
+
+    // the stars are behind everything and fade out at dawn and into the horizon's haze
+    sky += vec3(stars(normalize(v_stars))) * (1.0 - daylight) * smoothstep(0.0, 0.2, dir.y);
+
+    // the old cubemap sky, only during the day
+    if (overlay > 0.0) {
+        sky = mix(sky, texture(skybox, v_uv).rgb, overlay * daylight);
+    }
+
+    // nothing in the sky below the horizon, the terrain covers it anyway
+    if (dir.y > -0.05) {
+        // a disc with a soft halo for the sun, a dimmer one for the moon opposite it
+        sky += sun_color * (smoothstep(sun_size - 0.0002, sun_size, towards_sun) + pow(towards_sun, 400.0) * 0.5);
+        float towards_moon = max(dot(dir, -sun_dir), 0.0);
+        sky = mix(sky, moon_color, smoothstep(moon_size - 0.0001, moon_size, towards_moon) * (1.0 - daylight * 0.7));
+        sky += moon_color * pow(towards_moon, 800.0) * 0.2 * (1.0 - daylight);
+    }
+
+    color = vec4(sky, 1.0);
+}
@@ -4,6 +4,8 @@
 
 layout (location = 0) in vec3 pos;
 
+out vec3 v_dir; // in the world
+out vec3 v_stars; // turning with the sun and moon
 out vec3 v_uv;
 
 uniform mat4 projection;
@@ -11,7 +13,9 @@ uniform mat4 view;
 uniform float time;
 
 void main() {
+    v_dir = pos;
+    v_stars = (rotate_x(time / secs_in_day * pi * 2.0) * vec4(pos, 1.0)).xyz;
     v_uv = (rotate_y(time / secs_in_day * -pi * 2) * vec4(pos, 1.0)).xyz;
     vec4 p = projection * mat4(mat3(view)) * vec4(pos, 1.0);
     gl_Position = p.xyww;
-}
\ No newline at end of file
+}
@@ -4,39 +4,100 @@
 
 in vec3 v_pos;
 in vec3 v_normal;
-in vec2 v_uv;
+in vec2 v_uv; // in tiles
 in float v_ao;
+in vec2 v_light;
+in float v_depth;
 
-flat in uint v_block_id;
+flat in vec2 v_tile;
+flat in uint v_flags;
 
 out vec4 color;
 
 uniform sampler2D tex;
 uniform float u_light;
 uniform vec4 base_color;
-uniform float time;
+uniform vec2 atlas_size; // in tiles
+uniform vec3 light_dir; // towards the sun
+uniform vec3 camera_pos;
+
+// far away terrain fades into the sky's horizon, see SkyColors
+uniform vec3 fog_color;
+uniform float fog_start;
+uniform float fog_end;
+
+const int MAX_CASCADES = 4; // has to match MAX_CASCADES in shadow.rs
+
+uniform sampler2DArrayShadow shadow_maps; // a layer per cascade
+uniform mat4 shadow_matrices[MAX_CASCADES];
+uniform float cascade_ends[MAX_CASCADES]; // view depth where each cascade stops
+uniform float cascade_texels[MAX_CASCADES]; // how big a shadow map texel is in blocks
+uniform int cascade_count; // 0 without shadows
 
 const vec3 specular_color = vec3(1.0, 1.0, 1.0);
+const vec3 block_light_color = vec3(1.0, 0.85, 0.6);
+const float min_light = 0.04; // so caves aren't pitch black
+
+// how much of the sun reaches here, 0-1. 3x3 PCF on top of the hardware's 2x2
+float sunlight(vec3 normal) {
+    int cascade = 0;
+    while (cascade < cascade_count && v_depth > cascade_ends[cascade]) cascade++;
+    if (cascade == cascade_count) return 1.0;
+
+    // pushed out along the normal so faces don't shadow themselves (shadow acne)
+    vec3 pos = v_pos + normal * cascade_texels[cascade] * 1.5;
+    vec3 coords = (shadow_matrices[cascade] * vec4(pos, 1.0)).xyz * 0.5 + 0.5;
+    if (coords.z > 1.0) return 1.0;
+
+    vec2 texel = 1.0 / vec2(textureSize(shadow_maps, 0).xy);
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            lit += texture(shadow_maps, vec4(coords.xy + vec2(x, y) * texel, float(cascade), coords.z));
+        }
+    }
+    return lit / 9.0;
+}
 
 void main() {
-    vec3 light_dir = normalize((rotate_x(time / secs_in_day * -pi * 2.0) * vec4(0.2, -1.0, 0.0, 0.0)).xyz);
-    float diffuse = max(dot(normalize(v_normal), light_dir), 0.0);
+    vec3 normal = normalize(v_normal);
+    float diffuse = max(dot(normal, light_dir), 0.0);
+    float sun = diffuse > 0.0 ? sunlight(normal) : 0.0;
+    diffuse *= sun;
 
     vec3 camera_dir = normalize(-v_pos);
     vec3 half_direction = normalize(light_dir + camera_dir);
-    vec3 diffuse_color = texture(tex, v_uv).xyz;
+    // wrapped into the tile, the gradients come from the unwrapped uv so the mip level doesn't
+    // jump at the tile edges of merged faces
+    vec2 uv = v_tile + fract(v_uv) / atlas_size;
+    vec4 texel = textureGrad(tex, uv, dFdx(v_uv) / atlas_size, dFdy(v_uv) / atlas_size);
+    // cut out, for plants and fences with see-through textures
+    if (texel.a < 0.5) discard;
+    vec3 diffuse_color = texel.xyz;
 
     vec3 ambient_color = diffuse_color * 0.3;
 
     vec3 final_color = ambient_color + diffuse * diffuse_color * v_ao * u_light / 800.0;
 
-    // check if block is water or something and only then apply specular reflection;
-    if (v_block_id == 6u) {
-        float specular = pow(max(dot(half_direction, normalize(v_normal)), 0.0), 16.0);
-        final_color += specular * specular_color * u_light / 2500.0; // idk idc
+    // light levels fall off quicker than linearly, like they do in the real world
+    float sky_light = max(pow(v_light.x, 2.0), min_light);
+    float block_light = pow(v_light.y, 2.0);
+    final_color = final_color * sky_light + diffuse_color * block_light_color * block_light * v_ao;
+
+    // emissive blocks stay lit regardless of the sun
+    float emission = float((v_flags >> 8) & 15u) / 15.0;
+    final_color = mix(final_color, diffuse_color, emission);
+
+    // only transparent blocks (water or something) get specular reflection
+    if ((v_flags & 1u) != 0u) {
+        float specular = sun * pow(max(dot(half_direction, normal), 0.0), 16.0);
+        final_color += specular * specular_color * sky_light * u_light / 2500.0; // idk idc
 
         color = vec4(final_color, 0.6) * base_color;
     } else {
         color = vec4(final_color, 1.0) * base_color;
     }
+
+    float fog = smoothstep(fog_start, fog_end, distance(v_pos, camera_pos));
+    color.rgb = mix(color.rgb, fog_color, fog);
 }
\ No newline at end of file
@@ -1,54 +1,41 @@
 #version 330 core
 
 #include common.glsl
+#include voxel.glsl
 
-layout(location = 0) in uint vertex_data;
+layout(location = 0) in uint position; // x, y and z in sixteenths of a block, 10 bits each
+layout(location = 1) in uint vertex_data; // see VoxelVertex
 
 out vec3 v_pos;
 out vec3 v_normal;
 out vec2 v_uv;
+flat out vec2 v_tile;
 out float v_ao;
+out vec2 v_light; // sky, block, 0-1
+out float v_depth; // along the camera's view, for picking a shadow cascade
 
-flat out uint v_block_id;
+flat out uint v_flags;
 
 uniform mat4 projection;
 uniform mat4 view;
-uniform mat4 model;
-
-vec2 get_uv(int normal, int block_id) {
-    float face_idx = 1.0;
-    if (normal == 3) face_idx = 0.0;
-    else if (normal == 2) face_idx = 2.0;
-
-    vec2 pos = vec2(face_idx / ATLAS_SIZE_X, 1.0 - (float(block_id) / ATLAS_SIZE_Y));
-
-    vec2 base[4] = vec2[4](
-        vec2(pos.x, pos.y + 1.0 / ATLAS_SIZE_Y),
-        vec2(pos.x, pos.y),
-        vec2(pos.x + 1.0 / ATLAS_SIZE_X, pos.y),
-        vec2(pos.x + 1.0 / ATLAS_SIZE_X, pos.y + 1.0 / ATLAS_SIZE_Y));
-
-    if (normal == 5 || normal == 4) return base[(gl_VertexID + 2) % 4];
-    else if (normal == 0) return base[(gl_VertexID + 1) % 4];
-    // my formatter breaks if i dont put the curly braces {}
-    else {
-        return base[(gl_VertexID + 3) % 4];
-    }
-}
 
 void main() {
-    vec3 pos = vec3(vertex_data & 63u, (vertex_data >> 6) & 63u, (vertex_data >> 12) & 63u);
-    uint normal = (vertex_data >> 18) & 7u;
-    uint ao = (vertex_data >> 21) & 3u;
-    uint block_id = (vertex_data >> 23) & 63u;
-
-    vec3 n = normals[int(normal)];
-
-    gl_Position = projection * view * model * vec4(pos, 1.0);
-
-    v_pos = (model * vec4(pos, 1.0)).xyz;
-    v_normal = normalize(transpose(inverse(mat3(model))) * n);
-    v_block_id = block_id;
+    vec3 pos = unpack_pos(position);
+    uint normal = vertex_data & 7u;
+    uint ao = (vertex_data >> 3) & 3u;
+    uint block_id = (vertex_data >> 5) & 63u;
+    v_uv = get_uv(int(normal), pos);
+    v_tile = get_tile(int(normal), int(block_id));
+    pos.y -= fluid_drop(vertex_data);
+
+    pos += chunk_origin();
+
+    gl_Position = projection * view * vec4(pos, 1.0);
+
+    v_pos = pos;
+    v_normal = normals[int(normal)];
+    v_depth = -(view * vec4(pos, 1.0)).z;
+    v_flags = block_data[block_id].z;
     v_ao = ao_values[ao];
-    v_uv = get_uv(int(normal), int(block_id));
+    v_light = vec2(float((vertex_data >> 14) & 15u), float((vertex_data >> 18) & 15u)) / 15.0;
 }
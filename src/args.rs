@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use crate::{ecs::*, preview::preview_size};
+
+const USAGE: &str = "usage: ferriscraft [--seed <number>] [--preset <name>] [--world <dir>]
+       ferriscraft --export-preview <dir> [--seed <number>] [--preset <name>]
+                   [--area <min x>,<min z>,<max x>,<max z>] [--scale <blocks per pixel>]";
+
+/// command line flags, every one of them is optional
+#[derive(Resource, Default, Debug)]
+pub struct LaunchArgs {
+    pub seed: Option<u32>,
+    pub preset: Option<String>,
+    pub world: Option<PathBuf>,
+    // headless mode, writes top-down maps of the terrain instead of opening a window
+    pub export_preview: Option<PathBuf>,
+    pub area: Option<(IVec2, IVec2)>, // inclusive
+    pub scale: Option<u32>,
+}
+
+impl LaunchArgs {
+    pub fn parse() -> Result<Self, String> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self::default();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("{arg} needs a value\n{USAGE}"))
+            };
+            match arg.as_str() {
+                "--seed" => {
+                    let seed = value()?;
+                    parsed.seed =
+                        Some(seed.parse().map_err(|_| {
+                            format!("invalid seed {seed}, has to be 0-{}", u32::MAX)
+                        })?);
+                }
+                "--preset" => parsed.preset = Some(value()?),
+                "--world" => parsed.world = Some(value()?.into()),
+                "--export-preview" => parsed.export_preview = Some(value()?.into()),
+                "--area" => {
+                    let area = value()?;
+                    let bounds = area
+                        .split(',')
+                        .map(|n| n.trim().parse::<i32>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .ok()
+                        .filter(|bounds| bounds.len() == 4)
+                        .ok_or_else(|| format!("invalid area {area}, expected x0,z0,x1,z1"))?;
+                    let (a, b) = (ivec2(bounds[0], bounds[1]), ivec2(bounds[2], bounds[3]));
+                    parsed.area = Some((a.min(b), a.max(b)));
+                }
+                "--scale" => {
+                    let scale = value()?;
+                    parsed.scale = Some(
+                        scale
+                            .parse()
+                            .ok()
+                            .filter(|&scale| scale > 0)
+                            .ok_or_else(|| format!("invalid scale {scale}"))?,
+                    );
+                }
+                "--help" | "-h" => return Err(USAGE.to_string()),
+                _ => return Err(format!("unknown argument {arg}\n{USAGE}")),
+            }
+        }
+
+        if let Some((min, max)) = parsed.area {
+            preview_size(min, max, parsed.scale.unwrap_or(1))?;
+        }
+
+        Ok(parsed)
+    }
+}
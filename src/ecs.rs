@@ -9,7 +9,15 @@ pub use glam::*;
 use gl::types::*;
 use glfw::{Glfw, Key, MouseButton, PWindow};
 
-use crate::{render::material::Material, world::mesher::ChunkMesh};
+use crate::{
+    render::{
+        arena::{ArenaSlice, ChunkArena},
+        material::Material,
+        mesh::Mesh,
+        primitives::{Cuboid, PrimitiveVertex},
+    },
+    world::mesher::ChunkMesh,
+};
 
 pub struct NSWindow {
     pub window: PWindow,
@@ -107,16 +115,67 @@ impl KeyboardInput {
     }
 }
 
+/// Chunk meshes on their way to the GPU. Uploads and frees are queued here and applied by
+/// `render::upload_meshes` before the next frame is drawn, the meshes themselves only stay
+/// around on the CPU when `keep_cpu_copies` is set.
 #[derive(Resource, Default)]
-pub struct Meshes(pub HashMap<usize, ChunkMesh>, pub usize);
+pub struct Meshes {
+    pub cpu: HashMap<usize, ChunkMesh>,
+    pub keep_cpu_copies: bool,
+    pending: Vec<(Mesh3d, Option<ChunkMesh>)>,
+    next_id: usize,
+}
 
 impl Meshes {
     pub fn add(&mut self, mesh: ChunkMesh) -> Mesh3d {
-        self.0.insert(self.1, mesh);
-        let mesh_id = Mesh3d(self.1);
-        self.1 += 1;
+        let mesh_id = Mesh3d(self.next_id);
+        self.next_id += 1;
+        self.pending.push((mesh_id, Some(mesh)));
         mesh_id
     }
+
+    /// for remeshed chunks, they keep their id
+    pub fn replace(&mut self, mesh_id: Mesh3d, mesh: ChunkMesh) {
+        self.pending.push((mesh_id, Some(mesh)));
+    }
+
+    pub fn remove(&mut self, mesh_id: Mesh3d) {
+        self.cpu.remove(&mesh_id.0);
+        // an upload still queued for it would otherwise land after the free and never go away
+        self.pending
+            .retain(|(pending_id, _)| *pending_id != mesh_id);
+        self.pending.push((mesh_id, None));
+    }
+
+    /// everything queued since the last call, in order, None for meshes to free
+    pub fn take_pending(&mut self) -> Vec<(Mesh3d, Option<ChunkMesh>)> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// The GPU side of [`Meshes`] by id, plus the unit cuboid the block highlight and projectiles
+/// are drawn with.
+pub struct GpuMeshes {
+    pub chunks: HashMap<usize, GpuChunkMesh>,
+    pub arena: ChunkArena,
+    pub cuboid: Mesh<PrimitiveVertex>,
+}
+
+impl GpuMeshes {
+    pub fn new() -> Result<Self, String> {
+        let vertices = Cuboid::new(Vec3::ONE, Vec3::ZERO);
+        Ok(Self {
+            chunks: HashMap::new(),
+            arena: ChunkArena::new(),
+            cuboid: Mesh::new(&vertices, &Cuboid::generate_indices(vertices.len()))?,
+        })
+    }
+}
+
+/// where a chunk's parts are in the [`ChunkArena`], None for parts without any faces
+pub struct GpuChunkMesh {
+    pub opaque: Option<ArenaSlice>,
+    pub translucent: Option<ArenaSlice>,
 }
 
 #[derive(Default)]
@@ -5,7 +5,10 @@ use bevy_tasks::{AsyncComputeTaskPool, TaskPool};
 use glam::*;
 use glfw::Context;
 
-use crate::{ecs::*, utils::SECS_IN_DAY, window::WindowEventECS};
+use crate::{
+    args::LaunchArgs, ecs::*, render::shadow::ShadowSettings, utils::SECS_IN_DAY,
+    window::WindowEventECS,
+};
 
 const CHUNK_SIZE: i32 = 32;
 const SEA_LEVEL: i32 = 64;
@@ -13,8 +16,10 @@ const SEA_LEVEL: i32 = 64;
 pub mod ecs;
 pub mod world;
 
+mod args;
 mod particles;
 mod player;
+mod preview;
 mod render;
 // mod scripting;
 mod ui;
@@ -57,6 +62,18 @@ impl App {
 }
 
 fn main() {
+    let args = LaunchArgs::parse().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2);
+    });
+    if let Some(dir) = &args.export_preview {
+        if let Err(e) = preview::export_preview(dir, &args) {
+            eprintln!("failed to export preview: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let mut app = App {
         world: World::new(),
         last_update: Instant::now(),
@@ -109,6 +126,7 @@ fn main() {
     app.world.init_resource::<DebugInfo>();
 
     app.world.init_resource::<GameSettings>();
+    app.world.insert_resource(args);
 
     app.world.insert_resource(Time::<UpdateTime> {
         extra: UpdateTime {
@@ -199,4 +217,7 @@ fn main() {
 pub struct GameSettings {
     pub wireframe: bool,
     pub time: f32, // seconds from 0.0 - SECS_IN_DAY
+    pub shadows: ShadowSettings,
+    // the cubemap in assets/skybox over the procedural sky
+    pub skybox_overlay: bool,
 }
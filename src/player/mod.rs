@@ -1,21 +1,34 @@
 use glfw::MouseButton;
 
 use crate::{
-    App, CHUNK_SIZE, SEA_LEVEL,
+    App, SEA_LEVEL,
     ecs::*,
     utils::set_cursor_grab,
     world::{
-        ChunkMarker, NoiseFunctions, WorldData,
+        NoiseFunctions, WorldData,
+        biome::terrain_noise,
+        block::Block,
+        events::{BlockChangeCause, BlockChanged},
         interaction::{place_block, ray_cast},
-        mesher::{Block, terrain_noise},
+        settings::WorldGenSettings,
+        ticks::BlockUpdates,
     },
 };
 
 pub mod movement;
+pub mod tools;
 
 pub fn player_plugin(app: &mut App) {
-    app.add_systems(Startup, setup)
-        .add_systems(Update, (movement::handle_movement, handle_interactions))
+    app.init_resource::<tools::Selection>()
+        .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (
+                movement::handle_movement,
+                handle_interactions,
+                tools::handle_build_tools.after(handle_interactions),
+            ),
+        )
         .add_systems(FixedUpdate, update_projectiles);
 }
 
@@ -41,8 +54,10 @@ fn handle_interactions(
     mut commands: Commands,
     player: Single<(&Transform, &Velocity), With<Camera3d>>,
     mouse: Res<MouseInput>,
-    chunks: Query<(Entity, &Transform), With<ChunkMarker>>,
     mut world_data: ResMut<WorldData>,
+    settings: Res<WorldGenSettings>,
+    mut updates: ResMut<BlockUpdates>,
+    mut block_changed: EventWriter<BlockChanged>,
 ) {
     let (transform, velocity) = player.into_inner();
     if let Some(hit) = ray_cast(
@@ -51,48 +66,30 @@ fn handle_interactions(
         transform.rotation * Vec3::NEG_Z, // == player.forward()
         5.0,
     ) {
-        world_data.highlighted_block = Some(hit.global_position);
-        let mut local_pos = hit.local_pos;
-        let mut chunk_pos = hit.chunk_pos;
-        if mouse.just_pressed(MouseButton::Left)
-            && let Some(chunk) = world_data.chunks.write().unwrap().get_mut(&chunk_pos)
-        {
-            place_block(
-                chunk,
-                local_pos,
-                Block::Air,
-                Some((&mut commands, chunks.iter().collect())),
-            );
-        } else if mouse.just_pressed(MouseButton::Right) {
-            local_pos += hit.normal.as_ivec3();
-
-            if local_pos.x < 0 {
-                local_pos.x += CHUNK_SIZE;
-                chunk_pos.x -= 1;
-            } else if local_pos.x >= CHUNK_SIZE {
-                local_pos.x -= CHUNK_SIZE;
-                chunk_pos.x += 1;
-            }
-            if local_pos.y < 0 {
-                local_pos.y += CHUNK_SIZE;
-                chunk_pos.y -= 1;
-            } else if local_pos.y >= CHUNK_SIZE {
-                local_pos.y -= CHUNK_SIZE;
-                chunk_pos.y += 1;
-            }
-            if local_pos.z < 0 {
-                local_pos.z += CHUNK_SIZE;
-                chunk_pos.z -= 1;
-            } else if local_pos.z >= CHUNK_SIZE {
-                local_pos.z -= CHUNK_SIZE;
-                chunk_pos.z += 1;
+        world_data.highlighted_block = Some(hit.pos);
+        if mouse.just_pressed(MouseButton::Left) {
+            // the bedrock floor is all that keeps the player from falling out of the world
+            if settings.min_y != Some(hit.pos.y) {
+                place_block(
+                    &mut world_data.chunks.write().unwrap(),
+                    &mut updates,
+                    &mut block_changed,
+                    hit.pos,
+                    Block::AIR,
+                    BlockChangeCause::Player,
+                );
             }
-            if let Some(chunk) = world_data.chunks.write().unwrap().get_mut(&chunk_pos) {
+        } else if mouse.just_pressed(MouseButton::Right) {
+            let pos = hit.pos.offset(hit.normal.as_ivec3());
+            // nothing can be built past the build limit either
+            if settings.contains_y(pos.y) {
                 place_block(
-                    chunk,
-                    local_pos,
-                    Block::Stone,
-                    Some((&mut commands, chunks.iter().collect())),
+                    &mut world_data.chunks.write().unwrap(),
+                    &mut updates,
+                    &mut block_changed,
+                    pos,
+                    Block::STONE,
+                    BlockChangeCause::Player,
                 );
             }
         }
@@ -0,0 +1,142 @@
+use std::path::Path;
+
+use glfw::Key;
+
+use crate::{
+    ecs::*,
+    world::{
+        WorldData,
+        block::Block,
+        coords::BlockPos,
+        editor::{MAX_REGION_VOLUME, Region, WorldEditor},
+        schematic::{BlockMapping, SCHEMATICS_DIR, Schematic},
+    },
+};
+
+/// The two corners of the box the build tools work on and how the clipboard is turned when
+/// it's pasted.
+#[derive(Resource, Default)]
+pub struct Selection {
+    pub corners: [Option<BlockPos>; 2],
+    pub paste_turns: u32,
+}
+
+impl Selection {
+    fn region(&self) -> Option<Region> {
+        limit_size(Region::Box(self.corners[0]?, self.corners[1]?))
+    }
+}
+
+// every block of a region is visited on the main thread, so huge selections would hang the game
+fn limit_size(region: Region) -> Option<Region> {
+    let volume = region.volume();
+    if volume > MAX_REGION_VOLUME {
+        eprintln!("the selection is too big ({volume} blocks, at most {MAX_REGION_VOLUME})");
+        return None;
+    }
+    Some(region)
+}
+
+pub fn handle_build_tools(
+    keyboard: Res<KeyboardInput>,
+    window: Res<Window>,
+    world_data: Res<WorldData>,
+    mapping: Res<BlockMapping>,
+    mut selection: ResMut<Selection>,
+    mut editor: WorldEditor,
+) {
+    if !window.cursor_grab {
+        return;
+    }
+    let target = world_data.highlighted_block;
+
+    let control = keyboard.pressed(Key::LeftControl) || keyboard.pressed(Key::RightControl);
+    for key in keyboard.just_pressed.iter() {
+        let changed = match key {
+            Key::Z if control => Some(editor.undo()),
+            Key::Y if control => Some(editor.redo()),
+            Key::Num1 | Key::Num2 => {
+                selection.corners[(*key == Key::Num2) as usize] = target;
+                continue;
+            }
+            Key::T => {
+                selection.paste_turns = (selection.paste_turns + 1) % 4;
+                continue;
+            }
+            Key::F => selection
+                .region()
+                .map(|region| editor.fill(region, Block::STONE)),
+            Key::X => selection
+                .region()
+                .map(|region| editor.fill(region, Block::AIR)),
+            // floods the air of the selection, mostly for testing water
+            Key::R => selection
+                .region()
+                .map(|region| editor.replace(region, Block::AIR, Block::WATER)),
+            // a sphere around the first corner reaching to the second one
+            Key::O => selection.corners[0]
+                .zip(selection.corners[1])
+                .and_then(|(center, edge)| {
+                    let radius = (*edge - *center).as_vec3().length().round() as i32;
+                    limit_size(Region::Sphere(center, radius))
+                })
+                .map(|region| editor.fill(region, Block::STONE)),
+            Key::K => selection.region().map(|region| editor.copy(region)),
+            Key::V => {
+                target.map(|pos| editor.paste(pos.offset(IVec3::Y), selection.paste_turns, true))
+            }
+            Key::F5 => {
+                if let Some(schematic) = editor.clipboard() {
+                    save_schematic(schematic, &mapping);
+                }
+                continue;
+            }
+            Key::F9 => {
+                match load_newest_schematic(&mapping) {
+                    Ok(schematic) => *editor.clipboard() = Some(schematic),
+                    Err(e) => eprintln!("{e}"),
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        if let Some(changed) = changed {
+            println!("{key:?}: {changed} blocks");
+        }
+    }
+}
+
+// both formats, .schem for minecraft tools and .vox for magicavoxel
+fn save_schematic(schematic: &Schematic, mapping: &BlockMapping) {
+    let name = format!(
+        "schematic-{}",
+        chrono::Local::now().format("%Y-%m-%d-%H-%M-%S")
+    );
+    for extension in ["schem", "vox"] {
+        let path = Path::new(SCHEMATICS_DIR)
+            .join(&name)
+            .with_extension(extension);
+        match schematic.save(&path, mapping) {
+            Ok(()) => println!("saved {}", path.display()),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+}
+
+// whatever was saved or dropped into the schematics directory last
+fn load_newest_schematic(mapping: &BlockMapping) -> Result<Schematic, String> {
+    let newest = std::fs::read_dir(SCHEMATICS_DIR)
+        .map_err(|_| format!("could not read {SCHEMATICS_DIR}"))?
+        .flatten()
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .is_some_and(|extension| extension == "schem" || extension == "vox")
+        })
+        .max_by_key(|entry| entry.metadata().and_then(|meta| meta.modified()).ok())
+        .ok_or_else(|| format!("no .schem or .vox files in {SCHEMATICS_DIR}"))?;
+    let schematic = Schematic::load(&newest.path(), mapping)?;
+    println!("loaded {}", newest.path().display());
+    Ok(schematic)
+}
@@ -0,0 +1,341 @@
+use std::{collections::HashMap, path::Path};
+
+use glam::*;
+use image::{Rgb, RgbImage};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    SEA_LEVEL,
+    args::LaunchArgs,
+    utils::generate_block_at,
+    world::{
+        NoiseFunctions,
+        biome::{Biome, terrain_noise},
+        block::{BLOCKS_PATH, Block, BlockRegistry},
+        caves::{OVERHANG_DEPTH, carve_block, density_at, ravine_depth},
+        mesher::Direction,
+        settings::{DEFAULT_PRESET, WorldGenSettings},
+        structures::{MAX_STRUCTURE_RADIUS, PlacedStructure, STRUCTURE_CELL_SIZE},
+    },
+};
+
+const ATLAS_PATH: &str = "assets/atlas.png";
+const DEFAULT_AREA: (IVec2, IVec2) = (IVec2::splat(-256), IVec2::splat(255));
+/// the biggest image `--export-preview` writes, in pixels on each side
+pub const MAX_PREVIEW_SIZE: u32 = 4096;
+// how far below the surface columns are scanned when the world has no floor
+const UNBOUNDED_SCAN_DEPTH: i32 = 128;
+
+/// what the terrain looks like from straight above
+#[derive(Clone, Copy, Debug)]
+pub struct ColumnSample {
+    pub height: i32, // the heightmap surface, before caves and structures
+    pub biome: Biome,
+    pub top_block: Block,
+}
+
+pub struct TerrainPreview {
+    pub size: UVec2,
+    pub columns: Vec<ColumnSample>, // row major, z is the row
+    // what black and white stand for in the height map
+    pub height_range: (i32, i32),
+}
+
+impl TerrainPreview {
+    /// samples every `scale`th column of the inclusive `min..=max` rectangle, see
+    /// [`preview_size`] for how big it can be
+    pub fn generate(min: IVec2, max: IVec2, scale: u32, noises: &NoiseFunctions) -> Self {
+        let size = preview_size(min, max, scale).expect("preview area is too big");
+
+        // the highest structure block of every sampled column, only the cells that can reach
+        // one are rolled
+        let mut structure_tops = HashMap::<IVec2, (i32, Block)>::new();
+        if noises.settings.structures {
+            let cells_x = sampled_cells(min.x, size.x, scale);
+            let cells_z = sampled_cells(min.y, size.y, scale);
+            let blocks = (0..cells_x.len() * cells_z.len())
+                .into_par_iter()
+                .filter_map(|i| {
+                    let cell = ivec2(cells_x[i % cells_x.len()], cells_z[i / cells_x.len()]);
+                    PlacedStructure::in_cell(cell, noises)
+                })
+                .flat_map_iter(|structure| {
+                    structure.blocks().into_iter().filter(|(pos, _)| {
+                        let offset = pos.xz().as_i64vec2() - min.as_i64vec2();
+                        offset.cmpge(I64Vec2::ZERO).all()
+                            && offset.cmplt(size.as_i64vec2() * scale as i64).all()
+                            && offset % scale as i64 == I64Vec2::ZERO
+                    })
+                })
+                .collect::<Vec<_>>();
+            for (pos, block) in blocks {
+                let top = structure_tops.entry(pos.xz()).or_insert((pos.y, block));
+                if pos.y > top.0 {
+                    *top = (pos.y, block);
+                }
+            }
+        }
+
+        let columns = (0..size.x * size.y)
+            .into_par_iter()
+            .map(|i| {
+                let offset = uvec2(i % size.x, i / size.x).as_i64vec2() * scale as i64;
+                let column = (min.as_i64vec2() + offset).as_ivec2();
+                let mut sample = sample_column(column, noises);
+                if let Some(&(y, block)) = structure_tops.get(&column)
+                    && (y >= sample.height || sample.top_block.is_air())
+                {
+                    sample.top_block = block;
+                }
+                sample
+            })
+            .collect::<Vec<_>>();
+
+        // the world's bounds, or what's there on the sides it has none
+        let (lowest, highest) = columns
+            .iter()
+            .fold((i32::MAX, i32::MIN), |(low, high), sample| {
+                (low.min(sample.height), high.max(sample.height))
+            });
+        let settings = &noises.settings;
+        let height_range = (
+            settings.min_y.unwrap_or(lowest),
+            settings.max_y.map_or(highest, |max_y| max_y - 1),
+        );
+
+        Self {
+            size,
+            columns,
+            height_range,
+        }
+    }
+
+    fn image(&self, color: impl Fn(&ColumnSample) -> [u8; 3]) -> RgbImage {
+        RgbImage::from_fn(self.size.x, self.size.y, |x, y| {
+            Rgb(color(&self.columns[(y * self.size.x + x) as usize]))
+        })
+    }
+
+    pub fn height_map(&self) -> RgbImage {
+        let (low, high) = self.height_range;
+        let range = (high as i64 - low as i64).max(1);
+        self.image(|sample| {
+            let height = (sample.height as i64 - low as i64).clamp(0, range);
+            [(height * 255 / range) as u8; 3]
+        })
+    }
+
+    pub fn biome_map(&self) -> RgbImage {
+        self.image(|sample| sample.biome.info().map_color)
+    }
+
+    /// needs the block registry for the block colors
+    pub fn top_block_map(&self, registry: &BlockRegistry) -> Result<RgbImage, String> {
+        let colors = block_colors(registry)?;
+        Ok(self.image(|sample| colors[sample.top_block.0 as usize]))
+    }
+}
+
+/// How many pixels a preview of the inclusive `min..=max` rectangle is on each side, errors if
+/// the corners are the wrong way around, too close to the edge of the world for the structures
+/// around them or it would be bigger than [`MAX_PREVIEW_SIZE`].
+pub fn preview_size(min: IVec2, max: IVec2, scale: u32) -> Result<UVec2, String> {
+    if min.cmpgt(max).any() {
+        return Err(format!(
+            "the preview area {min}..={max} is the wrong way around"
+        ));
+    }
+    let limit = i32::MAX - MAX_STRUCTURE_RADIUS;
+    if min.cmplt(IVec2::splat(-limit)).any() || max.cmpgt(IVec2::splat(limit)).any() {
+        return Err(format!(
+            "the preview area {min}..={max} has to stay within -{limit}..={limit}"
+        ));
+    }
+    let size = (max.as_i64vec2() - min.as_i64vec2()) / scale.max(1) as i64 + 1;
+    if size.max_element() > MAX_PREVIEW_SIZE as i64 {
+        return Err(format!(
+            "a preview of {min}..={max} would be {}x{} pixels, at most {MAX_PREVIEW_SIZE} on \
+             each side, use a smaller area or a bigger --scale",
+            size.x, size.y
+        ));
+    }
+    Ok(size.as_uvec2())
+}
+
+// the structure cells along one axis that can reach any of `count` columns `scale` apart
+fn sampled_cells(min: i32, count: u32, scale: u32) -> Vec<i32> {
+    let mut cells = Vec::<i32>::new();
+    for i in 0..count as i64 {
+        let column = (min as i64 + i * scale as i64) as i32;
+        let first = (column - MAX_STRUCTURE_RADIUS).div_euclid(STRUCTURE_CELL_SIZE);
+        let last = (column + MAX_STRUCTURE_RADIUS).div_euclid(STRUCTURE_CELL_SIZE);
+        // columns only go up, so the cells do too
+        let first = cells.last().map_or(first, |&cell| first.max(cell + 1));
+        cells.extend(first..=last);
+    }
+    cells
+}
+
+/// the heightmap surface and the first visible block of a column, same as chunk generation
+/// without the chunk
+pub fn sample_column(column: IVec2, noises: &NoiseFunctions) -> ColumnSample {
+    let (max_y, biome) = terrain_noise(column.as_vec2(), noises);
+    let ravine = ravine_depth(column.as_vec2(), max_y, noises);
+
+    let floor = noises
+        .settings
+        .min_y
+        .unwrap_or(max_y.min(SEA_LEVEL) - UNBOUNDED_SCAN_DEPTH);
+
+    let mut top_block = Block::AIR;
+    for y in (floor..max_y.max(SEA_LEVEL) + OVERHANG_DEPTH).rev() {
+        let pos = ivec3(column.x, y, column.y);
+        let block = if noises.settings.caves {
+            carve_block(pos, max_y, biome, ravine, || density_at(pos, noises))
+        } else {
+            generate_block_at(pos, max_y, biome)
+        };
+        let block = noises.settings.clip_to_bounds(y, block);
+        if !block.is_air() {
+            top_block = block;
+            break;
+        }
+    }
+
+    ColumnSample {
+        height: max_y,
+        biome,
+        top_block,
+    }
+}
+
+/// the average color of every block's top texture
+fn block_colors(registry: &BlockRegistry) -> Result<Vec<[u8; 3]>, String> {
+    let atlas = image::open(ATLAS_PATH)
+        .map_err(|_| format!("could not load {ATLAS_PATH}"))?
+        .into_rgba8();
+    let tile_size = uvec2(atlas.width(), atlas.height()) / registry.atlas_size;
+
+    Ok(registry
+        .blocks
+        .iter()
+        .map(|info| {
+            let tile = info.textures[Direction::Top as usize];
+            let origin =
+                uvec2(tile % registry.atlas_size.x, tile / registry.atlas_size.x) * tile_size;
+
+            let (mut sum, mut count) = (UVec3::ZERO, 0);
+            for y in 0..tile_size.y {
+                for x in 0..tile_size.x {
+                    let [r, g, b, a] = atlas.get_pixel(origin.x + x, origin.y + y).0;
+                    if a > 0 {
+                        sum += uvec3(r as u32, g as u32, b as u32);
+                        count += 1;
+                    }
+                }
+            }
+            let average = sum / count.max(1);
+            [average.x as u8, average.y as u8, average.z as u8]
+        })
+        .collect())
+}
+
+/// `--export-preview`, writes height.png, biome.png and top_block.png into `dir`
+pub fn export_preview(dir: &Path, args: &LaunchArgs) -> Result<(), String> {
+    let registry = BlockRegistry::init(BLOCKS_PATH)?;
+
+    let mut settings =
+        WorldGenSettings::load_preset(args.preset.as_deref().unwrap_or(DEFAULT_PRESET))?;
+    if let Some(seed) = args.seed {
+        settings.seed = seed;
+    }
+    let noises = settings.build_noises();
+    let (min, max) = args.area.unwrap_or(DEFAULT_AREA);
+    let scale = args.scale.unwrap_or(1);
+
+    let preview = TerrainPreview::generate(min, max, scale, &noises);
+
+    std::fs::create_dir_all(dir).map_err(|_| format!("could not create {}", dir.display()))?;
+    for (name, image) in [
+        ("height.png", preview.height_map()),
+        ("biome.png", preview.biome_map()),
+        ("top_block.png", preview.top_block_map(registry)?),
+    ] {
+        let path = dir.join(name);
+        image
+            .save(&path)
+            .map_err(|e| format!("could not write {}: {e}", path.display()))?;
+    }
+
+    println!(
+        "exported {}x{} preview of {min}..={max} (seed {}, {} preset) to {}",
+        preview.size.x,
+        preview.size.y,
+        settings.seed,
+        settings.preset,
+        dir.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 4x4 preview of seed 42, anything that changes worldgen changes this too
+    #[test]
+    fn small_preview_snapshot() {
+        BlockRegistry::init(BLOCKS_PATH).unwrap();
+        let mut settings = WorldGenSettings::load_preset(DEFAULT_PRESET).unwrap();
+        settings.seed = 42;
+        let noises = settings.build_noises();
+        let preview =
+            TerrainPreview::generate(IVec2::splat(-1024), IVec2::splat(1023), 512, &noises);
+
+        use Biome::*;
+        assert_eq!(preview.size, uvec2(4, 4));
+        assert_eq!(preview.height_range, (0, 143));
+        assert_eq!(
+            preview.columns.iter().map(|c| c.height).collect::<Vec<_>>(),
+            [
+                76, 80, 79, 72, 105, 72, 74, 75, 143, 74, 77, 73, 74, 73, 76, 75
+            ]
+        );
+        assert_eq!(
+            preview.columns.iter().map(|c| c.biome).collect::<Vec<_>>(),
+            [
+                Plains, Forest, Plains, Tundra, Plains, Desert, Plains, Tundra, Mountains, Desert,
+                Plains, Tundra, Desert, Desert, Plains, Tundra
+            ]
+        );
+        assert_eq!(
+            preview
+                .columns
+                .iter()
+                .map(|c| c.top_block)
+                .collect::<Vec<_>>(),
+            [3, 3, 3, 1, 3, 7, 3, 10, 1, 7, 1, 10, 7, 7, 3, 10].map(Block)
+        );
+        // from the floor to the highest column
+        let heights = preview.height_map();
+        assert_eq!(heights.get_pixel(0, 0).0, [135; 3]);
+        assert_eq!(heights.get_pixel(0, 2).0, [255; 3]);
+    }
+
+    #[test]
+    fn preview_size_is_checked() {
+        assert_eq!(
+            preview_size(ivec2(-10, 0), ivec2(9, 4), 2),
+            Ok(uvec2(10, 3))
+        );
+        assert!(preview_size(ivec2(1, 0), ivec2(0, 0), 1).is_err());
+        assert!(preview_size(IVec2::MIN, IVec2::MAX, 1).is_err());
+        // structures around the edges would be out of range
+        assert!(preview_size(IVec2::MIN, IVec2::MAX, u32::MAX).is_err());
+        let limit = i32::MAX - MAX_STRUCTURE_RADIUS;
+        assert_eq!(
+            preview_size(IVec2::splat(-limit), IVec2::splat(limit), u32::MAX),
+            Ok(uvec2(1, 1))
+        );
+    }
+}
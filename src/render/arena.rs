@@ -0,0 +1,347 @@
+use std::{ffi::CStr, ops::Range, ptr::null};
+
+use gl::types::*;
+
+use crate::{
+    ecs::*,
+    render::{mesh::bind_attributes, primitives::Cuboid},
+    world::mesher::VoxelVertex,
+};
+
+// vertices are handed out in pages so voxel.vert can tell which chunk a vertex belongs to from
+// gl_VertexID alone, which works the same with and without indirect drawing
+pub const PAGE_VERTICES: u32 = 1024;
+const INITIAL_PAGES: u32 = 1024; // 8 MiB of vertices
+
+/// Where a chunk part's vertices are in the [`ChunkArena`].
+#[derive(Clone, Copy, Debug)]
+pub struct ArenaSlice {
+    pub first_page: u32,
+    pub vertex_count: u32,
+}
+
+impl ArenaSlice {
+    #[inline]
+    fn pages(&self) -> Range<u32> {
+        self.first_page..self.first_page + self.vertex_count.div_ceil(PAGE_VERTICES)
+    }
+}
+
+// the layout glMultiDrawElementsIndirect reads its draws in
+#[repr(C)]
+struct DrawElementsIndirectCommand {
+    count: GLuint,
+    instance_count: GLuint,
+    first_index: GLuint,
+    base_vertex: GLint,
+    base_instance: GLuint,
+}
+
+/// Every chunk's vertices in one big buffer, so all visible chunks are drawn with a single
+/// multi-draw. Chunk origins are kept per page in a buffer texture instead of a `model`
+/// uniform. Quads are always indexed the same way, so all chunks share one index buffer.
+pub struct ChunkArena {
+    vao: GLuint,
+    vertex_buffer: GLuint,
+    index_buffer: GLuint,
+    origin_buffer: GLuint,
+    origin_texture: GLuint,
+    indirect_buffer: GLuint,
+    // in pages
+    capacity: u32,
+    // sorted and never touching, touching ranges are merged
+    free: Vec<Range<u32>>,
+    // how many quads the index buffer has indices for
+    max_quads: u32,
+    multi_draw_indirect: bool,
+}
+
+impl ChunkArena {
+    pub fn new() -> Self {
+        let multi_draw_indirect = supports_multi_draw_indirect();
+        println!(
+            "drawing chunks with {}",
+            if multi_draw_indirect {
+                "glMultiDrawElementsIndirect"
+            } else {
+                "glMultiDrawElementsBaseVertex"
+            }
+        );
+
+        let mut arena = Self {
+            vao: 0,
+            vertex_buffer: 0,
+            index_buffer: 0,
+            origin_buffer: 0,
+            origin_texture: 0,
+            indirect_buffer: 0,
+            capacity: 0,
+            free: Vec::new(),
+            max_quads: 0,
+            multi_draw_indirect,
+        };
+        unsafe {
+            gl::GenVertexArrays(1, &mut arena.vao);
+            gl::GenBuffers(1, &mut arena.index_buffer);
+            gl::GenTextures(1, &mut arena.origin_texture);
+            gl::GenBuffers(1, &mut arena.indirect_buffer);
+
+            gl::BindVertexArray(arena.vao);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, arena.index_buffer);
+            gl::BindVertexArray(0);
+        }
+        // creates the vertex and origin buffers
+        arena.grow(INITIAL_PAGES);
+        arena
+    }
+
+    /// copies a chunk part's vertices in, they're drawn offset by `origin`
+    pub fn alloc(&mut self, vertices: &[VoxelVertex], origin: IVec3) -> ArenaSlice {
+        let pages = (vertices.len() as u32).div_ceil(PAGE_VERTICES);
+        let first_page = self.take_free(pages).unwrap_or_else(|| {
+            self.grow(pages);
+            self.take_free(pages).unwrap()
+        });
+
+        let origins = vec![origin.extend(0); pages as usize];
+        write_buffer(
+            self.vertex_buffer,
+            first_page as usize * PAGE_VERTICES as usize * size_of::<VoxelVertex>(),
+            vertices,
+        );
+        write_buffer(
+            self.origin_buffer,
+            first_page as usize * size_of::<IVec4>(),
+            &origins,
+        );
+        self.reserve_indices(vertices.len() as u32 / 4);
+
+        ArenaSlice {
+            first_page,
+            vertex_count: vertices.len() as u32,
+        }
+    }
+
+    pub fn free(&mut self, slice: ArenaSlice) {
+        self.add_free(slice.pages());
+    }
+
+    /// draws every slice in order with the bound material, returns how many triangles were drawn
+    pub fn draw(&self, slices: &[ArenaSlice]) -> usize {
+        if slices.is_empty() {
+            return 0;
+        }
+        let counts = slices
+            .iter()
+            .map(|slice| (slice.vertex_count / 4 * 6) as GLsizei)
+            .collect::<Vec<_>>();
+
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::ActiveTexture(gl::TEXTURE1);
+            gl::BindTexture(gl::TEXTURE_BUFFER, self.origin_texture);
+            gl::ActiveTexture(gl::TEXTURE0);
+
+            if self.multi_draw_indirect {
+                let commands = slices
+                    .iter()
+                    .zip(&counts)
+                    .map(|(slice, &count)| DrawElementsIndirectCommand {
+                        count: count as GLuint,
+                        instance_count: 1,
+                        first_index: 0,
+                        base_vertex: (slice.first_page * PAGE_VERTICES) as GLint,
+                        base_instance: 0,
+                    })
+                    .collect::<Vec<_>>();
+                gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.indirect_buffer);
+                gl::BufferData(
+                    gl::DRAW_INDIRECT_BUFFER,
+                    size_of_val(commands.as_slice()) as isize,
+                    commands.as_ptr() as *const _,
+                    gl::STREAM_DRAW,
+                );
+                gl::MultiDrawElementsIndirect(
+                    gl::TRIANGLES,
+                    gl::UNSIGNED_INT,
+                    null(),
+                    commands.len() as GLsizei,
+                    0,
+                );
+                gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+            } else {
+                let offsets = vec![null(); slices.len()];
+                let base_vertices = slices
+                    .iter()
+                    .map(|slice| (slice.first_page * PAGE_VERTICES) as GLint)
+                    .collect::<Vec<_>>();
+                gl::MultiDrawElementsBaseVertex(
+                    gl::TRIANGLES,
+                    counts.as_ptr(),
+                    gl::UNSIGNED_INT,
+                    offsets.as_ptr(),
+                    slices.len() as GLsizei,
+                    base_vertices.as_ptr(),
+                );
+            }
+
+            gl::BindVertexArray(0);
+        }
+        counts.iter().map(|&count| count as usize / 3).sum()
+    }
+
+    // first fit
+    fn take_free(&mut self, pages: u32) -> Option<u32> {
+        let i = self
+            .free
+            .iter()
+            .position(|range| range.end - range.start >= pages)?;
+        let first_page = self.free[i].start;
+        self.free[i].start += pages;
+        if self.free[i].is_empty() {
+            self.free.remove(i);
+        }
+        Some(first_page)
+    }
+
+    fn add_free(&mut self, pages: Range<u32>) {
+        let i = self.free.partition_point(|range| range.start < pages.start);
+        if i < self.free.len() && self.free[i].start == pages.end {
+            self.free[i].start = pages.start;
+        } else {
+            self.free.insert(i, pages);
+        }
+        if i > 0 && self.free[i - 1].end == self.free[i].start {
+            self.free[i - 1].end = self.free[i].end;
+            self.free.remove(i);
+        }
+    }
+
+    // at least doubles, so there's always room for `pages` more at the end
+    fn grow(&mut self, pages: u32) {
+        let old_capacity = self.capacity;
+        self.capacity = (old_capacity * 2).max(old_capacity + pages);
+
+        let vertex_page = PAGE_VERTICES as usize * size_of::<VoxelVertex>();
+        resize_buffer(
+            &mut self.vertex_buffer,
+            old_capacity as usize * vertex_page,
+            self.capacity as usize * vertex_page,
+        );
+        resize_buffer(
+            &mut self.origin_buffer,
+            old_capacity as usize * size_of::<IVec4>(),
+            self.capacity as usize * size_of::<IVec4>(),
+        );
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vertex_buffer);
+            bind_attributes::<VoxelVertex>();
+            gl::BindVertexArray(0);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+            gl::BindTexture(gl::TEXTURE_BUFFER, self.origin_texture);
+            gl::TexBuffer(gl::TEXTURE_BUFFER, gl::RGBA32I, self.origin_buffer);
+            gl::BindTexture(gl::TEXTURE_BUFFER, 0);
+        }
+        self.add_free(old_capacity..self.capacity);
+
+        if old_capacity > 0 {
+            println!(
+                "chunk arena grown to {} MiB",
+                (self.capacity as usize * vertex_page) >> 20
+            );
+        }
+    }
+
+    fn reserve_indices(&mut self, quads: u32) {
+        if quads <= self.max_quads {
+            return;
+        }
+        self.max_quads = quads.next_power_of_two();
+        let indices = Cuboid::generate_indices(self.max_quads as usize * 4);
+        unsafe {
+            gl::BindBuffer(gl::COPY_WRITE_BUFFER, self.index_buffer);
+            gl::BufferData(
+                gl::COPY_WRITE_BUFFER,
+                size_of_val(indices.as_slice()) as isize,
+                indices.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+            gl::BindBuffer(gl::COPY_WRITE_BUFFER, 0);
+        }
+    }
+}
+
+impl Drop for ChunkArena {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vertex_buffer);
+            gl::DeleteBuffers(1, &self.index_buffer);
+            gl::DeleteBuffers(1, &self.origin_buffer);
+            gl::DeleteTextures(1, &self.origin_texture);
+            gl::DeleteBuffers(1, &self.indirect_buffer);
+        }
+    }
+}
+
+// GL 4.3 or the extension, main only asks for a 3.3 core context
+fn supports_multi_draw_indirect() -> bool {
+    if !gl::MultiDrawElementsIndirect::is_loaded() {
+        return false;
+    }
+    let (mut major, mut minor, mut extensions) = (0, 0, 0);
+    unsafe {
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut extensions);
+    }
+    (major, minor) >= (4, 3)
+        || (0..extensions as GLuint).any(|i| unsafe {
+            let name = gl::GetStringi(gl::EXTENSIONS, i);
+            !name.is_null() && CStr::from_ptr(name as *const _) == c"GL_ARB_multi_draw_indirect"
+        })
+}
+
+fn write_buffer<T>(buffer: GLuint, offset: usize, data: &[T]) {
+    unsafe {
+        gl::BindBuffer(gl::COPY_WRITE_BUFFER, buffer);
+        gl::BufferSubData(
+            gl::COPY_WRITE_BUFFER,
+            offset as isize,
+            size_of_val(data) as isize,
+            data.as_ptr() as *const _,
+        );
+        gl::BindBuffer(gl::COPY_WRITE_BUFFER, 0);
+    }
+}
+
+// a bigger buffer with the old one's contents
+fn resize_buffer(buffer: &mut GLuint, old_size: usize, new_size: usize) {
+    unsafe {
+        let mut resized = 0;
+        gl::GenBuffers(1, &mut resized);
+        gl::BindBuffer(gl::COPY_WRITE_BUFFER, resized);
+        gl::BufferData(
+            gl::COPY_WRITE_BUFFER,
+            new_size as isize,
+            null(),
+            gl::DYNAMIC_DRAW,
+        );
+        if old_size > 0 {
+            gl::BindBuffer(gl::COPY_READ_BUFFER, *buffer);
+            gl::CopyBufferSubData(
+                gl::COPY_READ_BUFFER,
+                gl::COPY_WRITE_BUFFER,
+                0,
+                0,
+                old_size as isize,
+            );
+            gl::BindBuffer(gl::COPY_READ_BUFFER, 0);
+        }
+        gl::BindBuffer(gl::COPY_WRITE_BUFFER, 0);
+        gl::DeleteBuffers(1, buffer);
+        *buffer = resized;
+    }
+}
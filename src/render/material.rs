@@ -19,6 +19,9 @@ pub enum UniformValue {
     Vec3(Vec3),
     Vec4(Vec4),
     Mat4(Mat4),
+    FloatArray(Vec<GLfloat>),
+    Mat4Array(Vec<Mat4>),
+    UVec4Array(Vec<UVec4>),
 }
 
 pub struct Material {
@@ -145,6 +148,18 @@ impl Material {
                 UniformValue::Mat4(m) => {
                     gl::UniformMatrix4fv(location, 1, gl::FALSE, m.as_ref().as_ptr())
                 }
+                UniformValue::FloatArray(v) => {
+                    gl::Uniform1fv(location, v.len() as GLsizei, v.as_ptr())
+                }
+                UniformValue::Mat4Array(v) => gl::UniformMatrix4fv(
+                    location,
+                    v.len() as GLsizei,
+                    gl::FALSE,
+                    v.as_ptr() as *const _,
+                ),
+                UniformValue::UVec4Array(v) => {
+                    gl::Uniform4uiv(location, v.len() as GLsizei, v.as_ptr() as *const _)
+                }
             }
         }
     }
@@ -40,30 +40,7 @@ impl<V: Vertex> Mesh<V> {
                 gl::STATIC_DRAW,
             );
 
-            for &(location, size, type_, normalized, offset) in V::attributes() {
-                match type_ {
-                    gl::UNSIGNED_INT | gl::INT | gl::UNSIGNED_BYTE | gl::BYTE => {
-                        gl::VertexAttribIPointer(
-                            location,
-                            size,
-                            type_,
-                            size_of::<V>() as GLint,
-                            offset as *const _,
-                        );
-                    }
-                    _ => {
-                        gl::VertexAttribPointer(
-                            location,
-                            size,
-                            type_,
-                            normalized,
-                            size_of::<V>() as GLint,
-                            offset as *const _,
-                        );
-                    }
-                }
-                gl::EnableVertexAttribArray(location);
-            }
+            bind_attributes::<V>();
 
             gl::BindVertexArray(0);
         }
@@ -77,15 +54,42 @@ impl<V: Vertex> Mesh<V> {
         })
     }
 
+    /// returns how many triangles were drawn
     pub fn draw(&self) -> usize {
         unsafe {
-            let mut size = 0;
-            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
-            gl::GetBufferParameteriv(gl::ARRAY_BUFFER, gl::BUFFER_SIZE, &mut size);
             gl::BindVertexArray(self.vao);
             gl::DrawElements(gl::TRIANGLES, self.index_count, gl::UNSIGNED_INT, null());
+        }
+        self.index_count as usize / 3
+    }
+}
 
-            (size as usize / size_of::<V>()) / 3
+/// points the bound vertex array's attributes at the bound `ARRAY_BUFFER`
+pub fn bind_attributes<V: Vertex>() {
+    unsafe {
+        for &(location, size, type_, normalized, offset) in V::attributes() {
+            match type_ {
+                gl::UNSIGNED_INT | gl::INT | gl::UNSIGNED_BYTE | gl::BYTE => {
+                    gl::VertexAttribIPointer(
+                        location,
+                        size,
+                        type_,
+                        size_of::<V>() as GLint,
+                        offset as *const _,
+                    );
+                }
+                _ => {
+                    gl::VertexAttribPointer(
+                        location,
+                        size,
+                        type_,
+                        normalized,
+                        size_of::<V>() as GLint,
+                        offset as *const _,
+                    );
+                }
+            }
+            gl::EnableVertexAttribArray(location);
         }
     }
 }
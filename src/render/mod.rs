@@ -1,38 +1,66 @@
+use std::collections::HashMap;
+
 use gl::types::*;
 use glfw::Context;
 
 use crate::{
-    App, GameSettings,
+    App, CHUNK_SIZE, GameSettings,
     ecs::*,
     player::Projectile,
     render::{
         material::{Material, MaterialOptions, UniformValue},
         mesh::Mesh,
         primitives::{Cuboid, PrimitiveVertex, Quad},
+        shadow::{ShadowMaps, render_shadows},
+        sky::SkyColors,
     },
     ui::{TextVertex, UIRect, UIText},
     utils::{should_cull_aabb, should_cull_sphere},
-    world::{WorldData, mesher::Direction},
+    world::{
+        ChunkLoadSettings, WorldData,
+        block::Blocks,
+        mesher::{Direction, VoxelMesh},
+    },
 };
 
+pub mod arena;
 pub mod material;
 pub mod mesh;
 pub mod primitives;
+pub mod shadow;
+pub mod sky;
 
 pub fn render_plugin(app: &mut App) {
     let mut materials = Materials::default();
 
     // materials[0] // voxel
-    materials.add(
-        Material::new(
-            "voxel",
-            MaterialOptions {
-                base_texture: Some("assets/atlas.png"),
-                ..Default::default()
-            },
-        )
-        .unwrap(),
-    );
+    let voxel = Material::new(
+        "voxel",
+        MaterialOptions {
+            base_texture: Some("assets/atlas.png"),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    // materials[2] // shadow, chunk depth from the sun with the same atlas for cut outs
+    let mut shadow = Material::new("shadow", MaterialOptions::default()).unwrap();
+    shadow.texture = voxel.texture;
+
+    // block textures and flags never change at runtime, upload them once
+    let registry = *app.world.resource::<Blocks>();
+    for material in [&voxel, &shadow] {
+        material.bind();
+        material.set_uniform(
+            c"atlas_size",
+            UniformValue::Vec2(registry.atlas_size.as_vec2()),
+        );
+        material.set_uniform(
+            c"block_data",
+            UniformValue::UVec4Array(registry.shader_data()),
+        );
+        material.set_uniform(c"chunk_origins", UniformValue::Int(1)); // see ChunkArena::draw
+    }
+    materials.add(voxel);
 
     // materials[1] // primitive
     materials.add(
@@ -45,16 +73,26 @@ pub fn render_plugin(app: &mut App) {
         )
         .unwrap(),
     );
+    materials.add(shadow);
+
     app.init_resource::<Meshes>()
         .insert_non_send_resource(materials)
+        .insert_non_send_resource(GpuMeshes::new().unwrap())
+        .insert_non_send_resource(ShadowMaps::new())
+        .insert_non_send_resource(UIMeshes::default())
         .add_systems(Startup, setup)
         .add_systems(
             RenderUpdate,
-            calculations
-                .pipe(render_world)
-                .pipe(render_projectiles)
-                .pipe(render_skybox)
-                .pipe(render_ui),
+            (
+                upload_meshes,
+                calculations
+                    .pipe(render_shadows)
+                    .pipe(render_world)
+                    .pipe(render_projectiles)
+                    .pipe(render_skybox)
+                    .pipe(render_ui),
+            )
+                .chain(),
         )
         .add_systems(PostRenderUpdate, finish_up);
 }
@@ -145,6 +183,33 @@ fn setup(mut commands: Commands, mut materials: NonSendMut<Materials>) {
     });
 }
 
+// chunk meshes are copied into the arena once when they're built or rebuilt, not every frame
+fn upload_meshes(mut meshes: ResMut<Meshes>, mut gpu_meshes: NonSendMut<GpuMeshes>) {
+    let gpu_meshes = &mut *gpu_meshes;
+    for (mesh_id, mesh) in meshes.take_pending() {
+        if let Some(old) = gpu_meshes.chunks.remove(&mesh_id.0) {
+            for slice in [old.opaque, old.translucent].into_iter().flatten() {
+                gpu_meshes.arena.free(slice);
+            }
+        }
+        let Some(mesh) = mesh else {
+            continue;
+        };
+
+        let mut upload = |part: &VoxelMesh| {
+            (!part.vertices.is_empty()).then(|| gpu_meshes.arena.alloc(&part.vertices, mesh.origin))
+        };
+        let gpu_mesh = GpuChunkMesh {
+            opaque: upload(&mesh.opaque),
+            translucent: upload(&mesh.translucent),
+        };
+        gpu_meshes.chunks.insert(mesh_id.0, gpu_mesh);
+        if meshes.keep_cpu_copies {
+            meshes.cpu.insert(mesh_id.0, mesh);
+        }
+    }
+}
+
 fn calculations(
     query: Single<(&mut Transform, &Camera3d)>,
     window: Res<Window>,
@@ -161,50 +226,46 @@ fn calculations(
 #[allow(clippy::too_many_arguments)]
 fn render_world(
     vp: In<(Mat4, Mat4, [Vec4; 6])>,
-    meshes: Res<Meshes>,
+    gpu_meshes: NonSend<GpuMeshes>,
+    shadow_maps: NonSend<ShadowMaps>,
     materials: NonSend<Materials>,
-    mesh_entities: Query<(&Transform, &Mesh3d, &MeshMaterial, &Aabb)>,
+    mesh_entities: Query<(&Transform, &Mesh3d, &Aabb)>,
     light: Single<&DirectionalLight>,
     game_settings: Res<GameSettings>,
     time: Res<Time>,
     world_data: Res<WorldData>,
+    load_settings: Res<ChunkLoadSettings>,
     #[cfg(debug_assertions)] mut debug_info: ResMut<DebugInfo>,
 ) -> (Mat4, Mat4, [Vec4; 6]) {
     let (projection, view, frustum) = *vp;
+    let camera_pos = view.inverse().w_axis.truncate();
+    let sky = SkyColors::at(time.extra.simulated);
 
     unsafe {
         gl::Enable(gl::MULTISAMPLE);
         gl::Enable(gl::DEPTH_TEST);
         gl::Enable(gl::CULL_FACE);
         gl::CullFace(gl::BACK);
-        gl::ClearColor(0.44, 0.73, 0.88, 1.0);
+        gl::ClearColor(sky.horizon.x, sky.horizon.y, sky.horizon.z, 1.0);
         gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
         if let Some(pos) = world_data.highlighted_block {
             gl::LineWidth(3.0);
 
-            let vertices = Cuboid::new(Vec3::ONE, Vec3::ZERO);
-
-            if let Ok(mesh) = Mesh::new(&vertices, &Cuboid::generate_indices(vertices.len())) {
-                let material = &materials.0[1]; // primitive
-                material.bind();
-                material.set_uniform(c"projection", UniformValue::Mat4(projection));
-                material.set_uniform(c"view", UniformValue::Mat4(view));
-                material.set_uniform(
-                    c"model",
-                    UniformValue::Mat4(Mat4::from_translation(pos.as_vec3())),
-                );
-                let mut size = 0;
-                gl::BindBuffer(gl::ARRAY_BUFFER, mesh.vbo);
-                gl::GetBufferParameteriv(gl::ARRAY_BUFFER, gl::BUFFER_SIZE, &mut size);
-                gl::BindVertexArray(mesh.vao);
-                gl::DrawArrays(gl::LINES, 0, vertices.len() as GLint);
-
-                #[cfg(debug_assertions)]
-                {
-                    debug_info.triangles += (size as usize / size_of::<glfw::Key>()) / 3;
-                    debug_info.draw_calls += 1;
-                }
+            let material = &materials.0[1]; // primitive
+            material.bind();
+            material.set_uniform(c"projection", UniformValue::Mat4(projection));
+            material.set_uniform(c"view", UniformValue::Mat4(view));
+            material.set_uniform(
+                c"model",
+                UniformValue::Mat4(Mat4::from_translation(pos.as_vec3())),
+            );
+            gl::BindVertexArray(gpu_meshes.cuboid.vao);
+            gl::DrawArrays(gl::LINES, 0, 24); // 6 faces of 4 vertices
+
+            #[cfg(debug_assertions)]
+            {
+                debug_info.draw_calls += 1;
             }
             gl::LineWidth(1.0);
         }
@@ -214,39 +275,79 @@ fn render_world(
         }
     }
 
+    // every chunk is drawn from the arena with the voxel material, see ChunkArena
+    let material = &materials.0[0];
+    material.bind();
+    material.set_uniform(c"projection", UniformValue::Mat4(projection));
+    material.set_uniform(c"view", UniformValue::Mat4(view));
+    material.set_uniform(c"u_light", UniformValue::Float(light.illuminance));
+    material.set_uniform(c"light_dir", UniformValue::Vec3(sky.sun));
+    material.set_uniform(c"camera_pos", UniformValue::Vec3(camera_pos));
+    shadow_maps.set_uniforms(material);
+
+    // fades out over the last 40% of the loaded chunks
+    let fog_end = (load_settings.horizontal_radius * CHUNK_SIZE) as f32;
+    material.set_uniform(c"fog_color", UniformValue::Vec3(sky.horizon));
+    material.set_uniform(c"fog_start", UniformValue::Float(fog_end * 0.6));
+    material.set_uniform(c"fog_end", UniformValue::Float(fog_end));
+
+    let mut opaque = Vec::new();
+    let mut translucent = Vec::new();
+    for (chunk_transform, mesh_id, aabb) in mesh_entities.iter() {
+        if should_cull_aabb(&frustum, chunk_transform.translation, aabb) {
+            continue;
+        }
+        let Some(chunk_mesh) = gpu_meshes.chunks.get(&mesh_id.0) else {
+            continue;
+        };
+        opaque.extend(chunk_mesh.opaque);
+        if let Some(slice) = chunk_mesh.translucent {
+            let center = chunk_transform.translation + (aabb.min + aabb.max) / 2.0;
+            translucent.push((center.distance_squared(camera_pos), slice));
+        }
+    }
+
     // main pass
     {
-        for (chunk_transform, mesh_id, material_id, aabb) in mesh_entities.iter() {
-            if should_cull_aabb(&frustum, chunk_transform.translation, aabb) {
-                continue;
-            }
-            let Some(mesh) = &meshes.0.get(&mesh_id.0) else {
-                continue;
-            };
-            let Ok(mesh) = Mesh::new(&mesh.vertices, &mesh.indices) else {
-                continue;
-            };
-            let material = &materials.0[material_id.0];
-
-            material.bind();
-            material.set_uniform(c"projection", UniformValue::Mat4(projection));
-            material.set_uniform(c"view", UniformValue::Mat4(view));
-            material.set_uniform(c"model", UniformValue::Mat4(chunk_transform.as_mat4()));
-            material.set_uniform(c"u_light", UniformValue::Float(light.illuminance));
-            material.set_uniform(c"time", UniformValue::Float(time.extra.simulated));
+        unsafe { gl::Disable(gl::BLEND) };
 
-            let _triangles = mesh.draw();
+        let _triangles = gpu_meshes.arena.draw(&opaque);
 
-            #[cfg(debug_assertions)]
-            {
-                debug_info.triangles += _triangles;
-                debug_info.draw_calls += 1;
-            }
+        #[cfg(debug_assertions)]
+        {
+            debug_info.triangles += _triangles;
+            debug_info.draw_calls += 1;
         }
     }
 
-    // TODO shadow mapping
-    {}
+    // translucent pass, back to front so what's behind is already there to blend with. the
+    // draws happen in order even within one multi-draw. only whole chunks are sorted, the
+    // depth test still hides translucent faces behind opaque ones
+    {
+        translucent.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let translucent = translucent
+            .into_iter()
+            .map(|(_, slice)| slice)
+            .collect::<Vec<_>>();
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::DepthMask(gl::FALSE);
+        }
+
+        let _triangles = gpu_meshes.arena.draw(&translucent);
+
+        #[cfg(debug_assertions)]
+        {
+            debug_info.triangles += _triangles;
+            debug_info.draw_calls += 1;
+        }
+
+        unsafe {
+            gl::DepthMask(gl::TRUE);
+            gl::Disable(gl::BLEND);
+        }
+    }
 
     (projection, view, frustum)
 }
@@ -254,6 +355,7 @@ fn render_world(
 fn render_projectiles(
     vp: In<(Mat4, Mat4, [Vec4; 6])>,
     materials: NonSend<Materials>,
+    gpu_meshes: NonSend<GpuMeshes>,
     query: Query<(&Transform, &Projectile), Without<Camera3d>>,
     game_settings: Res<GameSettings>,
     #[cfg(debug_assertions)] mut debug_info: ResMut<DebugInfo>,
@@ -270,30 +372,25 @@ fn render_projectiles(
         if should_cull_sphere(&frustum, proj_transform.translation, 0.5) {
             continue;
         }
-        let mut vertices = Vec::new();
-        for offset in -1..=1 {
-            vertices.extend(Cuboid::new(
-                Vec3::ONE * 0.25,
-                offset as f32 * projectile.direction,
-            ));
-        }
-        let indices = Cuboid::generate_indices(vertices.len());
-
-        let Ok(mesh) = Mesh::new(&vertices, &indices) else {
-            continue;
-        };
         let material = &materials.0[1]; // primitive
         material.bind();
         material.set_uniform(c"projection", UniformValue::Mat4(projection));
         material.set_uniform(c"view", UniformValue::Mat4(view));
-        material.set_uniform(c"model", UniformValue::Mat4(proj_transform.as_mat4()));
 
-        let _triangles = mesh.draw();
+        // three small cubes along the direction it's flying in
+        for offset in -1..=1 {
+            let model = proj_transform.as_mat4()
+                * Mat4::from_scale(Vec3::splat(0.25))
+                * Mat4::from_translation(offset as f32 * projectile.direction);
+            material.set_uniform(c"model", UniformValue::Mat4(model));
 
-        #[cfg(debug_assertions)]
-        {
-            debug_info.triangles += _triangles;
-            debug_info.draw_calls += 1;
+            let _triangles = gpu_meshes.cuboid.draw();
+
+            #[cfg(debug_assertions)]
+            {
+                debug_info.triangles += _triangles;
+                debug_info.draw_calls += 1;
+            }
         }
     }
 
@@ -311,8 +408,10 @@ fn render_skybox(
     materials: NonSend<Materials>,
     skybox: Res<Skybox>,
     time: Res<Time>,
+    game_settings: Res<GameSettings>,
 ) {
     let (projection, view) = *vp;
+    let sky = SkyColors::at(time.extra.simulated);
 
     unsafe {
         gl::DepthMask(gl::FALSE);
@@ -323,6 +422,15 @@ fn render_skybox(
         material.set_uniform(c"projection", UniformValue::Mat4(projection));
         material.set_uniform(c"view", UniformValue::Mat4(view));
         material.set_uniform(c"time", UniformValue::Float(time.extra.simulated));
+        material.set_uniform(c"sun_dir", UniformValue::Vec3(sky.sun));
+        material.set_uniform(c"zenith", UniformValue::Vec3(sky.zenith));
+        material.set_uniform(c"horizon", UniformValue::Vec3(sky.horizon));
+        material.set_uniform(c"sunset", UniformValue::Vec3(sky.sunset));
+        material.set_uniform(c"daylight", UniformValue::Float(sky.daylight));
+        material.set_uniform(
+            c"overlay",
+            UniformValue::Float(game_settings.skybox_overlay as u8 as f32),
+        );
 
         gl::BindVertexArray(skybox.vao);
         gl::BindTexture(gl::TEXTURE_CUBE_MAP, skybox.texture_id);
@@ -334,17 +442,34 @@ fn render_skybox(
     }
 }
 
+// ui meshes by entity, only rebuilt when their component changes or the window is resized
+#[derive(Default)]
+struct UIMeshes {
+    window_size: Vec2,
+    rects: HashMap<Entity, Mesh<PrimitiveVertex>>,
+    texts: HashMap<Entity, Mesh<TextVertex>>,
+}
+
 fn render_ui(
     materials: NonSend<Materials>,
-    text_query: Query<&UIText>,
-    rect_query: Query<&UIRect>,
+    mut ui_meshes: NonSendMut<UIMeshes>,
+    text_query: Query<(Entity, Ref<UIText>)>,
+    rect_query: Query<(Entity, Ref<UIRect>)>,
     window: Res<Window>,
     #[cfg(debug_assertions)] mut debug_info: ResMut<DebugInfo>,
 ) {
-    const CHARACTERS: &str =
-        "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+-=()[]{}<>/*:#%!?.,'\"@&$";
-
+    let ui_meshes = &mut *ui_meshes;
     let window_size = vec2(window.width as f32, window.height as f32);
+    let resized = ui_meshes.window_size != window_size;
+    ui_meshes.window_size = window_size;
+
+    // despawned ui elements
+    ui_meshes
+        .rects
+        .retain(|entity, _| rect_query.contains(*entity));
+    ui_meshes
+        .texts
+        .retain(|entity, _| text_query.contains(*entity));
 
     unsafe {
         gl::Disable(gl::DEPTH_TEST);
@@ -353,79 +478,106 @@ fn render_ui(
         gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
     }
 
-    for ui_rect in rect_query.iter() {
-        let material = &materials.0[ui_rect.material.0];
-
-        let quad = Quad::new(
-            Direction::Front,
-            vec3(
-                ui_rect.x.calculate(window_size.x) - 1.0,
-                1.0 - ui_rect.y.calculate(window_size.y),
-                0.0,
-            ),
-            vec3(
-                ui_rect.width.calculate(window_size.x),
-                -ui_rect.height.calculate(window_size.y),
-                0.0,
-            ),
-        );
-
-        let vertices = quad
-            .iter()
-            .map(|pos| PrimitiveVertex { pos: *pos })
-            .collect::<Vec<_>>();
-
-        if let Ok(mesh) = Mesh::new(&vertices, &Cuboid::generate_indices(vertices.len())) {
-            material.bind();
+    for (entity, ui_rect) in rect_query.iter() {
+        if resized || ui_rect.is_changed() || !ui_meshes.rects.contains_key(&entity) {
+            match rect_mesh(&ui_rect, window_size) {
+                Ok(mesh) => ui_meshes.rects.insert(entity, mesh),
+                Err(_) => ui_meshes.rects.remove(&entity),
+            };
+        }
+        let Some(mesh) = ui_meshes.rects.get(&entity) else {
+            continue;
+        };
 
-            let _triangles = mesh.draw();
+        materials.0[ui_rect.material.0].bind();
+        let _triangles = mesh.draw();
 
-            #[cfg(debug_assertions)]
-            {
-                debug_info.triangles += _triangles;
-                debug_info.draw_calls += 1;
-            }
+        #[cfg(debug_assertions)]
+        {
+            debug_info.triangles += _triangles;
+            debug_info.draw_calls += 1;
         }
     }
 
-    for ui_text in text_query.iter() {
+    for (entity, ui_text) in text_query.iter() {
+        if resized || ui_text.is_changed() || !ui_meshes.texts.contains_key(&entity) {
+            match text_mesh(&ui_text, window_size) {
+                Ok(mesh) => ui_meshes.texts.insert(entity, mesh),
+                Err(_) => ui_meshes.texts.remove(&entity),
+            };
+        }
+        let Some(mesh) = ui_meshes.texts.get(&entity) else {
+            continue;
+        };
+
         let material = &materials.0[ui_text.material.0];
-        let char_width = ui_text.font_size.calculate(window_size.x);
-        let char_height = ui_text.font_height.calculate(window_size.y);
-        let base_x = ui_text.x.calculate(window_size.x);
-        let mut base_y = ui_text.y.calculate(window_size.y);
-
-        let mut vertices = Vec::new();
-
-        for line in ui_text.text.split('\n') {
-            for (char_index, character) in line.chars().enumerate() {
-                if let Some(i) = CHARACTERS.find(character) {
-                    let vert = TextVertex {
-                        pos: [base_x + char_index as f32 * char_width - 1.0, 1.0 - base_y],
-                        char_id: i as u32,
-                    };
-                    vertices.extend_from_slice(&[vert, vert, vert, vert]);
-                }
-            }
-            base_y += char_height;
+        material.bind();
+        material.set_uniform(
+            c"u_size",
+            UniformValue::Vec2(Vec2::new(
+                ui_text.font_size.calculate(window_size.x),
+                -ui_text.font_height.calculate(window_size.y),
+            )),
+        );
+
+        let _triangles = mesh.draw();
+
+        #[cfg(debug_assertions)]
+        {
+            debug_info.triangles += _triangles;
+            debug_info.draw_calls += 1;
         }
+    }
+}
 
-        if let Ok(mesh) = Mesh::new(&vertices, &Cuboid::generate_indices(vertices.len())) {
-            material.bind();
-            material.set_uniform(
-                c"u_size",
-                UniformValue::Vec2(Vec2::new(char_width, -char_height)),
-            );
+fn rect_mesh(ui_rect: &UIRect, window_size: Vec2) -> Result<Mesh<PrimitiveVertex>, String> {
+    let quad = Quad::new(
+        Direction::Front,
+        vec3(
+            ui_rect.x.calculate(window_size.x) - 1.0,
+            1.0 - ui_rect.y.calculate(window_size.y),
+            0.0,
+        ),
+        vec3(
+            ui_rect.width.calculate(window_size.x),
+            -ui_rect.height.calculate(window_size.y),
+            0.0,
+        ),
+    );
 
-            let _triangles = mesh.draw();
+    let vertices = quad
+        .iter()
+        .map(|pos| PrimitiveVertex { pos: *pos })
+        .collect::<Vec<_>>();
 
-            #[cfg(debug_assertions)]
-            {
-                debug_info.triangles += _triangles;
-                debug_info.draw_calls += 1;
+    Mesh::new(&vertices, &Cuboid::generate_indices(vertices.len()))
+}
+
+fn text_mesh(ui_text: &UIText, window_size: Vec2) -> Result<Mesh<TextVertex>, String> {
+    const CHARACTERS: &str =
+        "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+-=()[]{}<>/*:#%!?.,'\"@&$";
+
+    let char_width = ui_text.font_size.calculate(window_size.x);
+    let char_height = ui_text.font_height.calculate(window_size.y);
+    let base_x = ui_text.x.calculate(window_size.x);
+    let mut base_y = ui_text.y.calculate(window_size.y);
+
+    let mut vertices = Vec::new();
+
+    for line in ui_text.text.split('\n') {
+        for (char_index, character) in line.chars().enumerate() {
+            if let Some(i) = CHARACTERS.find(character) {
+                let vert = TextVertex {
+                    pos: [base_x + char_index as f32 * char_width - 1.0, 1.0 - base_y],
+                    char_id: i as u32,
+                };
+                vertices.extend_from_slice(&[vert, vert, vert, vert]);
             }
         }
+        base_y += char_height;
     }
+
+    Mesh::new(&vertices, &Cuboid::generate_indices(vertices.len()))
 }
 
 pub fn finish_up(
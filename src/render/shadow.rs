@@ -0,0 +1,326 @@
+use std::ptr::null;
+
+use gl::types::*;
+
+use crate::{
+    GameSettings,
+    ecs::*,
+    render::{
+        arena::ArenaSlice,
+        material::{Material, UniformValue},
+        sky::sun_direction,
+    },
+    utils::should_cull_aabb,
+};
+
+// the most voxel.frag has room for
+pub const MAX_CASCADES: usize = 4;
+// how far past a cascade towards the sun blocks still cast shadows into it, so mountains and
+// trees outside the view aren't cut off
+const CASTER_DISTANCE: f32 = 96.0;
+// 0 splits the view evenly, 1 logarithmically (more of the maps close to the camera)
+const SPLIT_LAMBDA: f32 = 0.75;
+// texture unit the maps are bound to, 0 is the atlas and 1 the chunk origins
+const SHADOW_UNIT: GLuint = 2;
+
+/// `cascades` shadow maps of `resolution` pixels square cover the view up to `distance` blocks
+/// away, the closer ones cover less so shadows near the camera are sharper. No cascades turn
+/// shadows off.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ShadowSettings {
+    pub cascades: usize,
+    pub resolution: GLsizei,
+    pub distance: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            cascades: 3,
+            resolution: 2048,
+            distance: 160.0,
+        }
+    }
+}
+
+pub struct Cascade {
+    pub light_space: Mat4,
+    // view depth the cascade reaches to
+    pub end: f32,
+    // how big a texel is in blocks
+    pub texel_size: f32,
+}
+
+/// The sun's shadow maps, a layer of one depth texture array per cascade, refitted to the
+/// camera every frame.
+pub struct ShadowMaps {
+    framebuffer: GLuint,
+    texture: GLuint,
+    // what the texture was made for
+    settings: Option<ShadowSettings>,
+    pub cascades: Vec<Cascade>,
+}
+
+impl ShadowMaps {
+    pub fn new() -> Self {
+        let mut framebuffer = 0;
+        unsafe {
+            gl::GenFramebuffers(1, &mut framebuffer);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+            // depth only
+            gl::DrawBuffer(gl::NONE);
+            gl::ReadBuffer(gl::NONE);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        Self {
+            framebuffer,
+            texture: 0,
+            settings: None,
+            cascades: Vec::new(),
+        }
+    }
+
+    // remakes the texture when the settings changed
+    fn resize(&mut self, mut settings: ShadowSettings) {
+        settings.cascades = settings.cascades.min(MAX_CASCADES);
+        if self.settings == Some(settings) {
+            return;
+        }
+        self.settings = Some(settings);
+
+        unsafe {
+            gl::DeleteTextures(1, &self.texture);
+            self.texture = 0;
+            if settings.cascades == 0 {
+                return;
+            }
+
+            gl::GenTextures(1, &mut self.texture);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.texture);
+            gl::TexImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                gl::DEPTH_COMPONENT24 as GLint,
+                settings.resolution,
+                settings.resolution,
+                settings.cascades as GLsizei,
+                0,
+                gl::DEPTH_COMPONENT,
+                gl::FLOAT,
+                null(),
+            );
+            // linear filtering with a compare mode gets 2x2 PCF from the hardware for free
+            for (parameter, value) in [
+                (gl::TEXTURE_MIN_FILTER, gl::LINEAR),
+                (gl::TEXTURE_MAG_FILTER, gl::LINEAR),
+                (gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE),
+                (gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE),
+                (gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE),
+                (gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL),
+            ] {
+                gl::TexParameteri(gl::TEXTURE_2D_ARRAY, parameter, value as GLint);
+            }
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, 0);
+        }
+        println!(
+            "{} shadow cascades at {}x{}",
+            settings.cascades, settings.resolution, settings.resolution
+        );
+    }
+
+    // splits the view up to `settings.distance` and fits a cascade around each part
+    fn fit(
+        &mut self,
+        settings: ShadowSettings,
+        camera: &Camera3d,
+        projection: Mat4,
+        view: Mat4,
+        light_dir: Vec3,
+    ) {
+        self.cascades.clear();
+        let (near, far) = (camera.near, settings.distance.min(camera.far));
+        let cascades = settings.cascades.min(MAX_CASCADES);
+
+        // the edges of the view frustum, from the near plane to the far one
+        let inverse = (projection * view).inverse();
+        let edges = [
+            vec2(-1.0, -1.0),
+            vec2(1.0, -1.0),
+            vec2(-1.0, 1.0),
+            vec2(1.0, 1.0),
+        ]
+        .map(|corner| {
+            (
+                inverse.project_point3(corner.extend(-1.0)),
+                inverse.project_point3(corner.extend(1.0)),
+            )
+        });
+        let at_depth = |depth: f32| {
+            edges.map(|(start, end)| start.lerp(end, (depth - near) / (camera.far - near)))
+        };
+
+        // the same orientation for every cascade, only the position changes
+        let rotation = Mat4::look_to_rh(Vec3::ZERO, -light_dir, Vec3::Z);
+        let mut start = near;
+        for i in 1..=cascades {
+            let fraction = i as f32 / cascades as f32;
+            let even = near + (far - near) * fraction;
+            let logarithmic = near * (far / near).powf(fraction);
+            let end = even + (logarithmic - even) * SPLIT_LAMBDA;
+
+            // a sphere around the part so the cascade's size doesn't change as the camera turns
+            let corners = [at_depth(start), at_depth(end)].concat();
+            let center = corners.iter().sum::<Vec3>() / corners.len() as f32;
+            let radius = corners
+                .iter()
+                .map(|corner| corner.distance(center))
+                .fold(0.0, f32::max)
+                .ceil();
+            let texel_size = radius * 2.0 / settings.resolution as f32;
+
+            // moved in whole texels so shadow edges don't shimmer when the camera moves
+            let mut light_center = rotation.transform_point3(center);
+            light_center.x = (light_center.x / texel_size).floor() * texel_size;
+            light_center.y = (light_center.y / texel_size).floor() * texel_size;
+            let center = rotation.inverse().transform_point3(light_center);
+
+            let light_view = Mat4::look_to_rh(
+                center + light_dir * (radius + CASTER_DISTANCE),
+                -light_dir,
+                Vec3::Z,
+            );
+            let light_projection = Mat4::orthographic_rh_gl(
+                -radius,
+                radius,
+                -radius,
+                radius,
+                0.0,
+                radius * 2.0 + CASTER_DISTANCE,
+            );
+            self.cascades.push(Cascade {
+                light_space: light_projection * light_view,
+                end,
+                texel_size,
+            });
+            start = end;
+        }
+    }
+
+    /// binds the maps and sets what voxel.frag needs to sample them
+    pub fn set_uniforms(&self, material: &Material) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + SHADOW_UNIT);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.texture);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+        material.set_uniform(c"shadow_maps", UniformValue::Int(SHADOW_UNIT as GLint));
+        material.set_uniform(
+            c"cascade_count",
+            UniformValue::Int(self.cascades.len() as GLint),
+        );
+        if self.cascades.is_empty() {
+            return;
+        }
+        material.set_uniform(
+            c"shadow_matrices",
+            UniformValue::Mat4Array(self.cascades.iter().map(|c| c.light_space).collect()),
+        );
+        material.set_uniform(
+            c"cascade_ends",
+            UniformValue::FloatArray(self.cascades.iter().map(|c| c.end).collect()),
+        );
+        material.set_uniform(
+            c"cascade_texels",
+            UniformValue::FloatArray(self.cascades.iter().map(|c| c.texel_size).collect()),
+        );
+    }
+}
+
+impl Drop for ShadowMaps {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+// chunk depth from the sun into each cascade, before anything samples them
+#[allow(clippy::too_many_arguments)]
+pub fn render_shadows(
+    vp: In<(Mat4, Mat4, [Vec4; 6])>,
+    mut shadow_maps: NonSendMut<ShadowMaps>,
+    gpu_meshes: NonSend<GpuMeshes>,
+    materials: NonSend<Materials>,
+    mesh_entities: Query<(&Transform, &Mesh3d, &Aabb)>,
+    camera: Single<&Camera3d>,
+    game_settings: Res<GameSettings>,
+    time: Res<Time>,
+    #[cfg(debug_assertions)] mut debug_info: ResMut<DebugInfo>,
+) -> (Mat4, Mat4, [Vec4; 6]) {
+    let (projection, view, frustum) = *vp;
+    let settings = game_settings.shadows;
+    shadow_maps.resize(settings);
+    shadow_maps.fit(
+        settings,
+        &camera,
+        projection,
+        view,
+        sun_direction(time.extra.simulated),
+    );
+    if shadow_maps.cascades.is_empty() {
+        return (projection, view, frustum);
+    }
+
+    let mut viewport = [0; 4];
+    unsafe {
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        gl::BindFramebuffer(gl::FRAMEBUFFER, shadow_maps.framebuffer);
+        gl::Viewport(0, 0, settings.resolution, settings.resolution);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::Disable(gl::BLEND);
+        // plants are single planes, and the back faces of blocks are in the right place too
+        gl::Disable(gl::CULL_FACE);
+    }
+
+    let material = &materials.0[2]; // shadow
+    material.bind();
+    for (layer, cascade) in shadow_maps.cascades.iter().enumerate() {
+        unsafe {
+            gl::FramebufferTextureLayer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                shadow_maps.texture,
+                0,
+                layer as GLint,
+            );
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+        }
+        material.set_uniform(c"light_space", UniformValue::Mat4(cascade.light_space));
+
+        // only opaque blocks cast shadows, the sun shines through water and glass
+        let light_frustum = camera.frustum(cascade.light_space);
+        let casters = mesh_entities
+            .iter()
+            .filter(|(transform, _, aabb)| {
+                !should_cull_aabb(&light_frustum, transform.translation, aabb)
+            })
+            .filter_map(|(_, mesh_id, _)| gpu_meshes.chunks.get(&mesh_id.0)?.opaque)
+            .collect::<Vec<ArenaSlice>>();
+
+        let _triangles = gpu_meshes.arena.draw(&casters);
+
+        #[cfg(debug_assertions)]
+        {
+            debug_info.triangles += _triangles;
+            debug_info.draw_calls += 1;
+        }
+    }
+
+    unsafe {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+    }
+
+    (projection, view, frustum)
+}
@@ -0,0 +1,52 @@
+use std::f32::consts::TAU;
+
+use crate::{ecs::*, utils::SECS_IN_DAY};
+
+const DAY_ZENITH: Vec3 = vec3(0.18, 0.42, 0.78);
+const DAY_HORIZON: Vec3 = vec3(0.44, 0.73, 0.88);
+const NIGHT_ZENITH: Vec3 = vec3(0.005, 0.008, 0.025);
+const NIGHT_HORIZON: Vec3 = vec3(0.03, 0.04, 0.08);
+const SUNSET: Vec3 = vec3(1.0, 0.42, 0.12);
+
+/// Direction towards the sun `time` seconds into the day, the moon is opposite it.
+pub fn sun_direction(time: f32) -> Vec3 {
+    (Mat3::from_rotation_x(time / SECS_IN_DAY * TAU) * vec3(0.2, -1.0, 0.0)).normalize()
+}
+
+/// The sky's colours for the sun's position. skybox.frag draws the sky from them, and the
+/// clear colour and the fog are its horizon so far away terrain fades into it.
+#[derive(Clone, Copy, Debug)]
+pub struct SkyColors {
+    pub sun: Vec3,
+    pub zenith: Vec3,
+    pub horizon: Vec3,
+    // the glow around the sun when it's low, black the rest of the day
+    pub sunset: Vec3,
+    // 1 at day, 0 at night, the stars come out as it goes down
+    pub daylight: f32,
+}
+
+impl SkyColors {
+    pub fn at(time: f32) -> Self {
+        let sun = sun_direction(time);
+        let daylight = smoothstep(-0.25, 0.25, sun.y);
+        // strongest with the sun right at the horizon, gone once it's a bit above or below
+        let sunset = (1.0 - (sun.y / 0.3).abs()).max(0.0).powi(2);
+
+        Self {
+            sun,
+            zenith: NIGHT_ZENITH.lerp(DAY_ZENITH, daylight),
+            horizon: NIGHT_HORIZON
+                .lerp(DAY_HORIZON, daylight)
+                .lerp(SUNSET, sunset * 0.5),
+            sunset: SUNSET * sunset,
+            daylight,
+        }
+    }
+}
+
+#[inline]
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
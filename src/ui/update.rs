@@ -1,9 +1,9 @@
 use glfw::MouseButton;
 
 use crate::{
-    CHUNK_SIZE,
     ecs::*,
     ui::{Button, DebugText, UIRect, UIText},
+    world::{ChunkLoadSettings, NoiseFunctions, biome::biome_at, coords::BlockPos},
 };
 
 pub fn handle_picking(
@@ -36,16 +36,11 @@ pub fn update_ui(
     mut last_frames: Local<(u32, f64, u32, f64)>, // frame count, time, last fps, last update time
     time: Res<Time>,
     player: Single<&Transform, With<Camera3d>>,
+    noises: Res<NoiseFunctions>,
+    load_settings: Res<ChunkLoadSettings>,
 ) {
     let pt = player.translation;
-    let chunk_pos = (pt / CHUNK_SIZE as f32).as_ivec3();
-
-    let local_block_pos = vec3(
-        pt.x.rem_euclid(CHUNK_SIZE as f32),
-        pt.y.rem_euclid(CHUNK_SIZE as f32),
-        pt.z.rem_euclid(CHUNK_SIZE as f32),
-    )
-    .as_ivec3();
+    let (chunk_pos, local_block_pos) = BlockPos::from_world(pt).split();
 
     let (yaw, pitch, _) = player.rotation.to_euler(EulerRot::YXZ);
 
@@ -72,8 +67,8 @@ pub fn update_ui(
         *t = 0.0;
     }
 
-    debug_text.text = format!(
-        "FPS:    {}\nXYZ:    {:.2}\nChunk:  {:.2}\nBlock:  {:.2}\nFacing: {} / {}'/ {}'\nTime: {}",
+    let text = format!(
+        "FPS:    {}\nXYZ:    {:.2}\nChunk:  {:.2}\nBlock:  {:.2}\nFacing: {} / {}'/ {}'\nTime: {}\nBiome:  {}\nSeed:   {} ({})\nRender distance: {}/{}",
         *lf,
         pt,
         chunk_pos,
@@ -83,6 +78,15 @@ pub fn update_ui(
         -pitch.to_degrees() as i32,
         chrono::NaiveTime::from_num_seconds_from_midnight_opt(time.extra.simulated as u32, 0)
             .unwrap()
-            .format("%H:%M")
-    )
+            .format("%H:%M"),
+        biome_at(*BlockPos::from_world(pt), &noises).info().name,
+        noises.seed,
+        noises.settings.preset,
+        load_settings.horizontal_radius,
+        load_settings.vertical_radius,
+    );
+    // the text's mesh is rebuilt whenever it changes
+    if debug_text.text != text {
+        debug_text.text = text;
+    }
 }
@@ -6,7 +6,7 @@ use glfw::{Context, PWindow};
 use crate::{
     CHUNK_SIZE, SEA_LEVEL,
     ecs::{Aabb, Window},
-    world::mesher::Block,
+    world::{biome::Biome, block::Block},
 };
 
 pub const SECS_IN_DAY: f32 = 86400.0;
@@ -18,44 +18,31 @@ pub fn set_cursor_grab(window: &mut Window, val: bool) {
 }
 
 #[inline]
-pub const fn generate_block_at(pos: IVec3, max_y: i32) -> Block {
+pub fn generate_block_at(pos: IVec3, max_y: i32, biome: Biome) -> Block {
     let y = pos.y;
-    if y == 0 {
-        Block::Bedrock
-    } else if y < max_y {
+    if y < max_y {
+        let (surface, subsurface) = biome.layers(max_y);
         match y {
-            _ if y > 165 => Block::Snow,
-            _ if y > 140 => Block::Stone,
-            _ if y == max_y - 1 => Block::Grass,
-            _ if y >= max_y - 4 => Block::Dirt,
-            _ => Block::Stone,
+            _ if y == max_y - 1 => surface,
+            _ if y >= max_y - 1 - biome.info().subsurface_depth => subsurface,
+            _ => Block::STONE,
         }
     } else if y < SEA_LEVEL {
-        Block::Water
+        Block::WATER
     } else {
-        Block::Air
+        Block::AIR
     }
+}
 
-    // let tree_probabilty = tree_noise(pos.xz().as_vec2(), seed);
-
-    // if tree_probabilty > 0.85 && max_y < 90 && max_y > SEA_LEVEL + 2 {
-    //     for (y, tree_layer) in TREE_OBJECT.iter().enumerate() {
-    //         for (z, tree_row) in tree_layer.iter().enumerate() {
-    //             for (x, block) in tree_row.iter().enumerate() {
-    //                 let mut tree_pos = ivec3(3 + x as i32, y as i32, 3 + z as i32);
-    //                 let (local_max_y, _) = terrain_noise((pos + tree_pos).as_vec3().xz(), seed);
-
-    //                 tree_pos.y += local_max_y;
-
-    //                 if pos == tree_pos {
-    //                     return *block;
-    //                 }
-    //             }
-    //         }
-    //     }
-    // }
-
-    // terrain_block
+/// deterministic per-position randomness (splitmix64 over the seed and coordinates)
+#[inline]
+pub const fn hash_2d(seed: u32, pos: IVec2) -> u64 {
+    let mut x = (seed as u64) << 32 ^ (pos.x as u32 as u64) << 16 ^ (pos.y as u32 as u64);
+    x ^= (pos.y as u32 as u64) << 40;
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
 }
 
 #[inline]
@@ -4,6 +4,7 @@ use crate::{
     App, GameSettings,
     ecs::*,
     utils::{take_screenshot, toggle_fullscreen},
+    world::{ChunkLoadSettings, mesher::MeshingMode},
 };
 
 #[derive(Event)]
@@ -66,12 +67,18 @@ fn handle_keybinds(
     ns_window: NonSend<NSWindow>,
     keyboard: Res<KeyboardInput>,
     mut game_settings: ResMut<GameSettings>,
+    mut load_settings: ResMut<ChunkLoadSettings>,
+    mut meshing_mode: ResMut<MeshingMode>,
 ) {
     for key in keyboard.just_pressed.iter() {
         match key {
             Key::F1 => game_settings.wireframe = !game_settings.wireframe,
             Key::F2 => take_screenshot(&ns_window.window),
+            Key::F3 => meshing_mode.toggle(),
+            Key::F4 => game_settings.skybox_overlay = !game_settings.skybox_overlay,
             Key::F11 => toggle_fullscreen(&ns_window.window),
+            Key::Equal => load_settings.adjust(1),
+            Key::Minus => load_settings.adjust(-1),
             _ => {}
         }
     }
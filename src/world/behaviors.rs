@@ -0,0 +1,128 @@
+use std::collections::{HashSet, VecDeque};
+
+use rand::Rng;
+
+use crate::{
+    ecs::*,
+    world::{
+        block::Block,
+        coords::BlockPos,
+        fluid,
+        mesher::Direction,
+        ticks::{BlockBehavior, BlockBehaviors, TickContext},
+    },
+};
+
+// fixed updates sand hangs in the air before it falls another block
+const FALL_DELAY: u64 = 2;
+// grass needs about as much sky light as a block under an open sky minus a few blocks of shade
+const GRASS_MIN_SKY_LIGHT: u8 = 9;
+// leaves further than this (through other leaves) from any wood decay
+const LEAF_REACH: u32 = 6;
+
+/// the behaviours of the built-in blocks
+pub fn register_default_behaviors(behaviors: &mut BlockBehaviors) {
+    let water = BlockBehavior {
+        neighbor_update: Some(fluid::schedule_flow),
+        scheduled_tick: Some(fluid::flow),
+        ..Default::default()
+    };
+    behaviors
+        .register(Block::AIR, water)
+        .register(Block::WATER, water)
+        .register(
+            Block::SAND,
+            BlockBehavior {
+                neighbor_update: Some(check_fall),
+                scheduled_tick: Some(fall),
+                ..Default::default()
+            },
+        )
+        .register(
+            Block::GRASS,
+            BlockBehavior {
+                random_tick: Some(spread_grass),
+                ..Default::default()
+            },
+        )
+        .register(
+            Block::LEAF,
+            BlockBehavior {
+                random_tick: Some(decay_leaves),
+                ..Default::default()
+            },
+        );
+}
+
+// whether a falling block can move into this one, water and other fluids just get replaced
+#[inline]
+fn can_fall_into(block: Block) -> bool {
+    block.is_air() || block == Block::WATER
+}
+
+fn check_fall(ctx: &mut TickContext, pos: BlockPos, _block: Block) {
+    if ctx
+        .block(pos.offset(IVec3::NEG_Y))
+        .is_some_and(can_fall_into)
+    {
+        ctx.schedule(pos, FALL_DELAY);
+    }
+}
+
+fn fall(ctx: &mut TickContext, pos: BlockPos, block: Block) {
+    let below = pos.offset(IVec3::NEG_Y);
+    if ctx.block(below).is_some_and(can_fall_into) {
+        ctx.set_block(pos, Block::AIR);
+        // the neighbour update of its new position makes it keep falling
+        ctx.set_block(below, block);
+    }
+}
+
+fn spread_grass(ctx: &mut TickContext, pos: BlockPos, _block: Block) {
+    if !can_grass_live(ctx, pos) {
+        ctx.set_block(pos, Block::DIRT);
+        return;
+    }
+
+    let target = pos.offset(ivec3(
+        ctx.rng.random_range(-1..=1),
+        ctx.rng.random_range(-3..=1),
+        ctx.rng.random_range(-1..=1),
+    ));
+    if ctx.block(target) == Some(Block::DIRT)
+        && can_grass_live(ctx, target)
+        && ctx
+            .light(target.offset(IVec3::Y))
+            .is_some_and(|light| light.sky() >= GRASS_MIN_SKY_LIGHT)
+    {
+        ctx.set_block(target, Block::GRASS);
+    }
+}
+
+// grass dies under water and anything that isn't see-through, unloaded chunks count as open
+#[inline]
+fn can_grass_live(ctx: &TickContext, pos: BlockPos) -> bool {
+    ctx.block(pos.offset(IVec3::Y)).is_none_or(|above| {
+        above != Block::WATER && (!above.is_solid() || above.info().transparent)
+    })
+}
+
+fn decay_leaves(ctx: &mut TickContext, pos: BlockPos, _block: Block) {
+    // breadth first through connected leaves, stopping at the first piece of wood
+    let mut visited = HashSet::from([pos]);
+    let mut queue = VecDeque::from([(pos, 0)]);
+    while let Some((pos, distance)) = queue.pop_front() {
+        for normal in Direction::NORMALS {
+            let neighbor = pos.offset(IVec3::from(normal));
+            match ctx.block(neighbor) {
+                // the tree may continue in a chunk that isn't loaded
+                Some(Block::WOOD) | None => return,
+                Some(Block::LEAF) if distance + 1 < LEAF_REACH && visited.insert(neighbor) => {
+                    queue.push_back((neighbor, distance + 1));
+                }
+                _ => {}
+            }
+        }
+    }
+    ctx.set_block(pos, Block::AIR);
+}
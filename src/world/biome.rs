@@ -0,0 +1,245 @@
+use glam::*;
+use noise::NoiseFn;
+
+use crate::{
+    SEA_LEVEL,
+    world::{NoiseFunctions, block::Block},
+};
+
+// surfaces this close to the sea are beaches
+const BEACH_HEIGHT: i32 = 2;
+// how far apart (in climate space) biome height profiles are blended,
+// larger values give smoother but less distinct transitions
+const BLEND_WIDTH: f32 = 0.08;
+// continentalness decides between ocean, lowlands and mountains so it weighs more than climate
+const CONTINENTALNESS_WEIGHT: f32 = 2.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Biome {
+    Ocean,
+    Desert,
+    Plains,
+    Forest,
+    Tundra,
+    Mountains,
+}
+
+pub struct BiomeInfo {
+    pub name: &'static str,
+    pub map_color: [u8; 3], // used by the preview exporter
+    // where the biome sits in (continentalness, temperature, humidity), every axis 0-1
+    pub climate: Vec3,
+    pub surface: Block,
+    pub subsurface: Block,
+    pub subsurface_depth: i32,
+    pub beach: Block,
+    pub ocean_floor: Block,
+    // surfaces above these heights turn to bare stone / snow
+    pub rock_line: i32,
+    pub snow_line: i32,
+    pub tree_chance: f32,
+}
+
+// indexed by Biome
+static BIOMES: [BiomeInfo; 6] = [
+    // Biome::Ocean
+    BiomeInfo {
+        name: "Ocean",
+        map_color: [36, 64, 180],
+        climate: Vec3::new(0.3, 0.5, 0.5),
+        surface: Block::SAND,
+        subsurface: Block::SAND,
+        subsurface_depth: 3,
+        beach: Block::SAND,
+        ocean_floor: Block::SAND,
+        rock_line: i32::MAX,
+        snow_line: i32::MAX,
+        tree_chance: 0.0,
+    },
+    // Biome::Desert
+    BiomeInfo {
+        name: "Desert",
+        map_color: [226, 208, 128],
+        climate: Vec3::new(0.5, 0.75, 0.3),
+        surface: Block::SAND,
+        subsurface: Block::SAND,
+        subsurface_depth: 5,
+        beach: Block::SAND,
+        ocean_floor: Block::SAND,
+        rock_line: i32::MAX,
+        snow_line: i32::MAX,
+        tree_chance: 0.0,
+    },
+    // Biome::Plains
+    BiomeInfo {
+        name: "Plains",
+        map_color: [120, 180, 72],
+        climate: Vec3::new(0.5, 0.5, 0.5),
+        surface: Block::GRASS,
+        subsurface: Block::DIRT,
+        subsurface_depth: 4,
+        beach: Block::SAND,
+        ocean_floor: Block::DIRT,
+        rock_line: i32::MAX,
+        snow_line: i32::MAX,
+        tree_chance: 0.1,
+    },
+    // Biome::Forest
+    BiomeInfo {
+        name: "Forest",
+        map_color: [34, 110, 40],
+        climate: Vec3::new(0.5, 0.5, 0.75),
+        surface: Block::GRASS,
+        subsurface: Block::DIRT,
+        subsurface_depth: 4,
+        beach: Block::SAND,
+        ocean_floor: Block::DIRT,
+        rock_line: i32::MAX,
+        snow_line: i32::MAX,
+        tree_chance: 0.4,
+    },
+    // Biome::Tundra
+    BiomeInfo {
+        name: "Tundra",
+        map_color: [220, 232, 240],
+        climate: Vec3::new(0.5, 0.2, 0.5),
+        surface: Block::SNOW,
+        subsurface: Block::DIRT,
+        subsurface_depth: 3,
+        beach: Block::SNOW,
+        ocean_floor: Block::DIRT,
+        rock_line: i32::MAX,
+        snow_line: i32::MIN,
+        tree_chance: 0.03,
+    },
+    // Biome::Mountains
+    BiomeInfo {
+        name: "Mountains",
+        map_color: [128, 128, 128],
+        climate: Vec3::new(0.72, 0.5, 0.5),
+        surface: Block::GRASS,
+        subsurface: Block::DIRT,
+        subsurface_depth: 3,
+        beach: Block::STONE,
+        ocean_floor: Block::STONE,
+        rock_line: 140,
+        snow_line: 165,
+        tree_chance: 0.1,
+    },
+];
+
+impl Biome {
+    pub const ALL: [Biome; 6] = [
+        Biome::Ocean,
+        Biome::Desert,
+        Biome::Plains,
+        Biome::Forest,
+        Biome::Tundra,
+        Biome::Mountains,
+    ];
+
+    #[inline]
+    pub fn info(self) -> &'static BiomeInfo {
+        &BIOMES[self as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|biome| biome.info().name.eq_ignore_ascii_case(name))
+    }
+
+    /// surface and subsurface blocks of a column whose surface is at `max_y`
+    #[inline]
+    pub fn layers(self, max_y: i32) -> (Block, Block) {
+        let info = self.info();
+        if max_y <= SEA_LEVEL {
+            (info.ocean_floor, info.ocean_floor)
+        } else if max_y <= SEA_LEVEL + BEACH_HEIGHT {
+            (info.beach, info.beach)
+        } else if max_y > info.snow_line {
+            let below = if max_y > info.rock_line {
+                Block::STONE
+            } else {
+                info.subsurface
+            };
+            (Block::SNOW, below)
+        } else if max_y > info.rock_line {
+            (Block::STONE, Block::STONE)
+        } else {
+            (info.surface, info.subsurface)
+        }
+    }
+}
+
+/// (continentalness, temperature, humidity) of a column, every axis 0-1
+#[inline]
+pub fn climate(pos: Vec2, noises: &NoiseFunctions) -> Vec3 {
+    let pos = pos.as_dvec2().to_array();
+    vec3(
+        noises.biome.get(pos) as f32,
+        noises.temperature.get(pos) as f32,
+        noises.humidity.get(pos) as f32,
+    ) * 0.5
+        + 0.5
+}
+
+#[inline]
+fn climate_distance_squared(a: Vec3, b: Vec3) -> f32 {
+    ((a - b) * vec3(CONTINENTALNESS_WEIGHT, 1.0, 1.0)).length_squared()
+}
+
+/// the biome closest to the climate of a column
+pub fn select_biome(climate: Vec3) -> Biome {
+    let mut closest = (Biome::Plains, f32::MAX);
+    for biome in Biome::ALL {
+        let distance = climate_distance_squared(climate, biome.info().climate);
+        if distance < closest.1 {
+            closest = (biome, distance);
+        }
+    }
+    closest.0
+}
+
+/// the biome of any world position, biomes span whole columns
+#[inline]
+pub fn biome_at(pos: IVec3, noises: &NoiseFunctions) -> Biome {
+    match noises.settings.only_biome {
+        Some(biome) => biome,
+        None => select_biome(climate(pos.xz().as_vec2(), noises)),
+    }
+}
+
+// max_y, biome
+pub fn terrain_noise(pos: Vec2, noises: &NoiseFunctions) -> (i32, Biome) {
+    let settings = &noises.settings;
+    let (climate, biome) = match settings.only_biome {
+        Some(biome) => (None, biome),
+        None => {
+            let climate = climate(pos, noises);
+            (Some(climate), select_biome(climate))
+        }
+    };
+    if let Some(height) = settings.flat_height {
+        return (height, biome);
+    }
+
+    let terrain_fbm = (noises.terrain.get(pos.as_dvec2().into()) as f32 + 1.0) / 2.0;
+    let Some(climate) = climate else {
+        return (settings.height(biome, terrain_fbm) as i32, biome);
+    };
+
+    // height profiles of nearby biomes are blended so borders don't turn into cliffs
+    let distances = Biome::ALL.map(|biome| climate_distance_squared(climate, biome.info().climate));
+    let nearest = distances.iter().copied().fold(f32::MAX, f32::min);
+
+    let mut total = 0.0;
+    let mut height = 0.0;
+    for (biome, distance) in Biome::ALL.into_iter().zip(distances) {
+        let weight = (-(distance - nearest) / (BLEND_WIDTH * BLEND_WIDTH)).exp();
+        height += weight * settings.height(biome, terrain_fbm);
+        total += weight;
+    }
+
+    ((height / total) as i32, biome)
+}
@@ -0,0 +1,268 @@
+use std::{collections::HashMap, ops::Deref, sync::OnceLock};
+
+use bevy_ecs::prelude::Resource;
+use glam::{UVec2, UVec4};
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::world::{
+    mesher::Direction,
+    shape::{BlockShape, FaceCoverage},
+};
+
+pub const BLOCKS_PATH: &str = "assets/blocks.toml";
+pub const MAX_BLOCKS: usize = 64; // block ids are packed into 6 bits of the voxel vertex
+
+// loaded once at startup and never changed after, so [`Block::info`] can be called from
+// meshing, lighting and worldgen tasks without passing the registry down to every one of them.
+// systems get it through the [`Blocks`] resource instead
+static REGISTRY: OnceLock<BlockRegistry> = OnceLock::new();
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct Block(pub u8);
+
+impl Block {
+    // blocks the engine itself refers to (worldgen etc.),
+    // their ids are checked against blocks.toml when the registry is loaded
+    pub const AIR: Block = Block(0);
+    pub const STONE: Block = Block(1);
+    pub const DIRT: Block = Block(2);
+    pub const GRASS: Block = Block(3);
+    pub const PLANK: Block = Block(4);
+    pub const BEDROCK: Block = Block(5);
+    pub const WATER: Block = Block(6);
+    pub const SAND: Block = Block(7);
+    pub const WOOD: Block = Block(8);
+    pub const LEAF: Block = Block(9);
+    pub const SNOW: Block = Block(10);
+
+    const BUILTIN: [(Block, &str); 11] = [
+        (Self::AIR, "air"),
+        (Self::STONE, "stone"),
+        (Self::DIRT, "dirt"),
+        (Self::GRASS, "grass"),
+        (Self::PLANK, "plank"),
+        (Self::BEDROCK, "bedrock"),
+        (Self::WATER, "water"),
+        (Self::SAND, "sand"),
+        (Self::WOOD, "wood"),
+        (Self::LEAF, "leaf"),
+        (Self::SNOW, "snow"),
+    ];
+
+    #[inline]
+    pub fn info(self) -> &'static BlockInfo {
+        &BlockRegistry::global().blocks[self.0 as usize]
+    }
+
+    #[inline]
+    pub fn is_air(&self) -> bool {
+        *self == Block::AIR
+    }
+
+    #[inline]
+    pub fn is_solid(&self) -> bool {
+        self.info().solid
+    }
+
+    /// drawn see-through, air included
+    #[inline]
+    pub fn is_transparent(&self) -> bool {
+        self.info().transparent
+    }
+}
+
+pub struct BlockInfo {
+    pub name: String,
+    pub display_name: String,
+    pub textures: [u32; 6], // atlas tile per face, indexed by Direction
+    pub solid: bool,
+    pub transparent: bool,
+    pub light_emission: u8, // 0-15
+    pub shape: BlockShape,
+    pub coverage: [FaceCoverage; 6], // indexed by Direction, see BlockShape::coverage
+}
+
+pub struct BlockRegistry {
+    pub blocks: Vec<BlockInfo>,
+    pub atlas_size: UVec2, // in tiles
+}
+
+/// The loaded [`BlockRegistry`] for systems and plugins.
+#[derive(Resource, Clone, Copy)]
+pub struct Blocks(pub &'static BlockRegistry);
+
+impl Deref for Blocks {
+    type Target = BlockRegistry;
+
+    fn deref(&self) -> &BlockRegistry {
+        self.0
+    }
+}
+
+impl BlockRegistry {
+    /// loads the registry from `path` and makes it available through [`BlockRegistry::global`]
+    pub fn init(path: &str) -> Result<&'static Self, String> {
+        if let Some(registry) = REGISTRY.get() {
+            return Ok(registry);
+        }
+        let registry = Self::load(path)?;
+        Ok(REGISTRY.get_or_init(|| registry))
+    }
+
+    #[inline]
+    pub fn global() -> &'static Self {
+        REGISTRY.get().expect("block registry isn't loaded")
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let doc = std::fs::read_to_string(path)
+            .map_err(|_| format!("could not read {path}"))?
+            .parse::<DocumentMut>()
+            .map_err(|e| format!("could not parse {path}: {e}"))?;
+
+        let atlas = doc
+            .get("atlas")
+            .and_then(Item::as_table)
+            .ok_or_else(|| format!("{path} is missing [atlas]"))?;
+        let atlas_size = UVec2::new(
+            get_int(atlas, "columns").ok_or_else(|| format!("{path}: atlas.columns"))? as u32,
+            get_int(atlas, "rows").ok_or_else(|| format!("{path}: atlas.rows"))? as u32,
+        );
+        if atlas_size.x * atlas_size.y > 256 {
+            return Err(format!("{path}: the atlas can't have more than 256 tiles"));
+        }
+
+        let mut tiles = HashMap::new();
+        if let Some(textures) = doc.get("textures").and_then(Item::as_table) {
+            for (name, tile) in textures.iter() {
+                let tile = tile
+                    .as_array()
+                    .filter(|tile| tile.len() == 2)
+                    .and_then(|tile| Some((tile.get(0)?.as_integer()?, tile.get(1)?.as_integer()?)))
+                    .filter(|&(x, y)| {
+                        (0..atlas_size.x as i64).contains(&x)
+                            && (0..atlas_size.y as i64).contains(&y)
+                    })
+                    .ok_or_else(|| format!("{path}: texture {name} isn't a valid [column, row]"))?;
+                tiles.insert(
+                    name.to_string(),
+                    tile.1 as u32 * atlas_size.x + tile.0 as u32,
+                );
+            }
+        }
+
+        let mut blocks = Vec::new();
+        let mut names = HashMap::new();
+        let entries = doc
+            .get("block")
+            .and_then(Item::as_array_of_tables)
+            .ok_or_else(|| format!("{path} has no [[block]] entries"))?;
+
+        for entry in entries.iter() {
+            let name = get_str(entry, "name")
+                .ok_or_else(|| format!("{path}: block #{} has no name", blocks.len()))?
+                .to_string();
+            let id = blocks.len();
+            if id >= MAX_BLOCKS {
+                return Err(format!("{path}: more than {MAX_BLOCKS} blocks"));
+            }
+
+            let mut textures = [0; 6];
+            if let Some(faces) = entry.get("textures").and_then(Item::as_table_like) {
+                // "all" < "side" < per-direction, later keys override earlier ones
+                for (key, dirs) in [
+                    ("all", &[0, 1, 2, 3, 4, 5][..]),
+                    ("side", &[0, 1, 4, 5]),
+                    ("left", &[Direction::Left as usize]),
+                    ("right", &[Direction::Right as usize]),
+                    ("bottom", &[Direction::Bottom as usize]),
+                    ("top", &[Direction::Top as usize]),
+                    ("back", &[Direction::Back as usize]),
+                    ("front", &[Direction::Front as usize]),
+                ] {
+                    let Some(texture) = faces.get(key).and_then(Item::as_str) else {
+                        continue;
+                    };
+                    let tile = *tiles
+                        .get(texture)
+                        .ok_or_else(|| format!("{path}: {name} uses unknown texture {texture}"))?;
+                    for &dir in dirs {
+                        textures[dir] = tile;
+                    }
+                }
+            }
+
+            let light_emission = get_int(entry, "light_emission").unwrap_or(0);
+            if !(0..=15).contains(&light_emission) {
+                return Err(format!("{path}: {name}.light_emission has to be 0-15"));
+            }
+
+            let shape =
+                BlockShape::from_entry(entry).map_err(|e| format!("{path}: {name}: {e}"))?;
+
+            if names.insert(name.clone(), Block(id as u8)).is_some() {
+                return Err(format!("{path}: block {name} is defined twice"));
+            }
+            blocks.push(BlockInfo {
+                display_name: get_str(entry, "display_name").unwrap_or(&name).to_string(),
+                name,
+                textures,
+                solid: get_bool(entry, "solid").unwrap_or(true),
+                transparent: get_bool(entry, "transparent").unwrap_or(false),
+                light_emission: light_emission as u8,
+                coverage: shape.coverage(),
+                shape,
+            });
+        }
+
+        for (block, name) in Block::BUILTIN {
+            if names.get(name) != Some(&block) {
+                return Err(format!(
+                    "{path}: block {name} has to be defined with id {}",
+                    block.0
+                ));
+            }
+        }
+
+        Ok(Self { blocks, atlas_size })
+    }
+
+    /// the block with this `name` in blocks.toml
+    pub fn by_name(&self, name: &str) -> Option<Block> {
+        self.blocks
+            .iter()
+            .position(|info| info.name == name)
+            .map(|id| Block(id as u8))
+    }
+
+    /// per-block data for the voxel shader:
+    /// x - tiles of the left, right, bottom and top faces (8 bits each)
+    /// y - tiles of the back and front faces
+    /// z - flags, bit 0 transparent, bits 8-11 light emission
+    pub fn shader_data(&self) -> Vec<UVec4> {
+        self.blocks
+            .iter()
+            .map(|info| {
+                let t = info.textures;
+                UVec4::new(
+                    t[0] | t[1] << 8 | t[2] << 16 | t[3] << 24,
+                    t[4] | t[5] << 8,
+                    info.transparent as u32 | (info.light_emission as u32) << 8,
+                    0,
+                )
+            })
+            .collect()
+    }
+}
+
+fn get_str<'a>(table: &'a Table, key: &str) -> Option<&'a str> {
+    table.get(key).and_then(Item::as_str)
+}
+
+fn get_int(table: &Table, key: &str) -> Option<i64> {
+    table.get(key).and_then(Item::as_integer)
+}
+
+fn get_bool(table: &Table, key: &str) -> Option<bool> {
+    table.get(key).and_then(Item::as_bool)
+}
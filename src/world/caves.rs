@@ -0,0 +1,221 @@
+use glam::*;
+use noise::NoiseFn;
+
+use crate::{
+    CHUNK_SIZE, SEA_LEVEL,
+    utils::generate_block_at,
+    world::{
+        NoiseFunctions,
+        biome::{Biome, terrain_noise},
+        block::Block,
+        coords::{ChunkPos, LocalPos},
+    },
+};
+
+// how far above/below the heightmap surface overhangs can add/remove terrain
+pub const OVERHANG_DEPTH: i32 = 8;
+const OVERHANG_STRENGTH: f32 = 12.0;
+const CHEESE_THRESHOLD: f32 = 0.45;
+const WORM_WIDTH: f32 = 0.07;
+const MAX_RAVINE_DEPTH: f32 = 40.0;
+const RAVINE_WIDTH: f32 = 0.025;
+// caves never break through the ocean floor, or the sea would need somewhere to go
+const OCEAN_FLOOR_THICKNESS: i32 = 6;
+
+// 3d noise is sampled on a coarse lattice and interpolated in between,
+// a chunk only needs (32 / 4 + 1)^3 samples instead of one per block
+const SAMPLE_SPACING: i32 = 4;
+
+/// x - cave density (> 0 is carved out), y - overhang offset
+fn sample(pos: IVec3, noises: &NoiseFunctions) -> Vec2 {
+    let p = pos.as_dvec3();
+    // worms are stretched horizontally so tunnels don't go straight down
+    let worm_p = (p * dvec3(1.0, 2.0, 1.0)).to_array();
+
+    let cheese = noises.cave.get(p.to_array()) as f32 - CHEESE_THRESHOLD;
+    let worm =
+        WORM_WIDTH - (noises.worm_a.get(worm_p).abs() + noises.worm_b.get(worm_p).abs()) as f32;
+
+    vec2(cheese.max(worm), noises.overhang.get(p.to_array()) as f32)
+}
+
+#[inline]
+fn interpolate(corners: [Vec2; 8], t: Vec3) -> Vec2 {
+    let x00 = corners[0].lerp(corners[1], t.x);
+    let x10 = corners[2].lerp(corners[3], t.x);
+    let x01 = corners[4].lerp(corners[5], t.x);
+    let x11 = corners[6].lerp(corners[7], t.x);
+    x00.lerp(x10, t.y).lerp(x01.lerp(x11, t.y), t.z)
+}
+
+#[inline]
+fn lattice_corners(pos: IVec3) -> (IVec3, Vec3) {
+    let base = pos.div_euclid(IVec3::splat(SAMPLE_SPACING));
+    let t = (pos - base * SAMPLE_SPACING).as_vec3() / SAMPLE_SPACING as f32;
+    (base, t)
+}
+
+/// the 3d noise samples of a whole chunk, or any other box of blocks
+pub struct DensityField {
+    // the box's first block
+    min: IVec3,
+    // the first sample and how many there are on each axis
+    base: IVec3,
+    size: IVec3,
+    samples: Vec<Vec2>,
+}
+
+impl DensityField {
+    pub fn new(chunk_pos: ChunkPos, noises: &NoiseFunctions) -> Self {
+        let origin = *chunk_pos.origin();
+        Self::covering(origin, origin + CHUNK_SIZE - 1, noises)
+    }
+
+    /// for the blocks from `min` to `max` (inclusive)
+    pub fn covering(min: IVec3, max: IVec3, noises: &NoiseFunctions) -> Self {
+        let base = min.div_euclid(IVec3::splat(SAMPLE_SPACING));
+        // the samples on both sides of the last block
+        let size = max.div_euclid(IVec3::splat(SAMPLE_SPACING)) - base + 2;
+        let mut samples = Vec::with_capacity(size.element_product() as usize);
+        for z in 0..size.z {
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    samples.push(sample((base + ivec3(x, y, z)) * SAMPLE_SPACING, noises));
+                }
+            }
+        }
+        Self {
+            min,
+            base,
+            size,
+            samples,
+        }
+    }
+
+    /// a block of the chunk, relative to the first block of the box
+    #[inline]
+    pub fn get(&self, local: LocalPos) -> Vec2 {
+        self.at(self.min + *local)
+    }
+
+    /// a block inside the box in world space
+    #[inline]
+    pub fn at(&self, pos: IVec3) -> Vec2 {
+        let (corner, t) = lattice_corners(pos);
+        let corner = corner - self.base;
+        let at = |x: i32, y: i32, z: i32| {
+            let sample = corner + ivec3(x, y, z);
+            self.samples[(sample.x + (sample.y + sample.z * self.size.y) * self.size.x) as usize]
+        };
+        interpolate(
+            [
+                at(0, 0, 0),
+                at(1, 0, 0),
+                at(0, 1, 0),
+                at(1, 1, 0),
+                at(0, 0, 1),
+                at(1, 0, 1),
+                at(0, 1, 1),
+                at(1, 1, 1),
+            ],
+            t,
+        )
+    }
+}
+
+/// same as [`DensityField::get`] but for a single block anywhere in the world
+pub fn density_at(pos: IVec3, noises: &NoiseFunctions) -> Vec2 {
+    let (base, t) = lattice_corners(pos);
+    let at = |x: i32, y: i32, z: i32| sample((base + ivec3(x, y, z)) * SAMPLE_SPACING, noises);
+    interpolate(
+        [
+            at(0, 0, 0),
+            at(1, 0, 0),
+            at(0, 1, 0),
+            at(1, 1, 0),
+            at(0, 0, 1),
+            at(1, 0, 1),
+            at(0, 1, 1),
+            at(1, 1, 1),
+        ],
+        t,
+    )
+}
+
+/// how deep a ravine cuts into this column, 0 if there's none
+#[inline]
+pub fn ravine_depth(column: Vec2, max_y: i32, noises: &NoiseFunctions) -> i32 {
+    if max_y < SEA_LEVEL + 2 {
+        return 0;
+    }
+    let band = noises.ravine.get(column.as_dvec2().into()).abs() as f32;
+    if band >= RAVINE_WIDTH {
+        return 0;
+    }
+    // deepest in the middle, tapering towards the walls
+    (MAX_RAVINE_DEPTH * (1.0 - band / RAVINE_WIDTH).sqrt()) as i32
+}
+
+/// runs the 3d stage (overhangs, caves, ravines) over the heightmap block at `pos`.
+/// `density` is only evaluated close to or below the surface so columns high above
+/// the terrain stay on the cheap path
+#[inline]
+pub fn carve_block(
+    pos: IVec3,
+    max_y: i32,
+    biome: Biome,
+    ravine: i32,
+    density: impl FnOnce() -> Vec2,
+) -> Block {
+    let block = generate_block_at(pos, max_y, biome);
+    let y = pos.y;
+
+    if y > max_y + OVERHANG_DEPTH {
+        return block;
+    }
+    if y < max_y && y >= max_y - ravine {
+        return Block::AIR;
+    }
+
+    let under_ocean = max_y < SEA_LEVEL;
+    let density = density();
+
+    if y >= max_y {
+        // rock sticking out of cliffs
+        let overhang = density.y * OVERHANG_STRENGTH - (y - max_y) as f32;
+        if !under_ocean && overhang > OVERHANG_DEPTH as f32 * 0.5 {
+            return Block::STONE;
+        }
+        return block;
+    }
+
+    if under_ocean && y >= max_y - OCEAN_FLOOR_THICKNESS {
+        return block;
+    }
+    if density.x > 0.0 {
+        return Block::AIR;
+    }
+    if y >= SEA_LEVEL && y >= max_y - OVERHANG_DEPTH {
+        // notches cut into the surface below overhangs
+        let notch = -density.y * OVERHANG_STRENGTH - (max_y - y) as f32;
+        if notch > OVERHANG_DEPTH as f32 * 0.5 {
+            return Block::AIR;
+        }
+    }
+
+    block
+}
+
+/// the generated terrain block at any world position, without structures
+pub fn terrain_block_at(pos: IVec3, noises: &NoiseFunctions) -> Block {
+    let column = pos.xz().as_vec2();
+    let settings = &noises.settings;
+    let (max_y, biome) = terrain_noise(column, noises);
+    let block = if settings.caves {
+        let ravine = ravine_depth(column, max_y, noises);
+        carve_block(pos, max_y, biome, ravine, || density_at(pos, noises))
+    } else {
+        generate_block_at(pos, max_y, biome)
+    };
+    settings.clip_to_bounds(pos.y, block)
+}
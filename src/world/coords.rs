@@ -0,0 +1,213 @@
+use std::{fmt, ops::Deref};
+
+use glam::*;
+
+use crate::{
+    CHUNK_SIZE,
+    utils::{index_to_vec3, vec3_to_index},
+};
+
+// every conversion between these goes through div_euclid/rem_euclid (or floor for floats),
+// plain `/` and `%` round towards zero and put e.g. block -1 into chunk 0
+
+/// a block in world space
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct BlockPos(pub IVec3);
+
+/// a chunk in chunk space, chunk `n` covers blocks `n * CHUNK_SIZE..(n + 1) * CHUNK_SIZE`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct ChunkPos(pub IVec3);
+
+/// a block inside of a chunk, every axis is in `0..CHUNK_SIZE`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct LocalPos(IVec3);
+
+impl BlockPos {
+    #[inline]
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self(ivec3(x, y, z))
+    }
+
+    /// the block containing a point in world space
+    #[inline]
+    pub fn from_world(pos: Vec3) -> Self {
+        Self(pos.floor().as_ivec3())
+    }
+
+    #[inline]
+    pub fn chunk(self) -> ChunkPos {
+        ChunkPos(self.0.div_euclid(IVec3::splat(CHUNK_SIZE)))
+    }
+
+    #[inline]
+    pub fn local(self) -> LocalPos {
+        LocalPos(self.0.rem_euclid(IVec3::splat(CHUNK_SIZE)))
+    }
+
+    #[inline]
+    pub fn split(self) -> (ChunkPos, LocalPos) {
+        (self.chunk(), self.local())
+    }
+
+    #[inline]
+    pub fn offset(self, offset: IVec3) -> Self {
+        Self(self.0 + offset)
+    }
+}
+
+impl ChunkPos {
+    #[inline]
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self(ivec3(x, y, z))
+    }
+
+    /// the chunk containing a point in world space
+    #[inline]
+    pub fn from_world(pos: Vec3) -> Self {
+        BlockPos::from_world(pos).chunk()
+    }
+
+    /// the block at local (0, 0, 0)
+    #[inline]
+    pub fn origin(self) -> BlockPos {
+        BlockPos(self.0 * CHUNK_SIZE)
+    }
+
+    #[inline]
+    pub fn block(self, local: LocalPos) -> BlockPos {
+        BlockPos(self.0 * CHUNK_SIZE + local.0)
+    }
+
+    /// the block at `relative` from this chunk's origin, which may be in a neighbouring chunk
+    #[inline]
+    pub fn relative(self, relative: IVec3) -> BlockPos {
+        self.origin().offset(relative)
+    }
+
+    #[inline]
+    pub fn offset(self, offset: IVec3) -> Self {
+        Self(self.0 + offset)
+    }
+}
+
+impl LocalPos {
+    /// `pos` has to be inside the chunk already
+    #[inline]
+    pub fn new(pos: IVec3) -> Self {
+        debug_assert!(Self::in_bounds(pos), "{pos} is outside of a chunk");
+        Self(pos)
+    }
+
+    #[inline]
+    pub fn try_new(pos: IVec3) -> Option<Self> {
+        Self::in_bounds(pos).then_some(Self(pos))
+    }
+
+    #[inline]
+    pub fn in_bounds(pos: IVec3) -> bool {
+        pos.cmpge(IVec3::ZERO).all() && pos.cmplt(IVec3::splat(CHUNK_SIZE)).all()
+    }
+
+    #[inline]
+    pub const fn index(self) -> usize {
+        vec3_to_index(self.0)
+    }
+
+    #[inline]
+    pub const fn from_index(index: usize) -> Self {
+        Self(index_to_vec3(index))
+    }
+}
+
+macro_rules! impl_ivec3_newtype {
+    ($($ty:ty),*) => {$(
+        impl Deref for $ty {
+            type Target = IVec3;
+
+            #[inline]
+            fn deref(&self) -> &IVec3 {
+                &self.0
+            }
+        }
+
+        impl From<$ty> for IVec3 {
+            #[inline]
+            fn from(pos: $ty) -> IVec3 {
+                pos.0
+            }
+        }
+
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}, {}, {}", self.0.x, self.0.y, self.0.z)
+            }
+        }
+    )*};
+}
+
+impl_ivec3_newtype!(BlockPos, ChunkPos, LocalPos);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_blocks_belong_to_negative_chunks() {
+        assert_eq!(BlockPos::new(-1, -1, -1).chunk(), ChunkPos::new(-1, -1, -1));
+        assert_eq!(
+            BlockPos::new(-1, -1, -1).local(),
+            LocalPos::new(IVec3::splat(CHUNK_SIZE - 1))
+        );
+        assert_eq!(
+            BlockPos::new(-CHUNK_SIZE, 0, 0).chunk(),
+            ChunkPos::new(-1, 0, 0)
+        );
+        assert_eq!(
+            BlockPos::new(-CHUNK_SIZE - 1, 0, 0).chunk(),
+            ChunkPos::new(-2, 0, 0)
+        );
+        assert_eq!(BlockPos::new(0, 0, 0).chunk(), ChunkPos::new(0, 0, 0));
+        assert_eq!(
+            BlockPos::new(CHUNK_SIZE - 1, 0, 0).chunk(),
+            ChunkPos::new(0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn world_positions_round_down() {
+        assert_eq!(
+            BlockPos::from_world(vec3(-0.5, 0.5, -31.9)),
+            BlockPos::new(-1, 0, -32)
+        );
+        assert_eq!(
+            ChunkPos::from_world(vec3(-0.5, 0.5, -32.5)),
+            ChunkPos::new(-1, 0, -2)
+        );
+        assert_eq!(
+            ChunkPos::from_world(vec3(31.9, 32.0, -0.0)),
+            ChunkPos::new(0, 1, 0)
+        );
+    }
+
+    #[test]
+    fn split_and_join_round_trip() {
+        for x in -70..70 {
+            for y in [-33, -32, -1, 0, 31, 32] {
+                let pos = BlockPos::new(x, y, -x);
+                let (chunk, local) = pos.split();
+                assert!(LocalPos::in_bounds(*local));
+                assert_eq!(chunk.block(local), pos);
+                assert_eq!(chunk.relative(*local), pos);
+            }
+        }
+    }
+
+    #[test]
+    fn local_index_round_trip() {
+        for index in [0, 1, 31, 32, 1023, 1024, 32767] {
+            assert_eq!(LocalPos::from_index(index).index(), index);
+        }
+        assert_eq!(LocalPos::try_new(ivec3(-1, 0, 0)), None);
+        assert_eq!(LocalPos::try_new(ivec3(0, CHUNK_SIZE, 0)), None);
+    }
+}
@@ -0,0 +1,258 @@
+use bevy_ecs::system::SystemParam;
+
+use crate::{
+    ecs::*,
+    world::{
+        WorldData,
+        block::Block,
+        coords::BlockPos,
+        events::{BlockChangeCause, BlockChanged},
+        history::EditHistory,
+        interaction::{set_blocks, set_blocks_with_fluid},
+        schematic::Schematic,
+        ticks::BlockUpdates,
+    },
+};
+
+/// the most blocks the build tools edit at once, bigger selections are refused
+pub const MAX_REGION_VOLUME: u64 = 1 << 21; // 128^3
+
+/// A set of blocks to edit, in world space.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Region {
+    // both corners are part of the box, in any order
+    Box(BlockPos, BlockPos),
+    Sphere(BlockPos, i32),
+}
+
+impl Region {
+    /// the smallest box around the region, min and max corner
+    pub fn bounds(self) -> (IVec3, IVec3) {
+        match self {
+            Region::Box(a, b) => (a.min(*b), a.max(*b)),
+            Region::Sphere(center, radius) => (*center - radius.abs(), *center + radius.abs()),
+        }
+    }
+
+    /// how many blocks the bounds cover
+    pub fn volume(self) -> u64 {
+        let (min, max) = self.bounds();
+        (max.as_i64vec3() - min.as_i64vec3() + 1)
+            .to_array()
+            .iter()
+            .map(|&side| side as u64)
+            .product()
+    }
+
+    #[inline]
+    pub fn contains(self, pos: BlockPos) -> bool {
+        match self {
+            Region::Box(..) => {
+                let (min, max) = self.bounds();
+                pos.cmpge(min).all() && pos.cmple(max).all()
+            }
+            // + radius rounds the sphere out a bit so it doesn't end in single-block bumps
+            Region::Sphere(center, radius) => {
+                (*pos - *center).length_squared() <= radius * radius + radius.abs()
+            }
+        }
+    }
+
+    /// every block of the region, x first, then z, then y
+    pub fn positions(self) -> impl Iterator<Item = BlockPos> {
+        let (min, max) = self.bounds();
+        (min.y..=max.y)
+            .flat_map(move |y| {
+                (min.z..=max.z)
+                    .flat_map(move |z| (min.x..=max.x).map(move |x| BlockPos::new(x, y, z)))
+            })
+            .filter(move |pos| self.contains(*pos))
+    }
+}
+
+/// what [`WorldEditor::copy`] copied last
+#[derive(Resource, Default)]
+pub struct Clipboard(pub Option<Schematic>);
+
+/// Edits many blocks at once. Every call relights once and remeshes each chunk it touched
+/// once, blocks in unloaded chunks are left alone. Methods return how many blocks changed.
+#[derive(SystemParam)]
+pub struct WorldEditor<'w> {
+    world_data: Res<'w, WorldData>,
+    updates: ResMut<'w, BlockUpdates>,
+    block_changed: EventWriter<'w, BlockChanged>,
+    clipboard: ResMut<'w, Clipboard>,
+    history: ResMut<'w, EditHistory>,
+}
+
+impl WorldEditor<'_> {
+    pub fn set_blocks(
+        &mut self,
+        blocks: impl IntoIterator<Item = (BlockPos, Block)>,
+        cause: BlockChangeCause,
+    ) -> usize {
+        set_blocks(
+            &mut self.world_data.chunks.write().unwrap(),
+            &mut self.updates,
+            &mut self.block_changed,
+            blocks,
+            cause,
+        )
+    }
+
+    pub fn fill(&mut self, region: Region, block: Block) -> usize {
+        self.set_blocks(
+            region.positions().map(|pos| (pos, block)),
+            BlockChangeCause::Editor,
+        )
+    }
+
+    /// only changes the blocks that are `from`
+    pub fn replace(&mut self, region: Region, from: Block, to: Block) -> usize {
+        let positions = {
+            let chunks = self.world_data.chunks.read().unwrap();
+            region
+                .positions()
+                .filter(|pos| {
+                    let (chunk_pos, local) = pos.split();
+                    chunks
+                        .get(&chunk_pos)
+                        .is_some_and(|chunk| chunk.blocks.get(local) == from)
+                })
+                .collect::<Vec<_>>()
+        };
+        self.set_blocks(
+            positions.into_iter().map(|pos| (pos, to)),
+            BlockChangeCause::Editor,
+        )
+    }
+
+    /// Copies the loaded blocks of a region into the clipboard, replacing what was in it.
+    /// Returns how many were copied.
+    pub fn copy(&mut self, region: Region) -> usize {
+        let (min, max) = region.bounds();
+        let mut schematic = Schematic::new(max - min + 1);
+        let mut copied = 0;
+        let chunks = self.world_data.chunks.read().unwrap();
+        for pos in region.positions() {
+            let (chunk_pos, local) = pos.split();
+            if let Some(chunk) = chunks.get(&chunk_pos) {
+                schematic.set(*pos - min, Some(chunk.blocks.get(local)));
+                copied += 1;
+            }
+        }
+        self.clipboard.0 = Some(schematic);
+        copied
+    }
+
+    /// Pastes the clipboard with its min corner at `at`, after turning it clockwise (seen
+    /// from above) around the y axis `quarter_turns` times. Air is pasted too unless
+    /// `skip_air` is set. Clipboards bigger than [`MAX_REGION_VOLUME`] (loaded from a file)
+    /// aren't pasted.
+    pub fn paste(&mut self, at: BlockPos, quarter_turns: u32, skip_air: bool) -> usize {
+        let Some(schematic) = &self.clipboard.0 else {
+            return 0;
+        };
+        let volume = schematic.blocks.len() as u64;
+        if volume > MAX_REGION_VOLUME {
+            eprintln!(
+                "the clipboard is too big to paste ({volume} blocks, at most {MAX_REGION_VOLUME})"
+            );
+            return 0;
+        }
+        let blocks = schematic
+            .rotated(quarter_turns)
+            .iter()
+            .filter(|(_, block)| !(skip_air && block.is_air()))
+            .map(|(offset, block)| (at.offset(offset), block))
+            .collect::<Vec<_>>();
+        self.set_blocks(blocks, BlockChangeCause::Editor)
+    }
+
+    /// reverts the last recorded transaction, returns how many blocks changed back
+    pub fn undo(&mut self) -> usize {
+        let Some(transaction) = self.history.undo() else {
+            return 0;
+        };
+        // backwards, so a block changed twice ends up as it was before the first change
+        let blocks = transaction
+            .iter()
+            .rev()
+            .map(|edit| (edit.pos, edit.old, edit.old_fluid))
+            .collect::<Vec<_>>();
+        self.set_blocks_with_fluid(blocks)
+    }
+
+    pub fn redo(&mut self) -> usize {
+        let Some(transaction) = self.history.redo() else {
+            return 0;
+        };
+        let blocks = transaction
+            .iter()
+            .map(|edit| (edit.pos, edit.new, 0))
+            .collect::<Vec<_>>();
+        self.set_blocks_with_fluid(blocks)
+    }
+
+    fn set_blocks_with_fluid(&mut self, blocks: Vec<(BlockPos, Block, u8)>) -> usize {
+        set_blocks_with_fluid(
+            &mut self.world_data.chunks.write().unwrap(),
+            &mut self.updates,
+            &mut self.block_changed,
+            blocks,
+            BlockChangeCause::History,
+        )
+    }
+
+    /// the clipboard, to save it or put a loaded schematic in it
+    pub fn clipboard(&mut self) -> &mut Option<Schematic> {
+        &mut self.clipboard.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boxes_contain_both_corners_in_any_order() {
+        let region = Region::Box(BlockPos::new(2, -1, 5), BlockPos::new(-1, 1, 3));
+        assert_eq!(region.bounds(), (ivec3(-1, -1, 3), ivec3(2, 1, 5)));
+        assert_eq!(region.volume(), 4 * 3 * 3);
+        assert!(region.contains(BlockPos::new(2, -1, 5)));
+        assert!(region.contains(BlockPos::new(-1, 1, 3)));
+        assert!(!region.contains(BlockPos::new(3, 0, 4)));
+        assert!(!region.contains(BlockPos::new(0, 2, 4)));
+
+        let positions = region.positions().collect::<Vec<_>>();
+        assert_eq!(positions.len(), 36);
+        assert_eq!(positions[0], BlockPos::new(-1, -1, 3));
+        assert_eq!(positions[1], BlockPos::new(0, -1, 3));
+        assert_eq!(positions[4], BlockPos::new(-1, -1, 4));
+        assert_eq!(positions[35], BlockPos::new(2, 1, 5));
+    }
+
+    #[test]
+    fn spheres_are_symmetric() {
+        let center = BlockPos::new(10, 20, 30);
+        let region = Region::Sphere(center, 3);
+        assert_eq!(region.volume(), 7 * 7 * 7);
+        assert!(region.contains(center));
+        assert!(region.contains(BlockPos::new(13, 20, 30)));
+        assert!(!region.contains(BlockPos::new(14, 20, 30)));
+        assert!(!region.contains(BlockPos::new(13, 23, 30)));
+
+        let positions = region.positions().collect::<Vec<_>>();
+        assert!(positions.iter().all(|pos| region.contains(*pos)));
+        for pos in &positions {
+            let mirrored = BlockPos(*center * 2 - **pos);
+            assert!(positions.contains(&mirrored));
+        }
+    }
+
+    #[test]
+    fn volume_does_not_overflow() {
+        let region = Region::Box(BlockPos::new(i32::MIN, 0, 0), BlockPos::new(i32::MAX, 0, 0));
+        assert_eq!(region.volume(), 1 << 32);
+    }
+}
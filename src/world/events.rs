@@ -0,0 +1,60 @@
+use crate::{
+    ecs::*,
+    world::{
+        block::Block,
+        coords::{BlockPos, ChunkPos},
+    },
+};
+
+/// What changed a block, so listeners can tell the player's edits apart from the world
+/// changing on its own.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlockChangeCause {
+    Player,
+    // WorldEditor, fills, pastes and so on
+    Editor,
+    // neighbour updates, scheduled and random ticks (falling sand, water, grass...)
+    BlockUpdate,
+    // undo and redo, which aren't recorded in the history again
+    History,
+}
+
+/// Sent after a loaded block was replaced by a different one, lighting is already updated by
+/// then but the chunk may not be remeshed yet. Changes that only touch the fluid level of a
+/// block aren't reported.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct BlockChanged {
+    pub pos: BlockPos,
+    pub old: Block,
+    // the fluid level the old block had, see world::fluid
+    pub old_fluid: u8,
+    pub new: Block,
+    pub cause: BlockChangeCause,
+}
+
+/// a chunk was generated or read from the save and is now part of the world
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ChunkLoaded {
+    pub pos: ChunkPos,
+    pub entity: Entity,
+    pub from_save: bool,
+}
+
+/// a loaded chunk was dropped, modified chunks have been handed to the save
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ChunkUnloaded {
+    pub pos: ChunkPos,
+    pub modified: bool,
+}
+
+// events stay readable for the frame they were sent in and the one after it, which also covers
+// everything sent from FixedUpdate
+pub fn update_events(
+    mut block_changed: ResMut<Events<BlockChanged>>,
+    mut chunk_loaded: ResMut<Events<ChunkLoaded>>,
+    mut chunk_unloaded: ResMut<Events<ChunkUnloaded>>,
+) {
+    block_changed.update();
+    chunk_loaded.update();
+    chunk_unloaded.update();
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use crate::{
+    ecs::*,
+    world::{
+        block::Block,
+        coords::{BlockPos, ChunkPos},
+        mesher::Chunk,
+        ticks::TickContext,
+    },
+};
+
+// fluid levels are stored per block next to the block id and only mean something for water
+pub const SOURCE: u8 = 0;
+// flowing water is one level lower for every block it spreads, past this it dries up
+pub const MAX_LEVEL: u8 = 7;
+// set on water that is falling down, it spreads like a source once it lands
+pub const FALLING: u8 = 8;
+
+// fixed updates between a block changing and the water next to it reacting, at 64 per second
+// water moves about 7 blocks a second
+const FLOW_DELAY: u64 = 8;
+
+const HORIZONTAL: [IVec3; 4] = [IVec3::NEG_X, IVec3::X, IVec3::NEG_Z, IVec3::Z];
+
+/// how far the surface of water with this level sits below the top of its block, in eighths
+#[inline]
+pub fn surface_drop(level: u8) -> u8 {
+    if level & FALLING == 0 { level } else { 0 }
+}
+
+/// neighbour update of water and air, water only moves once the flow delay is over
+pub fn schedule_flow(ctx: &mut TickContext, pos: BlockPos, _block: Block) {
+    let Some(current) = ctx.fluid(pos) else {
+        return;
+    };
+    // dropped while a neighbour isn't loaded, the chunk border stays as it is
+    if next_state(ctx.chunks, pos, current).is_some_and(|next| next != current) {
+        ctx.schedule(pos, FLOW_DELAY);
+    }
+}
+
+/// scheduled tick of water and air
+pub fn flow(ctx: &mut TickContext, pos: BlockPos, _block: Block) {
+    let Some(current) = ctx.fluid(pos) else {
+        return;
+    };
+    if let Some(next) = next_state(ctx.chunks, pos, current)
+        && next != current
+    {
+        // the neighbour updates this queues carry the flow on
+        ctx.set_fluid(pos, next.0, next.1);
+    }
+}
+
+#[inline]
+fn fluid_at(chunks: &HashMap<ChunkPos, Chunk>, pos: BlockPos) -> Option<(Block, u8)> {
+    let (chunk_pos, local) = pos.split();
+    let chunk = chunks.get(&chunk_pos)?;
+    Some((chunk.blocks.get(local), chunk.fluid.get(local)))
+}
+
+// whether water on top of this spreads out instead of falling through
+#[inline]
+fn stops_falling((block, level): (Block, u8)) -> bool {
+    match block {
+        Block::AIR => false,
+        Block::WATER => level == SOURCE,
+        _ => true,
+    }
+}
+
+// what a block turns into given its neighbours, None if any of them isn't loaded
+fn next_state(
+    chunks: &HashMap<ChunkPos, Chunk>,
+    pos: BlockPos,
+    (block, level): (Block, u8),
+) -> Option<(Block, u8)> {
+    // sources never change and only air can be flooded
+    if (block != Block::AIR && block != Block::WATER) || (block == Block::WATER && level == SOURCE)
+    {
+        return Some((block, level));
+    }
+
+    let above = fluid_at(chunks, pos.offset(IVec3::Y))?;
+    let below = fluid_at(chunks, pos.offset(IVec3::NEG_Y))?;
+
+    let mut sources = 0;
+    let mut lowest: Option<u8> = None;
+    for offset in HORIZONTAL {
+        let neighbor = pos.offset(offset);
+        let (block, level) = fluid_at(chunks, neighbor)?;
+        if block != Block::WATER {
+            continue;
+        }
+        if level == SOURCE {
+            sources += 1;
+        }
+        // flowing water only spreads sideways once it can't fall any further
+        if level == SOURCE || stops_falling(fluid_at(chunks, neighbor.offset(IVec3::NEG_Y))?) {
+            let level = if level & FALLING != 0 { SOURCE } else { level };
+            lowest = Some(lowest.map_or(level, |lowest| lowest.min(level)));
+        }
+    }
+
+    Some(if above.0 == Block::WATER {
+        (Block::WATER, FALLING)
+    } else if sources >= 2 && stops_falling(below) {
+        // refills gaps in oceans and lakes
+        (Block::WATER, SOURCE)
+    } else {
+        match lowest {
+            Some(level) if level < MAX_LEVEL => (Block::WATER, level + 1),
+            _ => (Block::AIR, 0),
+        }
+    })
+}
@@ -1,13 +1,22 @@
+use std::{cell::OnceCell, collections::HashSet};
+
 use bevy_tasks::{AsyncComputeTaskPool, futures_lite::future};
 use rayon::slice::ParallelSliceMut;
 
 use crate::{
     CHUNK_SIZE,
     ecs::*,
-    utils::{generate_block_at, vec3_to_index},
+    utils::generate_block_at,
     world::{
-        ChunkMarker, ComputeChunk, ComputeChunkMesh, NoiseFunctions, WorldData,
-        mesher::{Chunk, ChunkMesh, terrain_noise},
+        ChunkLoadSettings, ChunkMarker, ComputeChunk, ComputeChunkMesh, NoiseFunctions, WorldData,
+        biome::terrain_noise,
+        caves::{DensityField, carve_block, ravine_depth},
+        coords::{ChunkPos, LocalPos},
+        events::{ChunkLoaded, ChunkUnloaded},
+        interaction::update_chunks,
+        light::{LightEngine, StitchSeeds, light_chunk},
+        mesher::{Border, Chunk, ChunkMesh, MeshingMode},
+        structures::place_structures,
     },
 };
 
@@ -15,18 +24,29 @@ pub fn handle_chunk_gen(
     mut commands: Commands,
     world_data: Res<WorldData>,
     noises: Res<NoiseFunctions>,
+    load_settings: Res<ChunkLoadSettings>,
     player: Single<&Transform, With<Camera3d>>,
 ) {
     let thread_pool = AsyncComputeTaskPool::get();
-    let render_distance = 8;
 
     let mut chunks_to_load = Vec::new();
-    let player_chunk = player.translation.as_ivec3() / CHUNK_SIZE;
-
-    for chunk_y in (player_chunk.y - render_distance).max(0)..(player_chunk.y + render_distance) {
-        for chunk_z in (player_chunk.z - render_distance)..(player_chunk.z + render_distance) {
-            for chunk_x in (player_chunk.x - render_distance)..(player_chunk.x + render_distance) {
-                let pos = ivec3(chunk_x, chunk_y, chunk_z);
+    let player_chunk = ChunkPos::from_world(player.translation);
+    let (horizontal, vertical) = (
+        load_settings.horizontal_radius,
+        load_settings.vertical_radius,
+    );
+
+    let (min_chunk_y, max_chunk_y) = noises.settings.chunk_y_range();
+    let lowest = player_chunk.y.saturating_sub(vertical).max(min_chunk_y);
+    let highest = player_chunk.y.saturating_add(vertical).min(max_chunk_y);
+
+    for chunk_y in lowest..=highest {
+        for chunk_z in player_chunk.z - horizontal..=player_chunk.z + horizontal {
+            for chunk_x in player_chunk.x - horizontal..=player_chunk.x + horizontal {
+                let pos = ChunkPos::new(chunk_x, chunk_y, chunk_z);
+                if !load_settings.should_load(player_chunk, pos) {
+                    continue;
+                }
 
                 if let Ok(guard) = world_data.chunks.read() {
                     if guard.contains_key(&pos) {
@@ -50,34 +70,64 @@ pub fn handle_chunk_gen(
 
                 chunks_to_load.push(pos);
 
-                // let chunks = world_data.chunks.clone();
-                // let saved_chunks = world_data.saved_chunks.clone();
+                let chunks = world_data.chunks.clone();
+                let save = world_data.save.clone();
                 let noises = noises.clone();
 
+                let open_top = pos.y >= highest;
                 let task = thread_pool.spawn(async move {
+                    let with_seeds = |chunk: Chunk| {
+                        let seeds = StitchSeeds::gather(&chunk, &chunks.read().unwrap());
+                        (chunk, seeds)
+                    };
+                    // saved light already has whatever was above the chunk back then, the
+                    // heightmap knows nothing about shafts dug or roofs built since
+                    let saved = match save.load_chunk(pos) {
+                        Some((chunk, true)) => return with_seeds(chunk),
+                        saved => saved.map(|(chunk, _)| chunk),
+                    };
+
+                    // row major, z is the row
+                    let columns = (0..CHUNK_SIZE * CHUNK_SIZE)
+                        .map(|i| {
+                            let column = ivec2(i % CHUNK_SIZE, i / CHUNK_SIZE);
+                            terrain_noise((pos.origin().xz() + column).as_vec2(), &noises)
+                        })
+                        .collect::<Vec<_>>();
+                    let surface = columns.iter().map(|column| column.0).collect::<Vec<_>>();
+                    // nothing is loaded above the top of the load range either, so peaks up
+                    // there aren't left in the dark
+                    let lit = |chunk| with_seeds(light_chunk(chunk, &surface, open_top));
+
+                    if let Some(chunk) = saved {
+                        return lit(chunk);
+                    }
+
                     let mut chunk = Chunk::new(pos);
+                    // only sampled once a column actually reaches into the 3d stage
+                    let density = OnceCell::new();
 
                     for rela_z in 0..CHUNK_SIZE {
                         for rela_x in 0..CHUNK_SIZE {
-                            let hpos = vec2(
-                                (rela_x + pos.x * CHUNK_SIZE) as f32,
-                                (rela_z + pos.z * CHUNK_SIZE) as f32,
-                            );
-                            let (max_y, _biome) = terrain_noise(hpos, &noises);
+                            let hpos = (pos.origin().xz() + ivec2(rela_x, rela_z)).as_vec2();
+                            let (max_y, biome) = columns[(rela_z * CHUNK_SIZE + rela_x) as usize];
+                            let ravine = ravine_depth(hpos, max_y, &noises);
 
                             for rela_y in 0..CHUNK_SIZE {
-                                *unsafe {
-                                    chunk.blocks.get_unchecked_mut(vec3_to_index(ivec3(
-                                        rela_x, rela_y, rela_z,
-                                    )))
-                                } = generate_block_at(
-                                    ivec3(
-                                        hpos.x as i32,
-                                        rela_y + pos.y * CHUNK_SIZE,
-                                        hpos.y as i32,
-                                    ),
-                                    max_y,
-                                );
+                                let local = LocalPos::new(ivec3(rela_x, rela_y, rela_z));
+                                let world_pos = *pos.block(local);
+                                let block = if noises.settings.caves {
+                                    carve_block(world_pos, max_y, biome, ravine, || {
+                                        density
+                                            .get_or_init(|| DensityField::new(pos, &noises))
+                                            .get(local)
+                                    })
+                                } else {
+                                    generate_block_at(world_pos, max_y, biome)
+                                };
+                                chunk
+                                    .blocks
+                                    .set(local, noises.settings.clip_to_bounds(world_pos.y, block));
 
                                 // if rela_y == max_y
                                 //     && max_y > SEA_LEVEL
@@ -94,56 +144,12 @@ pub fn handle_chunk_gen(
                                 //     ));
                                 // }
                             }
-
-                            // let tree_probabilty = noise(noises.tree, pos);
-
-                            // // TODO: clean up
-                            // if tree_probabilty > 0.85 && max_y < 90 && max_y > SEA_LEVEL + 2 {
-                            //     for (y, tree_layer) in TREE_OBJECT.iter().enumerate() {
-                            //         for (z, tree_row) in tree_layer.iter().enumerate() {
-                            //             for (x, &block) in tree_row.iter().enumerate() {
-                            //                 let mut pos =
-                            //                     ivec3(3 + x as i32, y as i32, 3 + z as i32);
-                            //                 let (local_max_y, _) = terrain_noise(
-                            //                     (chunk.pos * CHUNK_SIZE + pos).as_vec3().xz(),
-                            //                     &noises,
-                            //                 );
-
-                            //                 pos.y += local_max_y;
-
-                            //                 if (0..CHUNK_SIZE).contains(&pos.x)
-                            //                     && (0..CHUNK_HEIGHT).contains(&pos.y)
-                            //                     && (0..CHUNK_SIZE).contains(&pos.z)
-                            //                 {
-                            //                     chunk.blocks[vec3_to_index(pos)] = block;
-                            //                 } else if let Some(relative_chunk) =
-                            //                     chunk.get_relative_chunk(pos)
-                            //                     && let Some(target) =
-                            //                         chunks.write().unwrap().get_mut(&relative_chunk)
-                            //                 {
-                            //                     let block_index = vec3_to_index(
-                            //                         pos - relative_chunk * CHUNK_SIZE,
-                            //                     );
-                            //                     if block_index < target.blocks.len() {
-                            //                         target.blocks[block_index] = block;
-                            //                     }
-                            //                 }
-                            //             }
-                            //         }
-                            //     }
-                            // }
                         }
                     }
 
-                    // if let Some(saved_chunks) = &saved_chunks
-                    //     && let Some(saved_chunk) = saved_chunks.read().unwrap().get(&pos)
-                    // {
-                    //     for (&pos, &block) in &saved_chunk.blocks {
-                    //         chunk.blocks[vec3_to_index(pos)] = block;
-                    //     }
-                    //     // chunk.entities = saved_chunk.entities.clone();
-                    // }
-                    chunk
+                    place_structures(&mut chunk, &noises);
+                    chunk.blocks.compact();
+                    lit(chunk)
                 });
                 commands.spawn(ComputeChunk(task, pos));
             }
@@ -155,21 +161,29 @@ pub fn handle_mesh_gen(
     mut commands: Commands,
     world_data: Res<WorldData>,
     noises: Res<NoiseFunctions>,
+    mode: Res<MeshingMode>,
     query: Query<(Entity, &Transform), Added<ChunkMarker>>,
 ) {
     let thread_pool = AsyncComputeTaskPool::get();
 
     for (entity, transform) in query {
-        let pos = transform.translation.as_ivec3() / CHUNK_SIZE;
+        let pos = ChunkPos::from_world(transform.translation);
 
         let chunks = world_data.chunks.clone();
         let noises = noises.clone();
+        let mode = *mode;
 
         let task = thread_pool.spawn(async move {
-            let guard = chunks.read().unwrap();
             #[cfg(feature = "profile")]
             let instant = std::time::Instant::now();
-            let mesh = ChunkMesh::build(guard.get(&pos)?, &guard, &noises);
+            // copied out so edits and loading don't wait for the mesh
+            let (chunk, mut border) = {
+                let guard = chunks.read().unwrap();
+                let chunk = guard.get(&pos)?.clone();
+                (chunk, Border::copy(pos, &guard))
+            };
+            border.generate(pos, &noises);
+            let mesh = ChunkMesh::build(&chunk, &border, mode);
             #[cfg(feature = "profile")]
             println!("Generated chunk in {:?}", instant.elapsed());
             mesh
@@ -181,13 +195,38 @@ pub fn handle_mesh_gen(
     }
 }
 
+/// remeshes everything that's loaded when the meshing mode is switched
+pub fn handle_meshing_mode(
+    mut commands: Commands,
+    mode: Res<MeshingMode>,
+    query: Query<Entity, With<ChunkMarker>>,
+) {
+    if !mode.is_changed() || mode.is_added() {
+        return;
+    }
+    println!("{:?} meshing", *mode);
+    for entity in query {
+        commands
+            .entity(entity)
+            .try_remove::<ChunkMarker>()
+            .try_insert(ChunkMarker);
+    }
+}
+
 #[allow(clippy::type_complexity)]
 pub fn handle_chunk_despawn(
     mut commands: Commands,
     mut meshes: ResMut<Meshes>,
     world_data: Res<WorldData>,
+    load_settings: Res<ChunkLoadSettings>,
+    mut chunk_unloaded: EventWriter<ChunkUnloaded>,
     query: Query<
-        (Entity, &Transform, Option<&Mesh3d>),
+        (
+            Entity,
+            Option<&Transform>,
+            Option<&ComputeChunk>,
+            Option<&Mesh3d>,
+        ),
         Or<(
             With<ChunkMarker>,
             With<ComputeChunkMesh>,
@@ -196,22 +235,22 @@ pub fn handle_chunk_despawn(
     >,
     player: Single<&Transform, With<Camera3d>>,
 ) {
-    let player_chunk = player.translation.as_ivec3() / CHUNK_SIZE;
-    let render_distance = 8;
+    let player_chunk = ChunkPos::from_world(player.translation);
 
     let mut chunks = world_data.chunks.write().unwrap();
     let mut loading_chunks = world_data.loading_chunks.write().unwrap();
-
-    for (entity, transform, mesh_id) in query {
-        let chunk_pos = transform.translation.as_ivec3() / CHUNK_SIZE;
-
-        if (chunk_pos.x + render_distance < player_chunk.x)
-            || (chunk_pos.x - render_distance > player_chunk.x)
-            || (chunk_pos.y + render_distance < player_chunk.y)
-            || (chunk_pos.y - render_distance > player_chunk.y)
-            || (chunk_pos.z + render_distance < player_chunk.z)
-            || (chunk_pos.z - render_distance > player_chunk.z)
-        {
+    let save = &world_data.save;
+
+    for (entity, transform, compute_chunk, mesh_id) in query {
+        // chunks that are still generating don't have a transform yet
+        let Some(chunk_pos) = compute_chunk
+            .map(|task| task.1)
+            .or_else(|| transform.map(|t| ChunkPos::from_world(t.translation)))
+        else {
+            continue;
+        };
+
+        if load_settings.should_unload(player_chunk, chunk_pos) {
             {
                 // if let Some(chunk_entities) = chunks.get(&pos) {
                 //     for (entity, _) in &chunk_entities.entities {
@@ -222,26 +261,37 @@ pub fn handle_chunk_despawn(
                 // }
             }
             if let Some(mesh_id) = mesh_id {
-                meshes.0.remove(&mesh_id.0);
+                meshes.remove(*mesh_id);
             }
             commands.entity(entity).try_despawn();
 
             loading_chunks.remove(&chunk_pos);
-            chunks.remove(&chunk_pos);
+            if let Some(chunk) = chunks.remove(&chunk_pos) {
+                chunk_unloaded.write(ChunkUnloaded {
+                    pos: chunk_pos,
+                    modified: chunk.modified,
+                });
+                if chunk.modified {
+                    save.store_chunk(chunk);
+                }
+            }
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn process_tasks(
     mut commands: Commands,
     mut meshes: ResMut<Meshes>,
     player: Single<&Transform, With<Camera3d>>,
-    mesh_tasks: Query<(Entity, &mut ComputeChunkMesh)>,
+    mesh_tasks: Query<(Entity, &mut ComputeChunkMesh, Option<&Mesh3d>)>,
     spawn_tasks: Query<(Entity, &mut ComputeChunk)>,
+    loaded: Query<(Entity, &Transform), With<ChunkMarker>>,
     world_data: Res<WorldData>,
+    mut chunk_loaded: EventWriter<ChunkLoaded>,
 ) {
     // GENERATING CHUNKS
-    let pt = player.translation.as_ivec3().with_y(0) / CHUNK_SIZE;
+    let pt = ChunkPos::from_world(player.translation).with_y(0);
 
     let mut tasks = spawn_tasks.into_iter().collect::<Vec<_>>();
     tasks.par_sort_by_cached_key(|(_, x)| x.1.distance_squared(pt));
@@ -252,9 +302,10 @@ pub fn process_tasks(
     //     .as_ref()
     //     .map(|saved_chunks| saved_chunks.write().unwrap());
     let mut loading_chunks = world_data.loading_chunks.write().unwrap();
+    let mut relit = HashSet::new();
 
     for (entity, mut compute_task) in tasks {
-        if let Some(chunk) = future::block_on(future::poll_once(&mut compute_task.0)) {
+        if let Some((chunk, seeds)) = future::block_on(future::poll_once(&mut compute_task.0)) {
             // if let Some(saved_chunks) = &mut saved_chunks {
             //     saved_chunks
             //         .entry(chunk.pos)
@@ -288,28 +339,61 @@ pub fn process_tasks(
                         Vec3::ZERO,
                         vec3(CHUNK_SIZE as f32, CHUNK_SIZE as f32, CHUNK_SIZE as f32),
                     ),
-                    Transform::from_translation((chunk.pos * CHUNK_SIZE).as_vec3()),
+                    Transform::from_translation(chunk.pos.origin().as_vec3()),
                 ))
                 .try_remove::<ComputeChunk>();
 
-            loading_chunks.remove(&chunk.pos);
-            chunks.insert(chunk.pos, chunk);
+            let pos = chunk.pos;
+            // only chunks that differ from worldgen are ever saved
+            let from_save = chunk.modified;
+            loading_chunks.remove(&pos);
+            chunks.insert(pos, chunk);
+
+            // the new chunk is meshed anyway, only its neighbours need to be told
+            let mut light = LightEngine::new(&mut chunks);
+            light.stitch(pos, seeds);
+            relit.extend(light.changed.into_iter().filter(|changed| *changed != pos));
+            chunk_loaded.write(ChunkLoaded {
+                pos,
+                entity,
+                from_save,
+            });
         }
     }
+    if !relit.is_empty() {
+        update_chunks(
+            &mut commands,
+            loaded.iter().collect(),
+            relit.into_iter().collect(),
+        );
+    }
 
     // GENERATING MESHES
 
     let mut tasks = mesh_tasks.into_iter().collect::<Vec<_>>();
-    tasks.par_sort_by_cached_key(|(_, x)| x.1.distance_squared(pt));
+    tasks.par_sort_by_cached_key(|(_, x, _)| x.1.distance_squared(pt));
 
-    for (entity, mut compute_task) in tasks {
+    for (entity, mut compute_task, mesh_id) in tasks {
         if let Some(result) = future::block_on(future::poll_once(&mut compute_task.0)) {
             commands.entity(entity).try_remove::<ComputeChunkMesh>();
+            // unloaded while it was being meshed, its entity is going away
+            if !chunks.contains_key(&compute_task.1) {
+                continue;
+            }
 
-            if let Some(mesh_data) = result {
-                commands
-                    .entity(entity)
-                    .try_insert((meshes.add(mesh_data), MeshMaterial(0)));
+            // remeshed chunks keep their mesh id, chunks that ended up empty lose it
+            match (result, mesh_id) {
+                (Some(mesh_data), Some(&mesh_id)) => meshes.replace(mesh_id, mesh_data),
+                (Some(mesh_data), None) => {
+                    commands
+                        .entity(entity)
+                        .try_insert((meshes.add(mesh_data), MeshMaterial(0)));
+                }
+                (None, Some(&mesh_id)) => {
+                    meshes.remove(mesh_id);
+                    commands.entity(entity).try_remove::<Mesh3d>();
+                }
+                (None, None) => {}
             }
         }
     }
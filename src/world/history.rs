@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+
+use crate::{
+    ecs::*,
+    world::{
+        block::Block,
+        coords::BlockPos,
+        editor::MAX_REGION_VOLUME,
+        events::{BlockChangeCause, BlockChanged},
+    },
+};
+
+// edits kept for undo and redo together (16 bytes each, so 64 MiB), the oldest transactions
+// are forgotten first. room for the biggest edit the editor allows and as much before it
+const MAX_EDITS: usize = 2 * MAX_REGION_VOLUME as usize;
+
+#[derive(Clone, Copy, Debug)]
+pub struct BlockEdit {
+    pub pos: BlockPos,
+    pub old: Block,
+    pub old_fluid: u8,
+    pub new: Block,
+}
+
+/// The player's and the editor's block changes, one transaction per frame so a fill or paste
+/// is undone as a whole. Changes the world makes on its own (falling sand, flowing water) are
+/// never recorded.
+#[derive(Resource, Default)]
+pub struct EditHistory {
+    undo: VecDeque<Vec<BlockEdit>>,
+    redo: Vec<Vec<BlockEdit>>,
+    edits: usize,
+}
+
+impl EditHistory {
+    /// a new transaction, anything that was undone can't be redone anymore
+    pub fn record(&mut self, transaction: Vec<BlockEdit>) {
+        if transaction.is_empty() {
+            return;
+        }
+        for undone in self.redo.drain(..) {
+            self.edits -= undone.len();
+        }
+
+        self.edits += transaction.len();
+        self.undo.push_back(transaction);
+        // the newest transaction is kept even when it's over the budget on its own
+        while self.edits > MAX_EDITS && self.undo.len() > 1 {
+            let oldest = self.undo.pop_front().unwrap();
+            self.edits -= oldest.len();
+        }
+    }
+
+    /// the last transaction, moved over to the redo stack
+    pub fn undo(&mut self) -> Option<&[BlockEdit]> {
+        let transaction = self.undo.pop_back()?;
+        self.redo.push(transaction);
+        self.redo.last().map(Vec::as_slice)
+    }
+
+    /// the last undone transaction, moved back to the undo stack
+    pub fn redo(&mut self) -> Option<&[BlockEdit]> {
+        let transaction = self.redo.pop()?;
+        self.undo.push_back(transaction);
+        self.undo.back().map(Vec::as_slice)
+    }
+}
+
+pub fn record_edits(
+    mut history: ResMut<EditHistory>,
+    mut block_changed: EventReader<BlockChanged>,
+) {
+    let transaction = block_changed
+        .read()
+        .filter(|event| {
+            matches!(
+                event.cause,
+                BlockChangeCause::Player | BlockChangeCause::Editor
+            )
+        })
+        .map(|event| BlockEdit {
+            pos: event.pos,
+            old: event.old,
+            old_fluid: event.old_fluid,
+            new: event.new,
+        })
+        .collect();
+    history.record(transaction);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(len: usize) -> Vec<BlockEdit> {
+        let edit = BlockEdit {
+            pos: BlockPos::new(0, 0, 0),
+            old: Block::AIR,
+            old_fluid: 0,
+            new: Block::STONE,
+        };
+        vec![edit; len]
+    }
+
+    #[test]
+    fn the_biggest_edit_keeps_the_history() {
+        let mut history = EditHistory::default();
+        history.record(transaction(3));
+        history.record(transaction(MAX_REGION_VOLUME as usize));
+        assert_eq!(
+            history.undo().map(<[_]>::len),
+            Some(MAX_REGION_VOLUME as usize)
+        );
+        assert_eq!(history.undo().map(<[_]>::len), Some(3));
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn the_oldest_edits_are_forgotten_first() {
+        let mut history = EditHistory::default();
+        history.record(transaction(MAX_EDITS / 2));
+        history.record(transaction(MAX_EDITS / 2));
+        history.record(transaction(1));
+        assert_eq!(history.edits, MAX_EDITS / 2 + 1);
+        // the newest one stays even when it alone is over the budget
+        history.record(transaction(MAX_EDITS + 1));
+        assert_eq!(history.undo().map(<[_]>::len), Some(MAX_EDITS + 1));
+        assert!(history.undo().is_none());
+    }
+}
@@ -1,65 +1,131 @@
+use std::collections::{HashMap, HashSet};
+
 use crate::{
     CHUNK_SIZE,
     ecs::*,
-    utils::vec3_to_index,
     world::{
         ChunkMarker, WorldData,
-        mesher::{Block, Chunk, Direction},
+        block::Block,
+        coords::{BlockPos, ChunkPos, LocalPos},
+        events::{BlockChangeCause, BlockChanged},
+        light::LightEngine,
+        mesher::{Chunk, Direction},
+        ticks::BlockUpdates,
     },
 };
 
+/// Sets a block of a loaded chunk and relights around it, false if the chunk isn't loaded
+/// or the block was already there. See [`set_blocks`].
 pub fn place_block(
-    chunk: &mut Chunk,
-    pos: IVec3,
+    chunks: &mut HashMap<ChunkPos, Chunk>,
+    updates: &mut BlockUpdates,
+    events: &mut EventWriter<BlockChanged>,
+    pos: BlockPos,
     block: Block,
-    // saved_chunks: &mut Option<&mut HashMap<IVec3, SavedChunk>>,
+    cause: BlockChangeCause,
     // client: Option<ResMut<RenetClient>>,
-    update: Option<(&mut Commands, Vec<(Entity, &Transform)>)>,
-) {
-    *unsafe { chunk.blocks.get_unchecked_mut(vec3_to_index(pos)) } = block;
-    // if let Some(saved_chunks) = saved_chunks {
-    //     saved_chunks
-    //         .entry(chunk.pos)
-    //         .and_modify(|c| {
-    //             c.blocks.insert(pos, block);
-    //         })
-    //         .or_insert(SavedChunk {
-    //             blocks: HashMap::from([(pos, block)]),
-    //             // entities: chunk.entities.clone(),
-    //         });
-    // }
-    if let Some((commands, chunks)) = update {
-        let mut positions = vec![];
-        // also update corner diagonal chunks
-        for x in -1..=1 {
-            if x != 0 && !((x == -1 && pos.x == 0) || (x == 1 && pos.x == CHUNK_SIZE - 1)) {
+) -> bool {
+    // ClientPacket::PlaceBlock(pos, block).send(client);
+    set_blocks(chunks, updates, events, [(pos, block)], cause) > 0
+}
+
+/// Sets any number of blocks and relights around all of them in one pass, returning how many
+/// actually changed. Blocks in chunks that aren't loaded are skipped. Every touched chunk is
+/// remeshed once at the end of the frame and the neighbours of the blocks get a block update
+/// on the next tick.
+pub fn set_blocks(
+    chunks: &mut HashMap<ChunkPos, Chunk>,
+    updates: &mut BlockUpdates,
+    events: &mut EventWriter<BlockChanged>,
+    blocks: impl IntoIterator<Item = (BlockPos, Block)>,
+    cause: BlockChangeCause,
+) -> usize {
+    set_blocks_with_fluid(
+        chunks,
+        updates,
+        events,
+        blocks.into_iter().map(|(pos, block)| (pos, block, 0)),
+        cause,
+    )
+}
+
+/// [`set_blocks`] with the fluid level of each block, see world::fluid
+pub fn set_blocks_with_fluid(
+    chunks: &mut HashMap<ChunkPos, Chunk>,
+    updates: &mut BlockUpdates,
+    events: &mut EventWriter<BlockChanged>,
+    blocks: impl IntoIterator<Item = (BlockPos, Block, u8)>,
+    cause: BlockChangeCause,
+) -> usize {
+    let mut changed = Vec::new();
+    let mut remesh = HashSet::new();
+    for (pos, block, fluid) in blocks {
+        let (chunk_pos, local) = pos.split();
+        let Some(chunk) = chunks.get_mut(&chunk_pos) else {
+            continue;
+        };
+        let (old, old_fluid) = (chunk.blocks.get(local), chunk.fluid.get(local));
+        if (old, old_fluid) == (block, fluid) {
+            continue;
+        }
+        chunk.blocks.set(local, block);
+        chunk.fluid.set(local, fluid);
+        chunk.modified = true;
+
+        remesh.extend(affected_chunks(chunk_pos, local));
+        updates.update_neighbors(pos);
+        changed.push(pos);
+        if old != block {
+            events.write(BlockChanged {
+                pos,
+                old,
+                old_fluid,
+                new: block,
+                cause,
+            });
+        }
+    }
+
+    let count = changed.len();
+    if count > 0 {
+        let mut light = LightEngine::new(chunks);
+        light.update_blocks(changed);
+        updates.remesh(light.changed);
+        updates.remesh(remesh);
+    }
+    count
+}
+
+/// the chunk of a changed block plus every neighbour whose mesh can see it
+pub fn affected_chunks(chunk_pos: ChunkPos, pos: LocalPos) -> Vec<ChunkPos> {
+    let mut positions = vec![];
+    // also update corner diagonal chunks
+    for x in -1..=1 {
+        if x != 0 && !((x == -1 && pos.x == 0) || (x == 1 && pos.x == CHUNK_SIZE - 1)) {
+            continue;
+        }
+        for y in -1..=1 {
+            if y != 0 && !((y == -1 && pos.y == 0) || (y == 1 && pos.y == CHUNK_SIZE - 1)) {
                 continue;
             }
-            for y in -1..=1 {
-                if y != 0 && !((y == -1 && pos.y == 0) || (y == 1 && pos.y == CHUNK_SIZE - 1)) {
+            for z in -1..=1 {
+                if z != 0 && !((z == -1 && pos.z == 0) || (z == 1 && pos.z == CHUNK_SIZE - 1)) {
                     continue;
                 }
-                for z in -1..=1 {
-                    if z != 0 && !((z == -1 && pos.z == 0) || (z == 1 && pos.z == CHUNK_SIZE - 1)) {
-                        continue;
-                    }
-                    positions.push(chunk.pos + IVec3::new(x, y, z));
-                }
+                positions.push(chunk_pos.offset(IVec3::new(x, y, z)));
             }
         }
-        positions.dedup();
-        update_chunks(commands, chunks, positions);
     }
-    // ClientPacket::PlaceBlock(chunk.pos * CHUNK_SIZE + pos, block).send(client);
+    positions
 }
 
 pub fn update_chunks(
     commands: &mut Commands,
     chunks: Vec<(Entity, &Transform)>,
-    positions: Vec<IVec3>,
+    positions: Vec<ChunkPos>,
 ) {
     for (entity, transform) in chunks {
-        if positions.contains(&(transform.translation / CHUNK_SIZE as f32).as_ivec3()) {
+        if positions.contains(&ChunkPos::from_world(transform.translation)) {
             commands
                 .entity(entity)
                 .try_remove::<ChunkMarker>()
@@ -70,9 +136,7 @@ pub fn update_chunks(
 
 #[derive(Debug)]
 pub struct RayHit {
-    pub global_position: IVec3,
-    pub chunk_pos: IVec3,
-    pub local_pos: IVec3,
+    pub pos: BlockPos,
     pub normal: Direction,
     pub _block: Block,
     pub distance: f32,
@@ -172,35 +236,19 @@ pub fn ray_cast(
             break;
         }
 
-        let chunk_pos = ivec3(
-            current_block_pos.x.div_euclid(CHUNK_SIZE as f32) as i32,
-            current_block_pos.y.div_euclid(CHUNK_SIZE as f32) as i32,
-            current_block_pos.z.div_euclid(CHUNK_SIZE as f32) as i32,
-        );
-
-        let local_block_pos = vec3(
-            current_block_pos.x.rem_euclid(CHUNK_SIZE as f32),
-            current_block_pos.y.rem_euclid(CHUNK_SIZE as f32),
-            current_block_pos.z.rem_euclid(CHUNK_SIZE as f32),
-        )
-        .as_ivec3();
+        let pos = BlockPos::from_world(current_block_pos);
+        let (chunk_pos, local_pos) = pos.split();
 
         if let Some(chunk) = world_data.chunks.read().unwrap().get(&chunk_pos) {
-            let block_index = vec3_to_index(local_block_pos);
-
-            if block_index < chunk.blocks.len() {
-                let block = *unsafe { chunk.blocks.get_unchecked(block_index) };
-
-                if block.is_solid() {
-                    return Some(RayHit {
-                        global_position: current_block_pos.as_ivec3(),
-                        chunk_pos,
-                        local_pos: local_block_pos,
-                        normal,
-                        _block: block,
-                        distance: current_distance,
-                    });
-                }
+            let block = chunk.blocks.get(local_pos);
+
+            if block.is_solid() {
+                return Some(RayHit {
+                    pos,
+                    normal,
+                    _block: block,
+                    distance: current_distance,
+                });
             }
         }
     }
@@ -0,0 +1,474 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{
+    CHUNK_SIZE,
+    ecs::*,
+    world::{
+        block::Block,
+        caves::OVERHANG_DEPTH,
+        coords::{BlockPos, ChunkPos, LocalPos},
+        interaction::affected_chunks,
+        mesher::{Chunk, Direction},
+    },
+};
+
+pub const MAX_LIGHT: u8 = 15;
+// a chunk only starts out with sky light in columns whose terrain surface is at least this far
+// below its top, anything closer may still have an overhang or a tree above it and has to wait
+// for the chunk above to bring the light down
+const SKY_MARGIN: i32 = OVERHANG_DEPTH + 10;
+
+/// sky light in the high 4 bits, block light in the low 4
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct Light(pub u8);
+
+impl Light {
+    /// what unloaded chunks are assumed to be lit with
+    pub const SKY: Light = Light::new(MAX_LIGHT, 0);
+
+    #[inline]
+    pub const fn new(sky: u8, block: u8) -> Self {
+        Self((sky << 4) | block)
+    }
+
+    #[inline]
+    pub const fn sky(self) -> u8 {
+        self.0 >> 4
+    }
+
+    #[inline]
+    pub const fn block(self) -> u8 {
+        self.0 & 15
+    }
+
+    #[inline]
+    fn get(self, channel: Channel) -> u8 {
+        match channel {
+            Channel::Sky => self.sky(),
+            Channel::Block => self.block(),
+        }
+    }
+
+    #[inline]
+    fn with(self, channel: Channel, level: u8) -> Self {
+        match channel {
+            Channel::Sky => Self::new(level, self.block()),
+            Channel::Block => Self::new(self.sky(), level),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Channel {
+    Sky,
+    Block,
+}
+
+// how much light is lost going into a block, None if it doesn't let any through
+#[inline]
+fn attenuation(block: Block) -> Option<u8> {
+    // light gets around slabs, fences and plants as if they weren't there
+    if block.is_air() || !block.info().shape.is_cube() {
+        Some(1)
+    } else if block.info().transparent {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Flood fills light through a set of chunks, usually every loaded one. Chunks whose light
+/// changed (including neighbours that sample it at their border) end up in `changed` so
+/// their meshes can be rebuilt.
+pub struct LightEngine<'a> {
+    chunks: &'a mut HashMap<ChunkPos, Chunk>,
+    pub changed: HashSet<ChunkPos>,
+}
+
+impl<'a> LightEngine<'a> {
+    pub fn new(chunks: &'a mut HashMap<ChunkPos, Chunk>) -> Self {
+        Self {
+            chunks,
+            changed: HashSet::new(),
+        }
+    }
+
+    #[inline]
+    fn get(&self, pos: BlockPos) -> Option<(Block, Light)> {
+        let (chunk_pos, local) = pos.split();
+        let chunk = self.chunks.get(&chunk_pos)?;
+        Some((chunk.blocks.get(local), chunk.light.get(local)))
+    }
+
+    fn set(&mut self, pos: BlockPos, channel: Channel, level: u8) {
+        let (chunk_pos, local) = pos.split();
+        if let Some(chunk) = self.chunks.get_mut(&chunk_pos) {
+            let light = chunk.light.get(local);
+            chunk.light.set(local, light.with(channel, level));
+            chunk.light_version = chunk.light_version.wrapping_add(1);
+            self.changed.extend(affected_chunks(chunk_pos, local));
+        }
+    }
+
+    /// relights around blocks that were just changed
+    pub fn update_blocks(&mut self, positions: impl IntoIterator<Item = BlockPos>) {
+        let positions = positions.into_iter().collect::<Vec<_>>();
+        for channel in [Channel::Sky, Channel::Block] {
+            let mut removed = VecDeque::new();
+            let mut lit = VecDeque::new();
+            for &pos in &positions {
+                let Some((block, light)) = self.get(pos) else {
+                    continue;
+                };
+                removed.push_back((pos, light.get(channel)));
+                self.set(pos, channel, 0);
+
+                // whatever is around the block may be able to light it (again)
+                lit.extend(Direction::NORMALS.map(|normal| pos.offset(IVec3::from(normal))));
+                if channel == Channel::Block && block.info().light_emission > 0 {
+                    self.set(pos, channel, block.info().light_emission);
+                    lit.push_back(pos);
+                }
+            }
+            self.remove(channel, removed, &mut lit);
+            self.propagate(channel, lit);
+        }
+    }
+
+    /// spreads light between a freshly loaded chunk and its neighbours, from the seeds gathered
+    /// when it was generated. Faces whose neighbour was loaded or relit since are seeded whole.
+    pub fn stitch(&mut self, pos: ChunkPos, gathered: StitchSeeds) {
+        let mut seeds = gathered.seeds;
+        for (i, normal) in Direction::NORMALS.into_iter().enumerate() {
+            let normal = IVec3::from(normal);
+            let neighbor = pos.offset(normal);
+            let Some(chunk) = self.chunks.get(&neighbor) else {
+                continue;
+            };
+            if gathered.neighbors[i] == Some(chunk.light_version) {
+                continue;
+            }
+            for (inner, outer) in face_pairs(normal) {
+                seeds.push_back(pos.block(inner));
+                seeds.push_back(neighbor.block(outer));
+            }
+        }
+
+        self.propagate(Channel::Sky, seeds.clone());
+        self.propagate(Channel::Block, seeds);
+    }
+
+    // darkens everything that was lit by the removed light, anything brighter found on the way
+    // is a different source and gets queued to fill the gap back in
+    fn remove(
+        &mut self,
+        channel: Channel,
+        mut queue: VecDeque<(BlockPos, u8)>,
+        lit: &mut VecDeque<BlockPos>,
+    ) {
+        while let Some((pos, level)) = queue.pop_front() {
+            for normal in Direction::NORMALS {
+                let normal = IVec3::from(normal);
+                let neighbor = pos.offset(normal);
+                let Some((block, light)) = self.get(neighbor) else {
+                    continue;
+                };
+                let neighbor_level = light.get(channel);
+                if neighbor_level == 0 {
+                    continue;
+                }
+
+                let from_here = neighbor_level < level
+                    || (channel == Channel::Sky
+                        && normal == IVec3::NEG_Y
+                        && level == MAX_LIGHT
+                        && neighbor_level == MAX_LIGHT);
+                if from_here {
+                    self.set(neighbor, channel, 0);
+                    queue.push_back((neighbor, neighbor_level));
+                    let emission = block.info().light_emission;
+                    if channel == Channel::Block && emission > 0 {
+                        self.set(neighbor, channel, emission);
+                        lit.push_back(neighbor);
+                    }
+                } else {
+                    lit.push_back(neighbor);
+                }
+            }
+        }
+    }
+
+    fn propagate(&mut self, channel: Channel, mut queue: VecDeque<BlockPos>) {
+        while let Some(pos) = queue.pop_front() {
+            let Some((_, light)) = self.get(pos) else {
+                continue;
+            };
+            let level = light.get(channel);
+            if level <= 1 {
+                continue;
+            }
+
+            for normal in Direction::NORMALS {
+                let normal = IVec3::from(normal);
+                let neighbor = pos.offset(normal);
+                let Some((block, light)) = self.get(neighbor) else {
+                    continue;
+                };
+                let Some(cost) = attenuation(block) else {
+                    continue;
+                };
+                // full sky light goes straight down through air without fading
+                let new_level = if channel == Channel::Sky
+                    && normal == IVec3::NEG_Y
+                    && level == MAX_LIGHT
+                    && cost == 1
+                {
+                    MAX_LIGHT
+                } else {
+                    level.saturating_sub(cost)
+                };
+                if new_level > light.get(channel) {
+                    self.set(neighbor, channel, new_level);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+}
+
+/// Where light can cross between a freshly generated chunk and the neighbours that were loaded
+/// at the time, gathered on the generation task so the main thread only has to spread it.
+pub struct StitchSeeds {
+    seeds: VecDeque<BlockPos>,
+    // the light version of every neighbour (by direction) the seeds were gathered against
+    neighbors: [Option<u32>; 6],
+}
+
+impl StitchSeeds {
+    pub fn gather(chunk: &Chunk, chunks: &HashMap<ChunkPos, Chunk>) -> Self {
+        let mut seeds = VecDeque::new();
+        let mut neighbors = [None; 6];
+        for (i, normal) in Direction::NORMALS.into_iter().enumerate() {
+            let normal = IVec3::from(normal);
+            let Some(neighbor) = chunks.get(&chunk.pos.offset(normal)) else {
+                continue;
+            };
+            neighbors[i] = Some(neighbor.light_version);
+
+            // light only goes from the brighter side into one that lets it in
+            for (inner, outer) in face_pairs(normal) {
+                let (block, light) = (chunk.blocks.get(inner), chunk.light.get(inner));
+                let (neighbor_block, neighbor_light) =
+                    (neighbor.blocks.get(outer), neighbor.light.get(outer));
+                if brighter(light, neighbor_light) && attenuation(neighbor_block).is_some() {
+                    seeds.push_back(chunk.pos.block(inner));
+                }
+                if brighter(neighbor_light, light) && attenuation(block).is_some() {
+                    seeds.push_back(neighbor.pos.block(outer));
+                }
+            }
+        }
+        Self { seeds, neighbors }
+    }
+}
+
+#[inline]
+fn brighter(light: Light, other: Light) -> bool {
+    light.sky() > other.sky() || light.block() > other.block()
+}
+
+// the border layers on both sides of a chunk's face towards `normal`, its own side first
+fn face_pairs(normal: IVec3) -> impl Iterator<Item = (LocalPos, LocalPos)> {
+    let axis = normal.abs().max_position();
+    let (inner, outer) = if normal[axis] > 0 {
+        (CHUNK_SIZE - 1, 0)
+    } else {
+        (0, CHUNK_SIZE - 1)
+    };
+    (0..CHUNK_SIZE * CHUNK_SIZE).map(move |i| {
+        let mut local = IVec3::ZERO;
+        local[(axis + 1) % 3] = i % CHUNK_SIZE;
+        local[(axis + 2) % 3] = i / CHUNK_SIZE;
+        local[axis] = inner;
+        let inner = LocalPos::new(local);
+        local[axis] = outer;
+        (inner, LocalPos::new(local))
+    })
+}
+
+/// Initial light of a chunk on its own, the neighbours are added with [`LightEngine::stitch`]
+/// once it's loaded. `surface` is the terrain height of every column (row major, z is the row),
+/// `open_top` marks chunks with nothing loaded above them, past the world's build limit or at
+/// the top of the load range, whose surface is lit even when it's close to the top.
+pub fn light_chunk(chunk: Chunk, surface: &[i32], open_top: bool) -> Chunk {
+    let pos = chunk.pos;
+    let top = pos.origin().y + CHUNK_SIZE;
+    let mut chunks = HashMap::from([(pos, chunk)]);
+    let mut engine = LightEngine::new(&mut chunks);
+
+    let mut sky = VecDeque::new();
+    for z in 0..CHUNK_SIZE {
+        for x in 0..CHUNK_SIZE {
+            if !open_top && top < surface[(z * CHUNK_SIZE + x) as usize] + SKY_MARGIN {
+                continue;
+            }
+            for y in (0..CHUNK_SIZE).rev() {
+                let pos = pos.block(LocalPos::new(ivec3(x, y, z)));
+                if !engine.get(pos).is_some_and(|(block, _)| block.is_air()) {
+                    break;
+                }
+                engine.set(pos, Channel::Sky, MAX_LIGHT);
+                sky.push_back(pos);
+            }
+        }
+    }
+
+    let mut emitters = VecDeque::new();
+    let blocks = engine.chunks[&pos].blocks.clone();
+    for (i, block) in blocks.iter().enumerate() {
+        let emission = block.info().light_emission;
+        if emission > 0 {
+            let pos = pos.block(LocalPos::from_index(i));
+            engine.set(pos, Channel::Block, emission);
+            emitters.push_back(pos);
+        }
+    }
+
+    engine.propagate(Channel::Sky, sky);
+    engine.propagate(Channel::Block, emitters);
+
+    let mut chunk = chunks.remove(&pos).unwrap();
+    chunk.light.compact();
+    chunk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block::{BLOCKS_PATH, BlockRegistry};
+
+    const CENTER: IVec3 = IVec3::splat(CHUNK_SIZE / 2);
+
+    fn glowstone() -> Block {
+        BlockRegistry::init(BLOCKS_PATH)
+            .unwrap()
+            .by_name("glowstone")
+            .unwrap()
+    }
+
+    // an air chunk with `blocks` in it, lit on its own
+    fn lit_chunk(pos: ChunkPos, blocks: &[(IVec3, Block)], open_top: bool) -> Chunk {
+        BlockRegistry::init(BLOCKS_PATH).unwrap();
+        let mut chunk = Chunk::new(pos);
+        for &(local, block) in blocks {
+            chunk.blocks.set(LocalPos::new(local), block);
+        }
+        // far below the surface, so only open chunks get sky light
+        let surface = vec![1000; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+        light_chunk(chunk, &surface, open_top)
+    }
+
+    fn light_at(chunk: &Chunk, local: IVec3) -> Light {
+        chunk.light.get(LocalPos::new(local))
+    }
+
+    #[test]
+    fn sky_light_goes_straight_down() {
+        let roof = ivec3(5, 20, 5);
+        let chunk = lit_chunk(ChunkPos::new(0, 0, 0), &[(roof, Block::STONE)], true);
+        for y in 0..CHUNK_SIZE {
+            assert_eq!(light_at(&chunk, ivec3(0, y, 0)).sky(), MAX_LIGHT);
+        }
+        // only what comes in from the side below the roof, one less every block
+        assert_eq!(light_at(&chunk, roof - IVec3::Y).sky(), MAX_LIGHT - 1);
+        assert_eq!(light_at(&chunk, roof).sky(), 0);
+
+        let closed = lit_chunk(ChunkPos::new(0, 0, 0), &[], false);
+        assert!(closed.light.iter().all(|light| light == Light::default()));
+    }
+
+    #[test]
+    fn emitters_fade_out_over_15_blocks() {
+        let chunk = lit_chunk(ChunkPos::new(0, 0, 0), &[(CENTER, glowstone())], false);
+        for distance in 0..=MAX_LIGHT as i32 {
+            let expected = (MAX_LIGHT as i32 - distance) as u8;
+            assert_eq!(
+                light_at(&chunk, CENTER + IVec3::X * distance).block(),
+                expected
+            );
+            assert_eq!(
+                light_at(&chunk, CENTER - IVec3::Y * distance).block(),
+                expected
+            );
+        }
+        // around a corner counts every block on the way
+        assert_eq!(
+            light_at(&chunk, CENTER + ivec3(3, 0, 4)).block(),
+            MAX_LIGHT - 7
+        );
+        assert!(chunk.light.iter().all(|light| light.sky() == 0));
+    }
+
+    #[test]
+    fn removed_emitters_take_their_light_with_them() {
+        let pos = ChunkPos::new(0, 0, 0);
+        let mut chunks = HashMap::from([(
+            pos,
+            lit_chunk(
+                pos,
+                &[(CENTER, glowstone()), (CENTER + 10, glowstone())],
+                false,
+            ),
+        )]);
+        chunks
+            .get_mut(&pos)
+            .unwrap()
+            .blocks
+            .set(LocalPos::new(CENTER), Block::AIR);
+
+        let mut light = LightEngine::new(&mut chunks);
+        light.update_blocks([pos.block(LocalPos::new(CENTER))]);
+        assert!(light.changed.contains(&pos));
+
+        // only the other emitter is left
+        let chunk = &chunks[&pos];
+        assert_eq!(light_at(chunk, CENTER).block(), 0);
+        assert_eq!(light_at(chunk, CENTER + 10).block(), MAX_LIGHT);
+        assert_eq!(
+            light_at(chunk, CENTER + ivec3(10, 10, 9)).block(),
+            MAX_LIGHT - 1
+        );
+        assert_eq!(light_at(chunk, CENTER - 3).block(), 0);
+    }
+
+    #[test]
+    fn stitching_lights_across_the_border() {
+        let emitter = ivec3(CHUNK_SIZE - 2, 16, 16);
+        for stale in [false, true] {
+            let (a, b) = (ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 0));
+            let mut chunks = HashMap::from([(a, lit_chunk(a, &[(emitter, glowstone())], false))]);
+            let chunk = lit_chunk(b, &[], false);
+            // gathered before the neighbour was loaded, the whole face has to be seeded then
+            let seeds = if stale {
+                StitchSeeds::gather(&chunk, &HashMap::new())
+            } else {
+                StitchSeeds::gather(&chunk, &chunks)
+            };
+            chunks.insert(b, chunk);
+
+            let mut light = LightEngine::new(&mut chunks);
+            light.stitch(b, seeds);
+            assert!(light.changed.contains(&b));
+            for x in 0..8 {
+                assert_eq!(
+                    light_at(&chunks[&b], ivec3(x, 16, 16)).block(),
+                    MAX_LIGHT - 2 - x as u8
+                );
+            }
+            assert_eq!(
+                light_at(&chunks[&b], ivec3(0, 20, 16)).block(),
+                MAX_LIGHT - 6
+            );
+        }
+    }
+}
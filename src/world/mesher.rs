@@ -1,46 +1,39 @@
-use std::collections::HashMap;
+use std::{cell::OnceCell, collections::HashMap};
 
 use gl::types::*;
 use glam::*;
-use noise::NoiseFn;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 use crate::{
-    CHUNK_SIZE, SEA_LEVEL,
+    CHUNK_SIZE,
+    ecs::*,
     render::{mesh::Vertex, primitives::Quad},
-    utils::{generate_block_at, index_to_vec3, vec3_to_index},
-    world::NoiseFunctions,
+    utils::{generate_block_at, index_to_vec3},
+    world::{
+        NoiseFunctions,
+        biome::terrain_noise,
+        block::Block,
+        caves::{DensityField, carve_block, ravine_depth},
+        coords::{ChunkPos, LocalPos},
+        fluid::{SOURCE, surface_drop},
+        light::Light,
+        palette::PalettedStorage,
+        shape::{BlockShape, FULL_RECT, SHAPE_GRID, face_axes, hides_face},
+        structures::StructureBlocks,
+    },
 };
 
 #[derive(Clone)]
 pub struct Chunk {
-    pub pos: IVec3,
-    pub blocks: Vec<Block>,
-}
-
-#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
-pub enum Block {
-    #[default]
-    Air,
-    Stone,
-    Dirt,
-    Grass,
-    Plank,
-    Bedrock,
-    Water,
-    Sand,
-    Wood,
-    Leaf,
-    Snow,
-}
-
-impl Block {
-    pub fn is_air(&self) -> bool {
-        matches!(self, Block::Air)
-    }
-    pub fn is_solid(&self) -> bool {
-        !matches!(self, Block::Air | Block::Water)
-    }
+    pub pos: ChunkPos,
+    pub blocks: PalettedStorage<Block>,
+    // see world::fluid for what the levels mean
+    pub fluid: PalettedStorage<u8>,
+    // not saved, rebuilt whenever the chunk is loaded
+    pub light: PalettedStorage<Light>,
+    // bumped whenever `light` changes, see StitchSeeds
+    pub light_version: u32,
+    pub modified: bool, // differs from worldgen and has to be saved
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
@@ -55,6 +48,14 @@ pub enum Direction {
 }
 
 impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::Left,
+        Direction::Right,
+        Direction::Bottom,
+        Direction::Top,
+        Direction::Back,
+        Direction::Front,
+    ];
     pub const NORMALS: [[i32; 3]; 6] = [
         [-1, 0, 0],
         [1, 0, 0],
@@ -66,29 +67,125 @@ impl Direction {
     pub fn as_ivec3(self) -> IVec3 {
         IVec3::from(Self::NORMALS[self as usize])
     }
+
+    #[inline]
+    pub fn opposite(self) -> Self {
+        Self::ALL[self as usize ^ 1]
+    }
+}
+
+/// How chunk meshes are built, switched with F3 to compare the two.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum MeshingMode {
+    /// a quad for every visible block face
+    #[default]
+    Naive,
+    /// coplanar faces that look the same are merged into larger quads
+    Greedy,
+}
+
+impl MeshingMode {
+    pub fn toggle(&mut self) {
+        *self = match self {
+            MeshingMode::Naive => MeshingMode::Greedy,
+            MeshingMode::Greedy => MeshingMode::Naive,
+        };
+    }
 }
 
+// x, y and z in sixteenths of a block, 10 bits each, then the shading:
+// bits 0-2 direction, 3-4 ao, 5-10 block id,
+// 11-13 how far the vertex is lowered in eighths of a block (fluid surfaces),
+// then the smoothed sky and block light in 4 bits each
 #[repr(C)]
-pub struct VoxelVertex(u32);
+#[derive(Clone, Copy)]
+pub struct VoxelVertex(u32, u32);
+
+impl VoxelVertex {
+    #[inline]
+    fn new(pos: Vec3, shading: u32) -> Self {
+        let pos = (pos * SHAPE_GRID as f32).round().as_uvec3();
+        Self(pos.x | (pos.y << 10) | (pos.z << 20), shading)
+    }
+
+    #[inline]
+    fn pos(&self) -> UVec3 {
+        uvec3(self.0 & 1023, (self.0 >> 10) & 1023, (self.0 >> 20) & 1023)
+    }
+}
+
+// everything about a voxel vertex but its position
+#[inline(always)]
+fn shading(dir: Direction, ao: u32, block: Block, drop: u8, light: (u32, u32)) -> u32 {
+    dir as u32
+        | (ao << 3)
+        | ((block.0 as u32) << 5)
+        | ((drop as u32) << 11)
+        | (light.0 << 14)
+        | (light.1 << 18)
+}
+
+#[inline(always)]
+fn flat_light(light: Light) -> (u32, u32) {
+    (light.sky() as u32, light.block() as u32)
+}
 
 impl Vertex for VoxelVertex {
     fn attributes() -> &'static [(GLuint, GLint, GLenum, GLboolean, usize)] {
-        &[(0, 1, gl::UNSIGNED_INT, gl::FALSE, 0)]
+        &[
+            (0, 1, gl::UNSIGNED_INT, gl::FALSE, 0),
+            (1, 1, gl::UNSIGNED_INT, gl::FALSE, 4),
+        ]
     }
 }
 
+// the two diagonal planes of a cross, from both sides
+const CROSS_QUADS: [[[f32; 3]; 4]; 4] = [
+    [
+        [0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [1.0, 1.0, 1.0],
+        [1.0, 0.0, 1.0],
+    ],
+    [
+        [1.0, 0.0, 1.0],
+        [1.0, 1.0, 1.0],
+        [0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0],
+    ],
+    [
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 1.0],
+        [0.0, 0.0, 1.0],
+    ],
+    [
+        [0.0, 0.0, 1.0],
+        [0.0, 1.0, 1.0],
+        [1.0, 1.0, 0.0],
+        [1.0, 0.0, 0.0],
+    ],
+];
+
 #[derive(Default)]
-pub struct ChunkMesh {
+pub struct VoxelMesh {
     pub vertices: Vec<VoxelVertex>,
     pub indices: Vec<GLuint>,
 }
 
+/// A chunk's faces, the translucent ones (water) are kept apart so they can be drawn after
+/// everything opaque.
+#[derive(Default)]
+pub struct ChunkMesh {
+    pub opaque: VoxelMesh,
+    pub translucent: VoxelMesh,
+    /// the chunk's first block, vertices are relative to it
+    pub origin: IVec3,
+}
+
 impl ChunkMesh {
-    pub fn build(
-        chunk: &Chunk,
-        chunks: &HashMap<IVec3, Chunk>,
-        noises: &NoiseFunctions,
-    ) -> Option<Self> {
+    /// `border` has to be generated already, see [`Border::generate`]
+    pub fn build(chunk: &Chunk, border: &Border, mode: MeshingMode) -> Option<Self> {
         // parallelized (thanks rayon)
         let mesh_parts = (0..CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE)
             .into_par_iter()
@@ -97,41 +194,28 @@ impl ChunkMesh {
 
                 let pos = index_to_vec3(i as usize);
 
-                let current = *unsafe { chunk.blocks.get_unchecked(i as usize) };
+                let current = chunk.blocks.get_index(i as usize);
 
-                let (back, left, down) = chunk.get_adjacent_blocks(pos, chunks, noises);
+                let (back, left, down) = chunk.get_adjacent_blocks(pos, border);
 
-                // TODO fix this so water works properly
-                if !current.is_air() {
-                    if left.is_air() {
-                        local_mesh.push_face(chunks, chunk, Direction::Left, pos, current, noises);
-                    }
-                    if back.is_air() {
-                        local_mesh.push_face(chunks, chunk, Direction::Back, pos, current, noises);
-                    }
-                    if down.is_air() {
-                        local_mesh.push_face(
-                            chunks,
-                            chunk,
-                            Direction::Bottom,
-                            pos,
-                            current,
-                            noises,
-                        );
-                    }
-                } else {
-                    if !left.is_air() {
-                        local_mesh.push_face(chunks, chunk, Direction::Right, pos, left, noises);
-                    }
-                    if !back.is_air() {
-                        local_mesh.push_face(chunks, chunk, Direction::Front, pos, back, noises);
+                // the face between this block and each neighbour, from whichever side shows it
+                for (neighbor, negative, positive) in [
+                    (left, Direction::Left, Direction::Right),
+                    (back, Direction::Back, Direction::Front),
+                    (down, Direction::Bottom, Direction::Top),
+                ] {
+                    if shows_face(current, neighbor, negative) {
+                        local_mesh.push_face(chunk, negative, pos, current, border);
                     }
-                    if !down.is_air() {
-                        local_mesh.push_face(chunks, chunk, Direction::Top, pos, down, noises);
+                    if shows_face(neighbor, current, positive) {
+                        local_mesh.push_face(chunk, positive, pos, neighbor, border);
                     }
                 }
+                if !current.is_air() && !current.info().shape.is_cube() {
+                    local_mesh.push_shape(chunk, pos, current, border);
+                }
 
-                if local_mesh.vertices.is_empty() {
+                if local_mesh.is_empty() {
                     None
                 } else {
                     Some(local_mesh)
@@ -139,40 +223,152 @@ impl ChunkMesh {
             })
             .collect::<Vec<_>>();
 
-        let mut vertices = Vec::new();
+        let (mut opaque, mut translucent) = (Vec::new(), Vec::new());
         for part in mesh_parts {
-            vertices.extend(part.vertices);
+            opaque.extend(part.opaque.vertices);
+            translucent.extend(part.translucent.vertices);
+        }
+        if mode == MeshingMode::Greedy {
+            opaque = merge_faces(opaque);
+            translucent = merge_faces(translucent);
         }
 
-        if vertices.is_empty() {
-            None
+        let mesh = Self {
+            opaque: VoxelMesh::from_quads(opaque),
+            translucent: VoxelMesh::from_quads(translucent),
+            origin: chunk.pos.origin().0,
+        };
+        if mesh.is_empty() { None } else { Some(mesh) }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.opaque.vertices.is_empty() && self.translucent.vertices.is_empty()
+    }
+
+    #[inline(always)]
+    fn mesh_for(&mut self, block: Block) -> &mut VoxelMesh {
+        if block.is_transparent() {
+            &mut self.translucent
         } else {
-            vertices.shrink_to_fit();
-
-            Some(Self {
-                indices: (0..vertices.len())
-                    .step_by(4)
-                    .flat_map(|i| {
-                        let idx = i as u32;
-                        [idx, idx + 1, idx + 2, idx, idx + 2, idx + 3]
-                    })
-                    .collect::<Vec<_>>(),
-                vertices,
-            })
+            &mut self.opaque
+        }
+    }
+
+    #[inline(always)]
+    fn push_face(
+        &mut self,
+        chunk: &Chunk,
+        dir: Direction,
+        pos: IVec3,
+        block: Block,
+        border: &Border,
+    ) {
+        self.mesh_for(block)
+            .push_face(chunk, dir, pos, block, border);
+    }
+
+    // anything that isn't a full cube, built from its boxes with flat lighting and without ao
+    fn push_shape(&mut self, chunk: &Chunk, pos: IVec3, block: Block, border: &Border) {
+        let shape = &block.info().shape;
+        let mesh = self.mesh_for(block);
+        let origin = pos.as_vec3();
+        let own_light = chunk.get_relative_light(pos, border);
+
+        if *shape == BlockShape::Cross {
+            // lit like a front face, which is also where its texture comes from
+            let shading = shading(Direction::Front, 0, block, 0, flat_light(own_light));
+            for corners in CROSS_QUADS {
+                mesh.push_quad(corners.map(|corner| origin + Vec3::from(corner)), shading);
+            }
+            return;
+        }
+
+        let neighbors =
+            Direction::ALL.map(|dir| chunk.get_relative_block(pos + dir.as_ivec3(), border));
+        for shape_box in shape.boxes(|dir| shape.connects_to(neighbors[dir as usize], dir)) {
+            let min = shape_box.min.as_vec3() / SHAPE_GRID as f32;
+            let max = shape_box.max.as_vec3() / SHAPE_GRID as f32;
+            for dir in Direction::ALL {
+                // faces inside the block are lit by the block itself
+                let light = if shape_box.touches(dir) {
+                    let neighbor = neighbors[dir as usize];
+                    if hides_face(neighbor, block, dir.opposite(), shape_box.face_rect(dir)) {
+                        continue;
+                    }
+                    chunk.get_relative_light(pos + dir.as_ivec3(), border)
+                } else {
+                    own_light
+                };
+                // quads lie on the plane through their position
+                let mut corner = min;
+                if dir as usize % 2 == 1 {
+                    corner[dir as usize / 2] = max[dir as usize / 2];
+                }
+                mesh.push_quad(
+                    Quad::new(dir, origin + corner, max - min).map(Vec3::from),
+                    shading(dir, 0, block, 0, flat_light(light)),
+                );
+            }
         }
     }
+}
+
+// only full cubes go through the neighbour pairs, everything else is meshed on its own
+#[inline(always)]
+fn shows_face(block: Block, neighbor: Block, dir: Direction) -> bool {
+    !block.is_air()
+        && block.info().shape.is_cube()
+        && !hides_face(neighbor, block, dir.opposite(), FULL_RECT)
+}
+
+impl VoxelMesh {
+    // vertices come in quads, two triangles each
+    fn from_quads(mut vertices: Vec<VoxelVertex>) -> Self {
+        vertices.shrink_to_fit();
+        Self {
+            indices: (0..vertices.len())
+                .step_by(4)
+                .flat_map(|i| {
+                    let idx = i as u32;
+                    [idx, idx + 1, idx + 2, idx, idx + 2, idx + 3]
+                })
+                .collect::<Vec<_>>(),
+            vertices,
+        }
+    }
+
+    #[inline(always)]
+    fn push_quad(&mut self, corners: [Vec3; 4], shading: u32) {
+        self.vertices
+            .extend(corners.map(|corner| VoxelVertex::new(corner, shading)));
+    }
 
     #[inline(always)]
     pub fn push_face(
         &mut self,
-        chunks: &HashMap<IVec3, Chunk>,
         chunk: &Chunk,
         dir: Direction,
         pos: IVec3,
         block: Block,
-        noises: &NoiseFunctions,
+        border: &Border,
     ) {
-        let ambient_corners = chunk.ambient_corner_voxels(chunks, dir, pos, noises);
+        let ambient_corners = chunk.ambient_corner_voxels(dir, pos, border);
+
+        // faces are pushed from the block on their negative side, so the block itself is
+        // the one behind the face for these
+        let block_pos = match dir {
+            Direction::Right | Direction::Top | Direction::Front => pos - dir.as_ivec3(),
+            _ => pos,
+        };
+        let drop = if block == Block::WATER {
+            surface_drop(chunk.get_relative_fluid(block_pos, border))
+        } else {
+            0
+        };
+        let (front_light, corner_light) =
+            chunk.face_light(dir, pos, block_pos + dir.as_ivec3(), border);
+
         for (i, pos) in Quad::new(dir, pos.as_vec3(), Vec3::ONE).iter().enumerate() {
             let index = i * 2;
 
@@ -183,103 +379,341 @@ impl ChunkMesh {
             if side_1 == 1 && side_2 == 1 {
                 ao_count = 3;
             }
+            // only the top edge of a face follows the fluid surface
+            let drop = if pos[1] as i32 > block_pos.y { drop } else { 0 };
+
+            // smooth lighting, the average of the light in front of the face and the (non solid)
+            // blocks around this corner
+            let (mut sky, mut block_light, mut count) =
+                (front_light.sky() as u32, front_light.block() as u32, 1);
+            for corner in [index, (index + 1) % 8, (index + 2) % 8] {
+                if !ambient_corners[corner] {
+                    sky += corner_light[corner].sky() as u32;
+                    block_light += corner_light[corner].block() as u32;
+                    count += 1;
+                }
+            }
 
-            self.vertices.push(VoxelVertex(
-                pos[0] as u32
-                    | (pos[1] as u32) << 6
-                    | (pos[2] as u32) << 12
-                    | (dir as u32) << 18
-                    | (ao_count as u32) << 21
-                    | (block as u32) << 23,
+            self.vertices.push(VoxelVertex::new(
+                Vec3::from(*pos),
+                shading(
+                    dir,
+                    ao_count as u32,
+                    block,
+                    drop,
+                    (sky / count, block_light / count),
+                ),
             ));
         }
     }
 }
 
-impl Chunk {
-    #[inline]
-    pub fn new(pos: IVec3) -> Self {
-        Chunk {
-            pos,
-            blocks: vec![Block::Air; (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize],
+/// Merges coplanar faces with the same block, ao, light and fluid drop into larger quads. Only
+/// faces that are shaded the same at all four corners are merged, merging anything else would
+/// stretch its shading over the whole quad, so those are kept as they are. So are the faces
+/// of shapes that aren't full cubes.
+fn merge_faces(vertices: Vec<VoxelVertex>) -> Vec<VoxelVertex> {
+    const SIZE: usize = CHUNK_SIZE as usize;
+
+    let mut merged = Vec::new();
+    // faces by direction and the layer they're in, with their position in that layer
+    let mut layers: HashMap<(usize, u32), Vec<(UVec2, u32)>> = HashMap::new();
+    for face in vertices.chunks_exact(4) {
+        let shading = face[0].1;
+        if face[1..].iter().any(|vertex| vertex.1 != shading) {
+            merged.extend_from_slice(face);
+            continue;
         }
-    }
 
-    fn get_relative_block(
-        &self,
-        relative_pos: IVec3,
-        chunks: &HashMap<IVec3, Chunk>,
-        noises: &NoiseFunctions,
-    ) -> Block {
-        let (nx, ny, nz) = (relative_pos.x, relative_pos.y, relative_pos.z);
-        if (0..CHUNK_SIZE).contains(&nx)
-            && (0..CHUNK_SIZE).contains(&ny)
-            && (0..CHUNK_SIZE).contains(&nz)
+        let dir = shading as usize & 7;
+        let [axis, u, v] = face_axes(dir);
+        let min = face
+            .iter()
+            .map(VoxelVertex::pos)
+            .reduce(UVec3::min)
+            .unwrap();
+        let size = face
+            .iter()
+            .map(VoxelVertex::pos)
+            .reduce(UVec3::max)
+            .unwrap()
+            - min;
+        if min % SHAPE_GRID != UVec3::ZERO
+            || size[axis] != 0
+            || size[u] != SHAPE_GRID
+            || size[v] != SHAPE_GRID
         {
-            return *unsafe {
-                self.blocks
-                    .get_unchecked(vec3_to_index(IVec3::new(nx, ny, nz)))
-            };
+            merged.extend_from_slice(face);
+            continue;
         }
 
-        const fn wrap_coord(mut block: i32, mut chunk: i32) -> (i32, i32) {
-            while block < 0 {
-                block += CHUNK_SIZE;
-                chunk -= 1;
+        let min = min / SHAPE_GRID;
+        layers
+            .entry((dir, min[axis]))
+            .or_default()
+            .push((uvec2(min[u], min[v]), shading));
+    }
+
+    let quads = layers
+        .into_par_iter()
+        .map(|((dir, layer), faces)| {
+            let mut grid = [[None; SIZE]; SIZE];
+            for (pos, shading) in faces {
+                grid[pos.y as usize][pos.x as usize] = Some(shading);
+            }
+
+            let [axis, u_axis, v_axis] = face_axes(dir);
+            let mut vertices = Vec::new();
+            for v in 0..SIZE {
+                let mut u = 0;
+                while u < SIZE {
+                    let Some(shading) = grid[v][u] else {
+                        u += 1;
+                        continue;
+                    };
+                    // as wide as the row allows, then as high as every row matches
+                    let mut width = 1;
+                    while u + width < SIZE && grid[v][u + width] == Some(shading) {
+                        width += 1;
+                    }
+                    let mut height = 1;
+                    while v + height < SIZE
+                        && grid[v + height][u..u + width]
+                            .iter()
+                            .all(|cell| *cell == Some(shading))
+                    {
+                        height += 1;
+                    }
+                    for row in &mut grid[v..v + height] {
+                        row[u..u + width].fill(None);
+                    }
+
+                    let (mut pos, mut size) = (Vec3::ZERO, Vec3::ONE);
+                    pos[axis] = layer as f32;
+                    pos[u_axis] = u as f32;
+                    pos[v_axis] = v as f32;
+                    size[u_axis] = width as f32;
+                    size[v_axis] = height as f32;
+                    for corner in Quad::new(Direction::ALL[dir], pos, size) {
+                        vertices.push(VoxelVertex::new(Vec3::from(corner), shading));
+                    }
+                    u += width;
+                }
+            }
+            vertices
+        })
+        .collect::<Vec<_>>();
+
+    for quad in quads {
+        merged.extend(quad);
+    }
+    merged
+}
+
+// one block around the chunk on every side
+const BORDER_SIZE: i32 = CHUNK_SIZE + 2;
+
+/// Everything the mesh looks at one block around a chunk, copied out of the loaded neighbours
+/// so the chunks don't stay locked while meshing. Blocks of neighbours that aren't loaded are
+/// generated, those are assumed to be out in the open and their water to be still.
+pub struct Border {
+    blocks: Vec<Block>,
+    light: Vec<Light>,
+    fluid: Vec<u8>,
+    // relative positions in neighbours that aren't loaded, see `generate`
+    missing: Vec<IVec3>,
+}
+
+impl Border {
+    /// what the loaded neighbours have, [`Border::generate`] fills in the rest
+    pub fn copy(chunk_pos: ChunkPos, chunks: &HashMap<ChunkPos, Chunk>) -> Self {
+        let volume = BORDER_SIZE.pow(3) as usize;
+        let mut border = Self {
+            blocks: vec![Block::AIR; volume],
+            light: vec![Light::SKY; volume],
+            fluid: vec![SOURCE; volume],
+            missing: Vec::new(),
+        };
+        for i in 0..volume {
+            let pos = Self::position(i);
+            if LocalPos::try_new(pos).is_some() {
+                continue;
             }
-            while block >= CHUNK_SIZE {
-                block -= CHUNK_SIZE;
-                chunk += 1;
+            let (neighbor_pos, local) = chunk_pos.relative(pos).split();
+            if let Some(chunk) = chunks.get(&neighbor_pos) {
+                border.blocks[i] = chunk.blocks.get(local);
+                border.light[i] = chunk.light.get(local);
+                border.fluid[i] = chunk.fluid.get(local);
+            } else {
+                border.missing.push(pos);
             }
-            (block, chunk)
         }
+        border
+    }
 
-        let (lx, chunk_x) = wrap_coord(nx, self.pos.x);
-        let (ly, chunk_y) = wrap_coord(ny, self.pos.y);
-        let (lz, chunk_z) = wrap_coord(nz, self.pos.z);
+    /// Generates the blocks of the neighbours that aren't loaded, the same way chunk generation
+    /// does. Every column is sampled once and caves once per neighbour.
+    pub fn generate(&mut self, chunk_pos: ChunkPos, noises: &NoiseFunctions) {
+        if self.missing.is_empty() {
+            return;
+        }
+        let origin = *chunk_pos.origin();
+        let structures = StructureBlocks::near(origin - 1, origin + CHUNK_SIZE, noises);
 
-        let neighbor_pos = ivec3(chunk_x, chunk_y, chunk_z);
+        let mut columns = self.missing.iter().map(|pos| pos.xz()).collect::<Vec<_>>();
+        columns.sort_unstable_by_key(|column| (column.x, column.y));
+        columns.dedup();
+        let columns = columns
+            .into_par_iter()
+            .map(|column| {
+                let world_column = (origin.xz() + column).as_vec2();
+                let (max_y, biome) = terrain_noise(world_column, noises);
+                let ravine = ravine_depth(world_column, max_y, noises);
+                (column, (max_y, biome, ravine))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut neighbors = HashMap::<ChunkPos, Vec<IVec3>>::new();
+        for pos in self.missing.drain(..) {
+            neighbors
+                .entry(chunk_pos.relative(pos).chunk())
+                .or_default()
+                .push(pos);
+        }
+        let generated = neighbors
+            .into_par_iter()
+            .flat_map_iter(|(_, positions)| {
+                // only sampled once a block reaches into the caves, and only around the border
+                let (min, max) = positions
+                    .iter()
+                    .fold((IVec3::MAX, IVec3::MIN), |(min, max), &pos| {
+                        (min.min(pos), max.max(pos))
+                    });
+                let slab = || {
+                    DensityField::covering(
+                        *chunk_pos.relative(min),
+                        *chunk_pos.relative(max),
+                        noises,
+                    )
+                };
+                let density = OnceCell::new();
+                positions
+                    .into_iter()
+                    .map(|pos| {
+                        let world_pos = chunk_pos.relative(pos);
+                        let (max_y, biome, ravine) = columns[&pos.xz()];
+                        let block = if noises.settings.caves {
+                            carve_block(*world_pos, max_y, biome, ravine, || {
+                                density.get_or_init(slab).at(*world_pos)
+                            })
+                        } else {
+                            generate_block_at(*world_pos, max_y, biome)
+                        };
+                        let block = noises.settings.clip_to_bounds(world_pos.y, block);
+                        (pos, structures.block_at(*world_pos, block))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        for (pos, block) in generated {
+            self.blocks[Self::index(pos)] = block;
+        }
+    }
+
+    #[inline]
+    fn index(relative_pos: IVec3) -> usize {
+        let pos = relative_pos + 1;
+        (pos.x + pos.z * BORDER_SIZE + pos.y * BORDER_SIZE * BORDER_SIZE) as usize
+    }
 
-        if let Some(chunk) = chunks.get(&neighbor_pos) {
-            return *unsafe {
-                chunk
-                    .blocks
-                    .get_unchecked(vec3_to_index(IVec3::new(lx, ly, lz)))
-            };
+    #[inline]
+    fn position(index: usize) -> IVec3 {
+        let index = index as i32;
+        ivec3(
+            index % BORDER_SIZE,
+            index / (BORDER_SIZE * BORDER_SIZE),
+            (index / BORDER_SIZE) % BORDER_SIZE,
+        ) - 1
+    }
+}
+
+impl Chunk {
+    #[inline]
+    pub fn new(pos: ChunkPos) -> Self {
+        Chunk {
+            pos,
+            blocks: PalettedStorage::new(Block::AIR),
+            fluid: PalettedStorage::new(SOURCE),
+            light: PalettedStorage::new(Light::default()),
+            light_version: 0,
+            modified: false,
         }
+    }
 
-        let world_pos = IVec3::new(
-            chunk_x * CHUNK_SIZE + lx,
-            chunk_y * CHUNK_SIZE + ly,
-            chunk_z * CHUNK_SIZE + lz,
-        );
-        let (max_y, _biome) = terrain_noise(world_pos.xz().as_vec2(), noises);
-        generate_block_at(world_pos, max_y) // ! shouldn't do this (at least without checking for changes)
+    #[inline]
+    fn get_relative_block(&self, relative_pos: IVec3, border: &Border) -> Block {
+        match LocalPos::try_new(relative_pos) {
+            Some(local) => self.blocks.get(local),
+            None => border.blocks[Border::index(relative_pos)],
+        }
+    }
+
+    #[inline]
+    fn get_relative_light(&self, relative_pos: IVec3, border: &Border) -> Light {
+        match LocalPos::try_new(relative_pos) {
+            Some(local) => self.light.get(local),
+            None => border.light[Border::index(relative_pos)],
+        }
+    }
+
+    #[inline]
+    fn get_relative_fluid(&self, relative_pos: IVec3, border: &Border) -> u8 {
+        match LocalPos::try_new(relative_pos) {
+            Some(local) => self.fluid.get(local),
+            None => border.fluid[Border::index(relative_pos)],
+        }
     }
 
     #[inline(always)]
-    pub fn get_adjacent_blocks(
-        &self,
-        pos: IVec3,
-        chunks: &HashMap<IVec3, Chunk>,
-        noises: &NoiseFunctions,
-    ) -> (Block, Block, Block) {
-        let back = self.get_relative_block(pos - IVec3::Z, chunks, noises);
-        let left = self.get_relative_block(pos - IVec3::X, chunks, noises);
-        let down = self.get_relative_block(pos - IVec3::Y, chunks, noises);
+    pub fn get_adjacent_blocks(&self, pos: IVec3, border: &Border) -> (Block, Block, Block) {
+        let back = self.get_relative_block(pos - IVec3::Z, border);
+        let left = self.get_relative_block(pos - IVec3::X, border);
+        let down = self.get_relative_block(pos - IVec3::Y, border);
 
         (back, left, down)
     }
 
-    pub fn ambient_corner_voxels(
+    pub fn ambient_corner_voxels(&self, dir: Direction, pos: IVec3, border: &Border) -> [bool; 8] {
+        let positions = corner_offsets(dir);
+        let mut result = [false; 8];
+        // only full blocks shade their neighbours
+        for i in 0..8 {
+            let block = self.get_relative_block(pos + positions[i], border);
+            result[i] = !block.is_air() && block.info().shape.is_cube();
+        }
+        result
+    }
+
+    /// the light in front of a face, then the same 8 blocks around it as
+    /// [`Chunk::ambient_corner_voxels`]
+    pub fn face_light(
         &self,
-        chunks: &HashMap<IVec3, Chunk>,
         dir: Direction,
         pos: IVec3,
-        noises: &NoiseFunctions,
-    ) -> [bool; 8] {
-        #[rustfmt::skip]
-        let positions = match dir {
+        front: IVec3,
+        border: &Border,
+    ) -> (Light, [Light; 8]) {
+        let corners =
+            corner_offsets(dir).map(|offset| self.get_relative_light(pos + offset, border));
+        (self.get_relative_light(front, border), corners)
+    }
+}
+
+// the 8 blocks around the front of a face, clockwise starting from a side
+#[inline(always)]
+fn corner_offsets(dir: Direction) -> [IVec3; 8] {
+    #[rustfmt::skip]
+    let positions = match dir {
             Direction::Left => [ivec3(-1,0,-1),ivec3(-1,-1,-1),ivec3(-1,-1,0),ivec3(-1,-1,1),ivec3(-1,0,1),ivec3(-1,1,1),ivec3(-1, 1, 0),ivec3(-1,1,-1),],
             Direction::Bottom => [ivec3(-1, -1, 0),ivec3(-1, -1, -1),ivec3(0, -1, -1), ivec3(1,-1,-1),ivec3(1,-1,0),ivec3(1, -1, 1),ivec3(0,-1,1),ivec3(-1,-1,1),],
             Direction::Back => [ivec3(0,-1,-1),ivec3(-1,-1,-1),ivec3(-1,0,-1),ivec3(-1,1,-1), ivec3(0,1,-1), ivec3(1,1,-1),ivec3(1,0,-1), ivec3(1,-1,-1)],
@@ -288,58 +722,5 @@ impl Chunk {
             Direction::Top => [ivec3(-1,0,0),ivec3(-1,0,1),ivec3(0,0,1),ivec3(1,0,1),ivec3(1,0,0),ivec3(1,0,-1),ivec3(0,0,-1),ivec3(-1,0,-1),],
             Direction::Front => [ivec3(0,-1,0),ivec3(1,-1,0),ivec3(1,0,0),ivec3(1,1,0),ivec3(0,1,0),ivec3(-1,1,0),ivec3(-1,0,0),ivec3(-1,-1,0),],
         };
-
-        let mut result = [false; 8];
-        for i in 0..8 {
-            result[i] = !self
-                .get_relative_block(pos + positions[i], chunks, noises)
-                .is_air();
-        }
-        result
-    }
-}
-
-const OCEAN_MIN_HEIGHT: f32 = SEA_LEVEL as f32 - 40.0;
-const OCEAN_MAX_HEIGHT: f32 = SEA_LEVEL as f32 + 5.0;
-const OCEAN_FLATTENING_EXPONENT: f32 = 4.0;
-const PLAINS_MIN_HEIGHT: f32 = SEA_LEVEL as f32 + 10.0;
-const PLAINS_MAX_HEIGHT: f32 = SEA_LEVEL as f32 + 40.0;
-const PLAINS_FLATTENING_EXPONENT: f32 = 3.0;
-const MOUNTAIN_MIN_HEIGHT: f32 = SEA_LEVEL as f32 + 50.0;
-const MOUNTAIN_MAX_HEIGHT: f32 = SEA_LEVEL as f32 + 180.0;
-const MOUNTAIN_FLATTENING_EXPONENT: f32 = 1.5;
-const OCEAN_PLAINS_THRESHOLD: f32 = 0.4;
-const PLAINS_MOUNTAIN_THRESHOLD: f32 = 0.6;
-
-// TODO make this better
-#[inline]
-// max_y, biome
-pub fn terrain_noise(pos: Vec2, noises: &NoiseFunctions) -> (i32, f32) {
-    let terrain_fbm = (noises.terrain.get(pos.as_dvec2().into()) as f32 + 1.0) / 2.0;
-    let biome_fbm = (noises.biome.get(pos.as_dvec2().into()) as f32 + 1.0) / 2.0;
-
-    let min_height: f32;
-    let max_height: f32;
-    let flattening_exp: f32;
-
-    if biome_fbm < OCEAN_PLAINS_THRESHOLD {
-        let t = biome_fbm / OCEAN_PLAINS_THRESHOLD;
-        min_height = OCEAN_MIN_HEIGHT.lerp(PLAINS_MIN_HEIGHT, t);
-        max_height = OCEAN_MAX_HEIGHT.lerp(PLAINS_MAX_HEIGHT, t);
-        flattening_exp = OCEAN_FLATTENING_EXPONENT.lerp(PLAINS_FLATTENING_EXPONENT, t);
-    } else if biome_fbm < PLAINS_MOUNTAIN_THRESHOLD {
-        let t = (biome_fbm - OCEAN_PLAINS_THRESHOLD)
-            / (PLAINS_MOUNTAIN_THRESHOLD - OCEAN_PLAINS_THRESHOLD);
-        min_height = PLAINS_MIN_HEIGHT.lerp(MOUNTAIN_MIN_HEIGHT, t);
-        max_height = PLAINS_MAX_HEIGHT.lerp(MOUNTAIN_MAX_HEIGHT, t);
-        flattening_exp = PLAINS_FLATTENING_EXPONENT.lerp(MOUNTAIN_FLATTENING_EXPONENT, t);
-    } else {
-        min_height = MOUNTAIN_MIN_HEIGHT;
-        max_height = MOUNTAIN_MAX_HEIGHT;
-        flattening_exp = MOUNTAIN_FLATTENING_EXPONENT;
-    }
-
-    let height = min_height + terrain_fbm.powf(flattening_exp) * (max_height - min_height);
-
-    (height as i32, biome_fbm)
+    positions
 }
@@ -4,55 +4,193 @@ use std::{
 };
 
 use bevy_tasks::Task;
-use noise::{Fbm, MultiFractal, Simplex};
+use noise::{Fbm, Simplex};
 
 use crate::{
     App,
+    args::LaunchArgs,
     ecs::*,
-    world::mesher::{Chunk, ChunkMesh},
+    world::{
+        behaviors::register_default_behaviors,
+        block::{BLOCKS_PATH, BlockRegistry, Blocks},
+        coords::{BlockPos, ChunkPos},
+        editor::Clipboard,
+        events::{BlockChanged, ChunkLoaded, ChunkUnloaded},
+        history::EditHistory,
+        light::StitchSeeds,
+        mesher::{Chunk, ChunkMesh, MeshingMode},
+        save::{DEFAULT_WORLD_DIR, WorldSave},
+        schematic::{BLOCK_MAPPING_PATH, BlockMapping},
+        settings::{DEFAULT_PRESET, WorldGenSettings},
+        ticks::{BlockBehaviors, BlockUpdates},
+    },
 };
 
+pub mod behaviors;
+pub mod biome;
+pub mod block;
+pub mod caves;
+pub mod coords;
+pub mod editor;
+pub mod events;
+pub mod fluid;
 pub mod generation;
+pub mod history;
 pub mod interaction;
+pub mod light;
 pub mod mesher;
+pub mod nbt;
+pub mod palette;
+pub mod save;
+pub mod schematic;
+pub mod settings;
+pub mod shape;
+pub mod structures;
+pub mod ticks;
 
 pub fn world_plugin(app: &mut App) {
-    let seed = 1337;
-    app.init_resource::<WorldData>()
-        .insert_resource(NoiseFunctions {
-            seed,
-            terrain: Fbm::<Simplex>::new(seed)
-                .set_frequency(0.002)
-                .set_persistence(0.5)
-                .set_octaves(4)
-                .set_lacunarity(2.0),
-            biome: Fbm::<Simplex>::new(seed + 1)
-                .set_frequency(0.0001)
-                .set_persistence(0.6)
-                .set_octaves(3)
-                .set_lacunarity(2.0),
-            // detail: Fbm::<Simplex>::new(seed)
-            //     .set_frequency(0.004)
-            //     .set_persistence(0.5)
-            //     .set_octaves(3)
-            //     .set_lacunarity(1.9),
-        })
+    let registry = BlockRegistry::init(BLOCKS_PATH).unwrap();
+
+    let args = app.world.resource::<LaunchArgs>();
+    let dir = args
+        .world
+        .clone()
+        .unwrap_or_else(|| DEFAULT_WORLD_DIR.into());
+    let preset = args.preset.as_deref().unwrap_or(DEFAULT_PRESET);
+    let mut settings = WorldGenSettings::load_preset(preset).unwrap();
+
+    // existing worlds keep generating with whatever they were created with
+    let save = WorldSave::open(&dir, args.seed.unwrap_or(settings.seed), preset).unwrap();
+    if args.seed.is_some_and(|seed| seed != save.seed)
+        || args
+            .preset
+            .as_ref()
+            .is_some_and(|preset| *preset != save.preset)
+    {
+        eprintln!(
+            "{} already exists with seed {} and preset {}, ignoring --seed/--preset",
+            dir.display(),
+            save.seed,
+            save.preset
+        );
+    }
+    if save.preset != settings.preset {
+        settings = WorldGenSettings::load_preset(&save.preset).unwrap();
+    }
+    settings.seed = save.seed;
+    println!("world seed {} ({} preset)", settings.seed, settings.preset);
+
+    // only schematics need it, they shouldn't keep the game from starting
+    let mapping = BlockMapping::load(BLOCK_MAPPING_PATH, registry).unwrap_or_else(|e| {
+        eprintln!("{e}, schematics will only have stone");
+        BlockMapping::empty(registry.blocks.len())
+    });
+
+    let world_data = WorldData {
+        save: Arc::new(save),
+        ..Default::default()
+    };
+    let mut behaviors = BlockBehaviors::default();
+    register_default_behaviors(&mut behaviors);
+
+    app.insert_resource(world_data)
+        .init_resource::<ChunkLoadSettings>()
+        .init_resource::<MeshingMode>()
+        .init_resource::<BlockUpdates>()
+        .init_resource::<Clipboard>()
+        .init_resource::<EditHistory>()
+        .insert_resource(Blocks(registry))
+        .insert_resource(mapping)
+        .init_resource::<Events<BlockChanged>>()
+        .init_resource::<Events<ChunkLoaded>>()
+        .init_resource::<Events<ChunkUnloaded>>()
+        .insert_resource(behaviors)
+        .insert_resource(settings.build_noises())
+        .insert_resource(settings)
         .add_systems(
             Update,
             (
                 generation::handle_chunk_gen,
+                generation::handle_meshing_mode,
                 generation::handle_mesh_gen,
                 generation::handle_chunk_despawn,
-                generation::process_tasks,
+                // so meshes finished for chunks that are unloading are already on their entity
+                // when they're despawned, instead of being uploaded after
+                generation::process_tasks.before(generation::handle_chunk_despawn),
             ),
-        );
+        )
+        .add_systems(FixedUpdate, ticks::tick_blocks)
+        // after everything that can change blocks this frame
+        .add_systems(
+            PostUpdate,
+            (
+                ticks::remesh_changed_chunks,
+                history::record_edits.before(events::update_events),
+                events::update_events,
+            ),
+        )
+        .add_systems(Exiting, save::save_world);
 }
 
 #[derive(Resource, Default)]
 pub struct WorldData {
-    pub chunks: Arc<RwLock<HashMap<IVec3, Chunk>>>,
-    pub loading_chunks: Arc<RwLock<HashSet<IVec3>>>,
-    pub highlighted_block: Option<IVec3>,
+    pub chunks: Arc<RwLock<HashMap<ChunkPos, Chunk>>>,
+    pub loading_chunks: Arc<RwLock<HashSet<ChunkPos>>>,
+    pub highlighted_block: Option<BlockPos>,
+    pub save: Arc<WorldSave>,
+}
+
+/// Which chunks are kept around the player, in chunks. Loading happens inside a cylinder of
+/// `horizontal_radius` x `vertical_radius`, unloading only once a chunk leaves that cylinder
+/// grown by `unload_margin` so chunks on the edge don't flip between the two.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ChunkLoadSettings {
+    pub horizontal_radius: i32,
+    pub vertical_radius: i32,
+    pub unload_margin: i32,
+}
+
+impl Default for ChunkLoadSettings {
+    fn default() -> Self {
+        Self {
+            horizontal_radius: 8,
+            vertical_radius: 6,
+            unload_margin: 2,
+        }
+    }
+}
+
+impl ChunkLoadSettings {
+    pub const MAX_RADIUS: i32 = 32;
+
+    #[inline]
+    fn in_cylinder(center: ChunkPos, pos: ChunkPos, horizontal: i32, vertical: i32) -> bool {
+        let offset = *pos - *center;
+        // + radius rounds the circle out a bit so it doesn't end in single-chunk bumps
+        offset.xz().length_squared() <= horizontal * horizontal + horizontal
+            && offset.y.abs() <= vertical
+    }
+
+    #[inline]
+    pub fn should_load(&self, center: ChunkPos, pos: ChunkPos) -> bool {
+        Self::in_cylinder(center, pos, self.horizontal_radius, self.vertical_radius)
+    }
+
+    #[inline]
+    pub fn should_unload(&self, center: ChunkPos, pos: ChunkPos) -> bool {
+        !Self::in_cylinder(
+            center,
+            pos,
+            self.horizontal_radius + self.unload_margin,
+            self.vertical_radius + self.unload_margin,
+        )
+    }
+
+    /// grows or shrinks both radii, keeping them between 1 and [`Self::MAX_RADIUS`]
+    pub fn adjust(&mut self, by: i32) {
+        self.horizontal_radius = (self.horizontal_radius + by).clamp(1, Self::MAX_RADIUS);
+        self.vertical_radius = (self.vertical_radius + by).clamp(1, Self::MAX_RADIUS);
+    }
 }
 
 #[derive(Resource, Clone)]
@@ -60,14 +198,22 @@ pub struct NoiseFunctions {
     pub seed: u32,
     pub terrain: Fbm<Simplex>,
     pub biome: Fbm<Simplex>,
+    pub cave: Fbm<Simplex>,
+    pub worm_a: Fbm<Simplex>,
+    pub worm_b: Fbm<Simplex>,
+    pub overhang: Fbm<Simplex>,
+    pub ravine: Fbm<Simplex>,
+    pub temperature: Fbm<Simplex>,
+    pub humidity: Fbm<Simplex>,
+    pub settings: Arc<WorldGenSettings>,
     // pub detail: Fbm<Simplex>,
 }
 
 #[derive(Component)]
-pub struct ComputeChunk(pub Task<Chunk>, pub IVec3);
+pub struct ComputeChunk(pub Task<(Chunk, StitchSeeds)>, pub ChunkPos);
 
 #[derive(Component)]
-pub struct ComputeChunkMesh(pub Task<Option<ChunkMesh>>, pub IVec3);
+pub struct ComputeChunkMesh(pub Task<Option<ChunkMesh>>, pub ChunkPos);
 
 #[derive(Component)]
 pub struct ChunkMarker;
@@ -0,0 +1,335 @@
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+
+use crate::world::save::ByteReader;
+
+// just enough of minecraft's named binary tag format (big endian, gzipped) for schematics
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(BTreeMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(tags) => tags.get(name),
+            _ => None,
+        }
+    }
+
+    /// any integer tag
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value as i64),
+            Tag::Short(value) => Some(value as i64),
+            Tag::Int(value) => Some(value as i64),
+            Tag::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&BTreeMap<String, Tag>> {
+        match self {
+            Tag::Compound(tags) => Some(tags),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Tag::ByteArray(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_ints(&self) -> Option<&[i32]> {
+        match self {
+            Tag::IntArray(ints) => Some(ints),
+            _ => None,
+        }
+    }
+}
+
+// far more than the biggest schematic the editor pastes, a few kilobytes of gzip can
+// otherwise unpack into gigabytes
+const MAX_DECOMPRESSED_SIZE: u64 = 64 << 20;
+
+/// reads a gzipped file, returns the root tag's name and the tag
+pub fn read(bytes: &[u8]) -> Result<(String, Tag), String> {
+    let mut data = Vec::new();
+    GzDecoder::new(bytes)
+        .take(MAX_DECOMPRESSED_SIZE + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("not gzipped: {e}"))?;
+    if data.len() as u64 > MAX_DECOMPRESSED_SIZE {
+        return Err(format!(
+            "more than {} MiB once decompressed",
+            MAX_DECOMPRESSED_SIZE >> 20
+        ));
+    }
+
+    let mut reader = ByteReader(&data);
+    let read_root = |reader: &mut ByteReader| {
+        if reader.take(1)?[0] != 10 {
+            return None;
+        }
+        let name = read_string(reader)?;
+        Some((name, read_payload(reader, 10, 0)?))
+    };
+    read_root(&mut reader).ok_or_else(|| "invalid nbt".to_string())
+}
+
+pub fn write(name: &str, tag: &Tag) -> Result<Vec<u8>, String> {
+    let mut data = vec![tag.id()];
+    write_string(&mut data, name);
+    write_payload(&mut data, tag);
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&data)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| format!("could not compress nbt: {e}"))
+}
+
+// deeper than anything real, only there so a broken file can't overflow the stack
+const MAX_DEPTH: usize = 64;
+
+fn read_payload(reader: &mut ByteReader, id: u8, depth: usize) -> Option<Tag> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    Some(match id {
+        1 => Tag::Byte(reader.take(1)?[0] as i8),
+        2 => Tag::Short(i16::from_be_bytes(reader.take(2)?.try_into().ok()?)),
+        3 => Tag::Int(read_i32(reader)?),
+        4 => Tag::Long(read_i64(reader)?),
+        5 => Tag::Float(f32::from_be_bytes(reader.take(4)?.try_into().ok()?)),
+        6 => Tag::Double(f64::from_be_bytes(reader.take(8)?.try_into().ok()?)),
+        7 => {
+            let len = read_len(reader)?;
+            Tag::ByteArray(reader.take(len)?.to_vec())
+        }
+        8 => Tag::String(read_string(reader)?),
+        9 => {
+            let id = reader.take(1)?[0];
+            let len = read_len(reader)?;
+            Tag::List(
+                (0..len)
+                    .map(|_| read_payload(reader, id, depth + 1))
+                    .collect::<Option<_>>()?,
+            )
+        }
+        10 => {
+            let mut tags = BTreeMap::new();
+            loop {
+                let id = reader.take(1)?[0];
+                if id == 0 {
+                    break;
+                }
+                let name = read_string(reader)?;
+                tags.insert(name, read_payload(reader, id, depth + 1)?);
+            }
+            Tag::Compound(tags)
+        }
+        11 => {
+            let len = read_len(reader)?;
+            Tag::IntArray((0..len).map(|_| read_i32(reader)).collect::<Option<_>>()?)
+        }
+        12 => {
+            let len = read_len(reader)?;
+            Tag::LongArray((0..len).map(|_| read_i64(reader)).collect::<Option<_>>()?)
+        }
+        _ => return None,
+    })
+}
+
+fn write_payload(data: &mut Vec<u8>, tag: &Tag) {
+    match tag {
+        Tag::Byte(value) => data.push(*value as u8),
+        Tag::Short(value) => data.extend_from_slice(&value.to_be_bytes()),
+        Tag::Int(value) => data.extend_from_slice(&value.to_be_bytes()),
+        Tag::Long(value) => data.extend_from_slice(&value.to_be_bytes()),
+        Tag::Float(value) => data.extend_from_slice(&value.to_be_bytes()),
+        Tag::Double(value) => data.extend_from_slice(&value.to_be_bytes()),
+        Tag::ByteArray(bytes) => {
+            data.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+            data.extend_from_slice(bytes);
+        }
+        Tag::String(string) => write_string(data, string),
+        Tag::List(tags) => {
+            // empty lists are written as lists of end tags
+            data.push(tags.first().map_or(0, Tag::id));
+            data.extend_from_slice(&(tags.len() as i32).to_be_bytes());
+            for tag in tags {
+                write_payload(data, tag);
+            }
+        }
+        Tag::Compound(tags) => {
+            for (name, tag) in tags {
+                data.push(tag.id());
+                write_string(data, name);
+                write_payload(data, tag);
+            }
+            data.push(0);
+        }
+        Tag::IntArray(ints) => {
+            data.extend_from_slice(&(ints.len() as i32).to_be_bytes());
+            for value in ints {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        Tag::LongArray(longs) => {
+            data.extend_from_slice(&(longs.len() as i32).to_be_bytes());
+            for value in longs {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+    }
+}
+
+#[inline]
+fn read_i32(reader: &mut ByteReader) -> Option<i32> {
+    Some(i32::from_be_bytes(reader.take(4)?.try_into().ok()?))
+}
+
+#[inline]
+fn read_i64(reader: &mut ByteReader) -> Option<i64> {
+    Some(i64::from_be_bytes(reader.take(8)?.try_into().ok()?))
+}
+
+// array and list lengths, negative ones are broken
+#[inline]
+fn read_len(reader: &mut ByteReader) -> Option<usize> {
+    usize::try_from(read_i32(reader)?).ok()
+}
+
+// nbt strings are java's modified utf-8, which only differs from utf-8 for \0 and characters
+// outside the bmp, neither of which show up in block ids
+fn read_string(reader: &mut ByteReader) -> Option<String> {
+    let len = u16::from_be_bytes(reader.take(2)?.try_into().ok()?) as usize;
+    String::from_utf8(reader.take(len)?.to_vec()).ok()
+}
+
+fn write_string(data: &mut Vec<u8>, string: &str) {
+    data.extend_from_slice(&(string.len() as u16).to_be_bytes());
+    data.extend_from_slice(string.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Tag {
+        Tag::Compound(BTreeMap::from([
+            ("byte".into(), Tag::Byte(-3)),
+            ("short".into(), Tag::Short(-300)),
+            ("int".into(), Tag::Int(70000)),
+            ("long".into(), Tag::Long(-1 << 40)),
+            ("float".into(), Tag::Float(0.5)),
+            ("double".into(), Tag::Double(-2.25)),
+            ("bytes".into(), Tag::ByteArray(vec![1, 2, 255])),
+            ("string".into(), Tag::String("minecraft:stone".into())),
+            ("list".into(), Tag::List(vec![Tag::Short(1), Tag::Short(2)])),
+            ("empty list".into(), Tag::List(Vec::new())),
+            (
+                "compound".into(),
+                Tag::Compound(BTreeMap::from([("inner".into(), Tag::Int(1))])),
+            ),
+            ("ints".into(), Tag::IntArray(vec![-1, 0, 1])),
+            ("longs".into(), Tag::LongArray(vec![i64::MIN, i64::MAX])),
+        ]))
+    }
+
+    // raw nbt the way files store it
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = write("root", &sample()).unwrap();
+        assert_eq!(read(&bytes).unwrap(), ("root".to_string(), sample()));
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        let mut data = vec![10, 0, 0];
+        write_payload(&mut data, &sample());
+        for len in 0..data.len() {
+            assert!(read(&gzip(&data[..len])).is_err());
+        }
+        assert!(read(&gzip(&data)).is_ok());
+        assert!(read(b"not gzipped").is_err());
+    }
+
+    #[test]
+    fn oversized_lengths_are_errors() {
+        // a byte array, an int array and a list claiming 2^31 - 1 entries with none there
+        for payload in [
+            &[7, 0, 1, b'a', 0x7f, 0xff, 0xff, 0xff][..],
+            &[11, 0, 1, b'a', 0x7f, 0xff, 0xff, 0xff],
+            &[9, 0, 1, b'a', 3, 0x7f, 0xff, 0xff, 0xff],
+            // negative
+            &[7, 0, 1, b'a', 0xff, 0xff, 0xff, 0xff],
+        ] {
+            let data = [&[10, 0, 0][..], payload, &[0]].concat();
+            assert!(read(&gzip(&data)).is_err());
+        }
+    }
+
+    #[test]
+    fn decompressed_size_is_limited() {
+        // an empty root compound, the rest is never read
+        let mut data = vec![0; MAX_DECOMPRESSED_SIZE as usize];
+        data[0] = 10;
+        assert!(read(&gzip(&data)).is_ok());
+        data.push(0);
+        assert!(read(&gzip(&data)).is_err());
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let mut tag = Tag::Int(0);
+        for _ in 0..MAX_DEPTH + 2 {
+            tag = Tag::List(vec![tag]);
+        }
+        let bytes = write("", &Tag::Compound(BTreeMap::from([("deep".into(), tag)]))).unwrap();
+        assert!(read(&bytes).is_err());
+    }
+}
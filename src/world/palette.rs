@@ -0,0 +1,272 @@
+use crate::{CHUNK_SIZE, world::coords::LocalPos};
+
+const VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// Per-chunk storage that only pays for the values it actually contains.
+///
+/// Uniform chunks (all air, all stone, ...) are a single value. Everything else is
+/// a palette of the distinct values plus a bit-packed index per voxel, using as few
+/// bits per index as the palette size allows.
+#[derive(Clone, Debug)]
+pub enum PalettedStorage<T> {
+    Single(T),
+    Paletted {
+        palette: Vec<T>,
+        bits: u32,
+        data: Vec<u64>,
+    },
+}
+
+impl<T: Copy + PartialEq + Default> Default for PalettedStorage<T> {
+    fn default() -> Self {
+        Self::Single(T::default())
+    }
+}
+
+impl<T: Copy + PartialEq> PalettedStorage<T> {
+    #[inline]
+    pub const fn new(value: T) -> Self {
+        Self::Single(value)
+    }
+
+    #[inline]
+    pub fn get(&self, pos: LocalPos) -> T {
+        self.get_index(pos.index())
+    }
+
+    #[inline]
+    pub fn set(&mut self, pos: LocalPos, value: T) {
+        self.set_index(pos.index(), value);
+    }
+
+    #[inline]
+    pub fn get_index(&self, index: usize) -> T {
+        match self {
+            Self::Single(value) => *value,
+            Self::Paletted {
+                palette,
+                bits,
+                data,
+            } => palette[read_packed(data, *bits, index)],
+        }
+    }
+
+    pub fn set_index(&mut self, index: usize, value: T) {
+        debug_assert!(index < VOLUME);
+
+        if let Self::Single(current) = self {
+            if *current == value {
+                return;
+            }
+            *self = Self::Paletted {
+                palette: vec![*current],
+                bits: 1,
+                data: vec![0; words_for(1)],
+            };
+        }
+
+        let Self::Paletted {
+            palette,
+            bits,
+            data,
+        } = self
+        else {
+            unreachable!()
+        };
+
+        let palette_index = match palette.iter().position(|v| *v == value) {
+            Some(i) => i,
+            None => {
+                palette.push(value);
+                if palette.len() > 1 << *bits {
+                    let new_bits = *bits + 1;
+                    *data = repack(data, *bits, new_bits);
+                    *bits = new_bits;
+                }
+                palette.len() - 1
+            }
+        };
+
+        write_packed(data, *bits, index, palette_index);
+    }
+
+    /// sets every voxel to `value`, dropping the palette
+    #[inline]
+    pub fn fill(&mut self, value: T) {
+        *self = Self::Single(value);
+    }
+
+    /// iterates over every voxel in index order
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..VOLUME).map(|i| self.get_index(i))
+    }
+
+    /// drops palette entries that are no longer used and shrinks the indices to match,
+    /// collapsing back to a single value when only one remains
+    pub fn compact(&mut self) {
+        let Self::Paletted {
+            palette,
+            bits,
+            data,
+        } = self
+        else {
+            return;
+        };
+
+        let mut used = vec![false; palette.len()];
+        for i in 0..VOLUME {
+            used[read_packed(data, *bits, i)] = true;
+        }
+
+        let mut remap = vec![0; palette.len()];
+        let mut new_palette = Vec::new();
+        for (i, value) in palette.iter().enumerate() {
+            if used[i] {
+                remap[i] = new_palette.len();
+                new_palette.push(*value);
+            }
+        }
+
+        if new_palette.len() == 1 {
+            *self = Self::Single(new_palette[0]);
+            return;
+        }
+        if new_palette.len() == palette.len() {
+            return;
+        }
+
+        let new_bits = bits_for(new_palette.len());
+        let mut new_data = vec![0; words_for(new_bits)];
+        for i in 0..VOLUME {
+            write_packed(
+                &mut new_data,
+                new_bits,
+                i,
+                remap[read_packed(data, *bits, i)],
+            );
+        }
+
+        *palette = new_palette;
+        *bits = new_bits;
+        *data = new_data;
+    }
+}
+
+#[inline]
+const fn bits_for(palette_len: usize) -> u32 {
+    let bits = usize::BITS - (palette_len - 1).leading_zeros();
+    if bits == 0 { 1 } else { bits }
+}
+
+// indices never straddle two words, so a few bits per word may go unused
+#[inline]
+const fn words_for(bits: u32) -> usize {
+    let per_word = (u64::BITS / bits) as usize;
+    VOLUME.div_ceil(per_word)
+}
+
+#[inline]
+fn read_packed(data: &[u64], bits: u32, index: usize) -> usize {
+    let per_word = (u64::BITS / bits) as usize;
+    let shift = (index % per_word) as u32 * bits;
+    ((data[index / per_word] >> shift) & ((1 << bits) - 1)) as usize
+}
+
+#[inline]
+fn write_packed(data: &mut [u64], bits: u32, index: usize, value: usize) {
+    let per_word = (u64::BITS / bits) as usize;
+    let shift = (index % per_word) as u32 * bits;
+    let mask = ((1u64 << bits) - 1) << shift;
+    let word = &mut data[index / per_word];
+    *word = (*word & !mask) | ((value as u64) << shift);
+}
+
+fn repack(data: &[u64], bits: u32, new_bits: u32) -> Vec<u64> {
+    let mut new_data = vec![0; words_for(new_bits)];
+    for i in 0..VOLUME {
+        write_packed(&mut new_data, new_bits, i, read_packed(data, bits, i));
+    }
+    new_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits<T>(storage: &PalettedStorage<T>) -> Option<u32> {
+        match storage {
+            PalettedStorage::Single(_) => None,
+            PalettedStorage::Paletted { bits, .. } => Some(*bits),
+        }
+    }
+
+    // spread out so neighbouring indices land in different words
+    fn scattered(i: usize) -> usize {
+        i * 7919 % VOLUME
+    }
+
+    #[test]
+    fn values_survive_every_bit_width() {
+        let mut storage = PalettedStorage::new(0u16);
+        let mut expected = vec![0u16; VOLUME];
+        storage.set_index(5, 0);
+        assert_eq!(bits(&storage), None);
+
+        // a new value every 100 voxels, up to a 256 value palette
+        for i in 0..255 * 100 {
+            let value = (i / 100 + 1) as u16;
+            let index = scattered(i);
+            storage.set_index(index, value);
+            expected[index] = value;
+
+            let palette_len = value as usize + 1;
+            assert_eq!(bits(&storage), Some(bits_for(palette_len)));
+            // right after every growth
+            if palette_len.is_power_of_two() || palette_len == 3 {
+                assert!(storage.iter().eq(expected.iter().copied()));
+            }
+        }
+        assert_eq!(bits(&storage), Some(8));
+        assert!(storage.iter().eq(expected.iter().copied()));
+    }
+
+    #[test]
+    fn compact_drops_unused_values() {
+        let mut storage = PalettedStorage::new(0u8);
+        for i in 0..VOLUME {
+            storage.set_index(scattered(i), (i % 20) as u8);
+        }
+        assert_eq!(bits(&storage), Some(5));
+
+        // three values left
+        for i in 0..VOLUME {
+            if i % 20 >= 3 {
+                storage.set_index(scattered(i), 1);
+            }
+        }
+        let expected = storage.iter().collect::<Vec<_>>();
+        storage.compact();
+        assert_eq!(bits(&storage), Some(2));
+        assert!(storage.iter().eq(expected.iter().copied()));
+
+        for i in 0..VOLUME {
+            storage.set_index(i, 7);
+        }
+        storage.compact();
+        assert!(matches!(storage, PalettedStorage::Single(7)));
+        assert!(storage.iter().all(|value| value == 7));
+    }
+
+    #[test]
+    fn every_voxel_can_be_different() {
+        let mut storage = PalettedStorage::new(u16::MAX);
+        for i in 0..VOLUME {
+            storage.set_index(i, i as u16);
+        }
+        // the value it started out with is still in the palette until it's compacted
+        assert_eq!(bits(&storage), Some(16));
+        storage.compact();
+        assert_eq!(bits(&storage), Some(15));
+        assert!(storage.iter().eq((0..VOLUME).map(|i| i as u16)));
+    }
+}
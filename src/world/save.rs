@@ -0,0 +1,554 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use toml_edit::{DocumentMut, value};
+
+use crate::{
+    CHUNK_SIZE,
+    ecs::*,
+    world::{
+        WorldData,
+        block::{Block, BlockRegistry},
+        coords::ChunkPos,
+        light::Light,
+        mesher::Chunk,
+        settings::DEFAULT_PRESET,
+    },
+};
+
+pub const DEFAULT_WORLD_DIR: &str = "saves/world";
+pub const REGION_SIZE: i32 = 16; // in chunks, on every axis
+
+const LEVEL_FILE: &str = "level.toml";
+const REGIONS_DIR: &str = "regions";
+const REGION_MAGIC: &[u8; 4] = b"FCRG";
+const REGION_VERSION: u32 = 4;
+const SAVE_VERSION: i64 = 1;
+const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+// regions kept in memory, the least recently used clean ones are dropped past this
+const MAX_CACHED_REGIONS: usize = 64;
+
+// world directory layout:
+// saves/<world>/level.toml              - metadata (seed, preset, version)
+// saves/<world>/regions/r.x.y.z.region  - modified chunks of a 16x16x16 chunk region
+//
+// every region has its own lock, so generation tasks reading different regions don't wait
+// for each other and the main thread only waits for the region it's storing a chunk in
+pub struct WorldSave {
+    pub dir: PathBuf,
+    pub seed: u32,
+    pub preset: String,
+    regions: Mutex<RegionCache>,
+}
+
+#[derive(Default)]
+struct RegionCache {
+    // with when they were last used
+    regions: HashMap<IVec3, (Arc<Mutex<Region>>, u64)>,
+    clock: u64,
+}
+
+#[derive(Default)]
+struct Region {
+    chunks: HashMap<ChunkPos, Chunk>,
+    // chunks saved without their light, by older versions
+    unlit: HashSet<ChunkPos>,
+    // read from disk yet
+    loaded: bool,
+    dirty: bool,
+}
+
+impl Default for WorldSave {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(DEFAULT_WORLD_DIR),
+            seed: 0,
+            preset: DEFAULT_PRESET.to_string(),
+            regions: Mutex::default(),
+        }
+    }
+}
+
+impl WorldSave {
+    /// opens the world at `dir`, creating it with `seed` and `preset` if it doesn't exist yet
+    pub fn open(dir: impl AsRef<Path>, seed: u32, preset: &str) -> Result<Self, String> {
+        let dir = dir.as_ref().to_path_buf();
+        let level_path = dir.join(LEVEL_FILE);
+
+        let mut save = Self {
+            dir,
+            seed,
+            preset: preset.to_string(),
+            regions: Mutex::default(),
+        };
+
+        if level_path.exists() {
+            let level = std::fs::read_to_string(&level_path)
+                .map_err(|_| format!("could not read {}", level_path.display()))?
+                .parse::<DocumentMut>()
+                .map_err(|e| format!("could not parse {}: {e}", level_path.display()))?;
+
+            let seed = level
+                .get("seed")
+                .and_then(|seed| seed.as_integer())
+                .ok_or_else(|| format!("{} is missing a seed", level_path.display()))?;
+            save.seed = u32::try_from(seed)
+                .map_err(|_| format!("{}: seed is out of range", level_path.display()))?;
+            // worlds from before presets existed
+            save.preset = level
+                .get("preset")
+                .and_then(|preset| preset.as_str())
+                .unwrap_or(DEFAULT_PRESET)
+                .to_string();
+        } else {
+            save.write_metadata()?;
+        }
+
+        Ok(save)
+    }
+
+    fn write_metadata(&self) -> Result<(), String> {
+        std::fs::create_dir_all(self.dir.join(REGIONS_DIR))
+            .map_err(|_| format!("could not create {}", self.dir.display()))?;
+
+        let mut level = DocumentMut::new();
+        level["version"] = value(SAVE_VERSION);
+        level["seed"] = value(self.seed as i64);
+        level["preset"] = value(&self.preset);
+
+        let level_path = self.dir.join(LEVEL_FILE);
+        std::fs::write(&level_path, level.to_string())
+            .map_err(|_| format!("could not write {}", level_path.display()))
+    }
+
+    #[inline]
+    pub fn region_pos(chunk_pos: ChunkPos) -> IVec3 {
+        chunk_pos.div_euclid(IVec3::splat(REGION_SIZE))
+    }
+
+    fn region_path(&self, region_pos: IVec3) -> PathBuf {
+        self.dir.join(REGIONS_DIR).join(format!(
+            "r.{}.{}.{}.region",
+            region_pos.x, region_pos.y, region_pos.z
+        ))
+    }
+
+    // the region's lock, it's read from disk by whoever locks it first
+    fn region(&self, region_pos: IVec3) -> Arc<Mutex<Region>> {
+        let mut cache = self.regions.lock().unwrap();
+        cache.clock += 1;
+        let clock = cache.clock;
+        if let Some((region, last_used)) = cache.regions.get_mut(&region_pos) {
+            *last_used = clock;
+            return region.clone();
+        }
+
+        cache.evict();
+        let region = Arc::new(Mutex::new(Region::default()));
+        cache.regions.insert(region_pos, (region.clone(), clock));
+        region
+    }
+
+    fn with_region<T>(&self, region_pos: IVec3, f: impl FnOnce(&mut Region) -> T) -> T {
+        let region = self.region(region_pos);
+        let mut region = region.lock().unwrap();
+        if !region.loaded {
+            let path = self.region_path(region_pos);
+            if path.exists() {
+                *region = Region::load(&path, region_pos).unwrap_or_else(|e| {
+                    eprintln!("{e}");
+                    Region::default()
+                });
+            }
+            region.loaded = true;
+        }
+        f(&mut region)
+    }
+
+    /// returns the saved copy of a chunk if it was ever modified, and whether its light was
+    /// saved with it (it has to be lit again if not)
+    pub fn load_chunk(&self, pos: ChunkPos) -> Option<(Chunk, bool)> {
+        self.with_region(Self::region_pos(pos), |region| {
+            let chunk = region.chunks.get(&pos)?.clone();
+            Some((chunk, !region.unlit.contains(&pos)))
+        })
+    }
+
+    /// keeps a modified chunk around until the next flush, with its light
+    pub fn store_chunk(&self, chunk: Chunk) {
+        self.with_region(Self::region_pos(chunk.pos), |region| {
+            region.dirty = true;
+            region.unlit.remove(&chunk.pos);
+            region.chunks.insert(chunk.pos, chunk);
+        });
+    }
+
+    /// writes every region that changed since the last flush to disk
+    pub fn flush(&self) -> Result<(), String> {
+        self.write_metadata()?;
+
+        let regions = self
+            .regions
+            .lock()
+            .unwrap()
+            .regions
+            .iter()
+            .map(|(pos, (region, _))| (*pos, region.clone()))
+            .collect::<Vec<_>>();
+
+        for (region_pos, region) in regions {
+            let mut region = region.lock().unwrap();
+            if region.dirty {
+                region.save(&self.region_path(region_pos), region_pos)?;
+                region.dirty = false;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl RegionCache {
+    // drops the least recently used regions that have nothing unsaved and aren't in use
+    fn evict(&mut self) {
+        while self.regions.len() >= MAX_CACHED_REGIONS {
+            let oldest = self
+                .regions
+                .iter()
+                .filter(|(_, (region, _))| {
+                    Arc::strong_count(region) == 1
+                        && region.try_lock().is_ok_and(|region| !region.dirty)
+                })
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(pos, _)| *pos);
+            let Some(oldest) = oldest else {
+                break;
+            };
+            self.regions.remove(&oldest);
+        }
+    }
+}
+
+// region file layout (little endian):
+// magic "FCRG" | version u32 | palette length u16 | per entry: name length u16 | name
+// chunk count u32
+// per chunk: local chunk index u16 | lit u8 | data length u32
+// rle data ((run u16, block u8, fluid u8, light u8)...)
+// the block byte is an index into the palette, the block names the region was saved with.
+// light is only kept for chunks that are lit, the others are lit again when they're loaded.
+// version 3 regions have no lit flag or light byte, version 1 and 2 regions have no palette
+// either and store registry ids, version 1 has no fluid byte either, its water is all still
+impl Region {
+    fn load(path: &Path, region_pos: IVec3) -> Result<Self, String> {
+        let bytes =
+            std::fs::read(path).map_err(|_| format!("could not read {}", path.display()))?;
+        Self::decode(&bytes, region_pos, &path.display().to_string())
+    }
+
+    // `name` is what errors call the region
+    fn decode(bytes: &[u8], region_pos: IVec3, name: &str) -> Result<Self, String> {
+        let corrupted = || format!("corrupted region file {name}");
+
+        let mut reader = ByteReader(bytes);
+        if reader.take(4).ok_or_else(corrupted)? != REGION_MAGIC {
+            return Err(corrupted());
+        }
+        let version = reader.u32().ok_or_else(corrupted)?;
+        if !(1..=REGION_VERSION).contains(&version) {
+            return Err(format!("unsupported region version {version} in {name}"));
+        }
+
+        let registry = BlockRegistry::global();
+        let palette = if version >= 3 {
+            let mut palette = Vec::new();
+            for _ in 0..reader.u16().ok_or_else(corrupted)? {
+                let len = reader.u16().ok_or_else(corrupted)? as usize;
+                let block_name = std::str::from_utf8(reader.take(len).ok_or_else(corrupted)?)
+                    .map_err(|_| corrupted())?;
+                // blocks removed from blocks.toml since
+                palette.push(registry.by_name(block_name).unwrap_or_else(|| {
+                    eprintln!("{name}: unknown block {block_name}, loading it as air");
+                    Block::AIR
+                }));
+            }
+            palette
+        } else {
+            (0..registry.blocks.len())
+                .map(|id| Block(id as u8))
+                .collect()
+        };
+
+        let mut region = Region {
+            loaded: true,
+            ..Default::default()
+        };
+        for _ in 0..reader.u32().ok_or_else(corrupted)? {
+            let index = reader.u16().ok_or_else(corrupted)? as i32;
+            let lit = version >= 4 && reader.take(1).ok_or_else(corrupted)?[0] != 0;
+            let len = reader.u32().ok_or_else(corrupted)? as usize;
+            let data = reader.take(len).ok_or_else(corrupted)?;
+            if index >= REGION_SIZE.pow(3) {
+                return Err(corrupted());
+            }
+
+            let local = ivec3(
+                index % REGION_SIZE,
+                (index / REGION_SIZE) % REGION_SIZE,
+                index / (REGION_SIZE * REGION_SIZE),
+            );
+            let chunk_pos = ChunkPos(region_pos * REGION_SIZE + local);
+            let chunk = decode_chunk(chunk_pos, data, version, &palette).ok_or_else(corrupted)?;
+            if region.chunks.insert(chunk_pos, chunk).is_some() {
+                return Err(corrupted());
+            }
+            if !lit {
+                region.unlit.insert(chunk_pos);
+            }
+        }
+
+        Ok(region)
+    }
+
+    fn save(&self, path: &Path, region_pos: IVec3) -> Result<(), String> {
+        let bytes = self.encode(region_pos);
+
+        // write to a temporary file first so a crash mid-save can't corrupt the region
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, bytes)
+            .map_err(|_| format!("could not write {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, path).map_err(|_| format!("could not write {}", path.display()))
+    }
+
+    fn encode(&self, region_pos: IVec3) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(REGION_MAGIC);
+        bytes.extend_from_slice(&REGION_VERSION.to_le_bytes());
+
+        // the whole registry, block ids are palette indices
+        let registry = BlockRegistry::global();
+        bytes.extend_from_slice(&(registry.blocks.len() as u16).to_le_bytes());
+        for info in &registry.blocks {
+            bytes.extend_from_slice(&(info.name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(info.name.as_bytes());
+        }
+
+        bytes.extend_from_slice(&(self.chunks.len() as u32).to_le_bytes());
+
+        for (pos, chunk) in &self.chunks {
+            let local = **pos - region_pos * REGION_SIZE;
+            let index = local.x + local.y * REGION_SIZE + local.z * REGION_SIZE * REGION_SIZE;
+            let data = encode_chunk(chunk);
+
+            bytes.extend_from_slice(&(index as u16).to_le_bytes());
+            bytes.push(!self.unlit.contains(pos) as u8);
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&data);
+        }
+        bytes
+    }
+}
+
+fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut run: Option<(u16, (Block, u8, Light))> = None;
+    let push = |data: &mut Vec<u8>, (len, (block, fluid, light)): (u16, (Block, u8, Light))| {
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&[block.0, fluid, light.0]);
+    };
+
+    let voxels = chunk
+        .blocks
+        .iter()
+        .zip(chunk.fluid.iter())
+        .zip(chunk.light.iter());
+    for ((block, fluid), light) in voxels {
+        let voxel = (block, fluid, light);
+        match &mut run {
+            Some((len, current)) if *current == voxel && *len < u16::MAX => *len += 1,
+            _ => {
+                if let Some(run) = run {
+                    push(&mut data, run);
+                }
+                run = Some((1, voxel));
+            }
+        }
+    }
+    if let Some(run) = run {
+        push(&mut data, run);
+    }
+
+    data
+}
+
+fn decode_chunk(pos: ChunkPos, data: &[u8], version: u32, palette: &[Block]) -> Option<Chunk> {
+    let mut chunk = Chunk::new(pos);
+    chunk.modified = true;
+
+    let mut reader = ByteReader(data);
+    let mut i = 0;
+    while !reader.0.is_empty() {
+        let len = reader.u16()? as usize;
+        let block = *palette.get(reader.take(1)?[0] as usize)?;
+        let fluid = if version >= 2 { reader.take(1)?[0] } else { 0 };
+        let light = if version >= 4 {
+            Light(reader.take(1)?[0])
+        } else {
+            Light::default()
+        };
+        if i + len > CHUNK_VOLUME {
+            return None;
+        }
+        for index in i..i + len {
+            chunk.blocks.set_index(index, block);
+            chunk.fluid.set_index(index, fluid);
+            chunk.light.set_index(index, light);
+        }
+        i += len;
+    }
+    chunk.blocks.compact();
+    chunk.fluid.compact();
+    chunk.light.compact();
+
+    (i == CHUNK_VOLUME).then_some(chunk)
+}
+
+pub(super) struct ByteReader<'a>(pub &'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    pub fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Some(head)
+    }
+    pub fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take(2)?.try_into().ok()?))
+    }
+    pub fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
+
+pub fn save_world(world_data: Res<WorldData>) {
+    let chunks = world_data.chunks.read().unwrap();
+    let save = &world_data.save;
+
+    for chunk in chunks.values().filter(|chunk| chunk.modified) {
+        save.store_chunk(chunk.clone());
+    }
+
+    match save.flush() {
+        Ok(()) => println!("saved world to {}", save.dir.display()),
+        Err(e) => eprintln!("failed to save world: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{block::BLOCKS_PATH, coords::LocalPos};
+
+    // a version 3 region with `palette`, every chunk filled with one palette index
+    fn region_bytes(palette: &[&str], chunks: &[(u16, u8)]) -> Vec<u8> {
+        let mut bytes = REGION_MAGIC.to_vec();
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+        for name in palette {
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        bytes.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+        for &(index, block) in chunks {
+            bytes.extend_from_slice(&index.to_le_bytes());
+            bytes.extend_from_slice(&4u32.to_le_bytes());
+            bytes.extend_from_slice(&(CHUNK_VOLUME as u16).to_le_bytes());
+            bytes.extend_from_slice(&[block, 0]);
+        }
+        bytes
+    }
+
+    fn blocks(region: &Region, pos: IVec3) -> Vec<Block> {
+        region.chunks[&ChunkPos(pos)].blocks.iter().collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        BlockRegistry::init(BLOCKS_PATH).unwrap();
+        let region_pos = ivec3(-1, 0, 2);
+        let pos = ChunkPos(region_pos * REGION_SIZE + ivec3(3, 15, 0));
+        let mut chunk = Chunk::new(pos);
+        for (i, block) in [Block::STONE, Block::WATER, Block::LEAF]
+            .into_iter()
+            .enumerate()
+        {
+            chunk
+                .blocks
+                .set(LocalPos::new(IVec3::splat(i as i32)), block);
+        }
+        chunk.fluid.set(LocalPos::new(IVec3::ONE), 3);
+        chunk.light.set(LocalPos::new(IVec3::ONE), Light(0xf3));
+        let unlit = ChunkPos(*pos + IVec3::X);
+
+        let mut region = Region::default();
+        region.chunks.insert(pos, chunk.clone());
+        region.chunks.insert(unlit, Chunk::new(unlit));
+        region.unlit.insert(unlit);
+        let loaded = Region::decode(&region.encode(region_pos), region_pos, "test").unwrap();
+        assert_eq!(
+            blocks(&loaded, *pos),
+            chunk.blocks.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            loaded.chunks[&pos].fluid.iter().collect::<Vec<_>>(),
+            chunk.fluid.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            loaded.chunks[&pos].light.iter().collect::<Vec<_>>(),
+            chunk.light.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(loaded.unlit, HashSet::from([unlit]));
+    }
+
+    #[test]
+    fn blocks_are_remapped_by_name() {
+        BlockRegistry::init(BLOCKS_PATH).unwrap();
+        let bytes = region_bytes(&["dirt", "stone", "ruby"], &[(0, 1), (1, 0), (2, 2)]);
+        let region = Region::decode(&bytes, IVec3::ZERO, "test").unwrap();
+        // from before light was saved
+        assert_eq!(region.unlit.len(), 3);
+        assert!(
+            blocks(&region, ivec3(0, 0, 0))
+                .iter()
+                .all(|&b| b == Block::STONE)
+        );
+        assert!(
+            blocks(&region, ivec3(1, 0, 0))
+                .iter()
+                .all(|&b| b == Block::DIRT)
+        );
+        // no longer in blocks.toml
+        assert!(
+            blocks(&region, ivec3(2, 0, 0))
+                .iter()
+                .all(|&b| b == Block::AIR)
+        );
+        // past the palette
+        assert!(Region::decode(&region_bytes(&["dirt"], &[(0, 1)]), IVec3::ZERO, "test").is_err());
+    }
+
+    #[test]
+    fn broken_chunk_indexes_are_rejected() {
+        BlockRegistry::init(BLOCKS_PATH).unwrap();
+        let decode =
+            |chunks| Region::decode(&region_bytes(&["stone"], chunks), IVec3::ZERO, "test");
+        assert!(decode(&[(4095, 0)]).is_ok());
+        // would be a chunk of another region
+        assert!(decode(&[(4096, 0)]).is_err());
+        assert!(decode(&[(7, 0), (7, 0)]).is_err());
+    }
+}
@@ -0,0 +1,647 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use toml_edit::{DocumentMut, Item};
+
+use crate::{
+    ecs::*,
+    world::{
+        block::{Block, BlockRegistry},
+        nbt::{self, Tag},
+        save::ByteReader,
+    },
+};
+
+pub const BLOCK_MAPPING_PATH: &str = "assets/schematics.toml";
+pub const SCHEMATICS_DIR: &str = "schematics";
+
+// the minecraft version sponge schematics are exported for (1.20.4), only used by other tools
+const DATA_VERSION: i32 = 3700;
+// what sponge calls blocks that aren't part of a schematic
+const STRUCTURE_VOID: &str = "minecraft:structure_void";
+// magicavoxel models can't be any bigger
+const MAX_VOX_SIZE: i32 = 256;
+const GRAY: [u8; 3] = [127, 127, 127];
+
+/// A box of blocks that can be saved, loaded and pasted into the world. `None` marks blocks
+/// that aren't part of it, pasting leaves the world alone there.
+#[derive(Clone, Debug, Default)]
+pub struct Schematic {
+    pub size: IVec3,
+    // x first, then z, then y (the same order sponge schematics use)
+    pub blocks: Vec<Option<Block>>,
+}
+
+impl Schematic {
+    pub fn new(size: IVec3) -> Self {
+        Self {
+            size,
+            blocks: vec![None; (size.x * size.y * size.z) as usize],
+        }
+    }
+
+    #[inline]
+    fn index(&self, pos: IVec3) -> usize {
+        (pos.x + pos.z * self.size.x + pos.y * self.size.x * self.size.z) as usize
+    }
+
+    #[inline]
+    pub fn get(&self, pos: IVec3) -> Option<Block> {
+        self.blocks[self.index(pos)]
+    }
+
+    #[inline]
+    pub fn set(&mut self, pos: IVec3, block: Option<Block>) {
+        let index = self.index(pos);
+        self.blocks[index] = block;
+    }
+
+    /// every block that is part of the schematic and where it is
+    pub fn iter(&self) -> impl Iterator<Item = (IVec3, Block)> + '_ {
+        let (width, length) = (self.size.x, self.size.z);
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, block)| {
+                let i = i as i32;
+                Some((
+                    ivec3(i % width, i / (width * length), (i / width) % length),
+                    (*block)?,
+                ))
+            })
+    }
+
+    /// turned clockwise (seen from above) around the y axis `quarter_turns` times
+    pub fn rotated(&self, quarter_turns: u32) -> Self {
+        let turns = quarter_turns % 4;
+        let max = self.size - 1;
+        let mut rotated = Self::new(if turns.is_multiple_of(2) {
+            self.size
+        } else {
+            self.size.zyx()
+        });
+        for (pos, block) in self.iter() {
+            let pos = match turns {
+                0 => pos,
+                1 => ivec3(max.z - pos.z, pos.y, pos.x),
+                2 => ivec3(max.x - pos.x, pos.y, max.z - pos.z),
+                _ => ivec3(pos.z, pos.y, max.x - pos.x),
+            };
+            rotated.set(pos, Some(block));
+        }
+        rotated
+    }
+
+    /// reads a .schem or .vox file depending on its extension
+    pub fn load(path: &Path, mapping: &BlockMapping) -> Result<Self, String> {
+        let bytes =
+            std::fs::read(path).map_err(|_| format!("could not read {}", path.display()))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("schem") => Self::from_sponge(&bytes, mapping),
+            Some("vox") => Self::from_vox(&bytes, mapping),
+            _ => Err(format!("{} isn't a .schem or .vox file", path.display())),
+        }
+        .map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// writes a .schem or .vox file depending on the extension of `path`
+    pub fn save(&self, path: &Path, mapping: &BlockMapping) -> Result<(), String> {
+        let bytes = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("schem") => self.to_sponge(mapping)?,
+            Some("vox") => self.to_vox(mapping)?,
+            _ => return Err(format!("{} isn't a .schem or .vox file", path.display())),
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|_| format!("could not create {}", dir.display()))?;
+        }
+        std::fs::write(path, bytes).map_err(|_| format!("could not write {}", path.display()))
+    }
+
+    // sponge schematic versions 1 to 3, gzipped nbt:
+    // v1/v2 - root "Schematic" { Width, Height, Length (shorts), Palette { id: index }, BlockData }
+    // v3    - root "" { Schematic { Width, Height, Length, Blocks { Palette, Data } } }
+    // block data is one varint palette index per block, x first, then z, then y
+    fn from_sponge(bytes: &[u8], mapping: &BlockMapping) -> Result<Self, String> {
+        let (_, root) = nbt::read(bytes)?;
+        let schematic = root.get("Schematic").unwrap_or(&root);
+        let version = schematic
+            .get("Version")
+            .and_then(Tag::as_int)
+            .ok_or("missing Version")?;
+        let (palette, data) = match version {
+            1 | 2 => (schematic.get("Palette"), schematic.get("BlockData")),
+            3 => {
+                let blocks = schematic.get("Blocks").ok_or("missing Blocks")?;
+                (blocks.get("Palette"), blocks.get("Data"))
+            }
+            _ => return Err(format!("unsupported sponge schematic version {version}")),
+        };
+        let palette = palette
+            .and_then(Tag::as_compound)
+            .ok_or("missing block palette")?;
+        let data = data.and_then(Tag::as_bytes).ok_or("missing block data")?;
+
+        // stored as signed shorts but meant as unsigned
+        let dimension = |name: &str| {
+            schematic
+                .get(name)
+                .and_then(Tag::as_int)
+                .map(|value| value as u16 as i32)
+                .ok_or_else(|| format!("missing {name}"))
+        };
+        let size = ivec3(
+            dimension("Width")?,
+            dimension("Height")?,
+            dimension("Length")?,
+        );
+        // every block takes at least a byte, checked before a broken size allocates gigabytes
+        if size.as_i64vec3().element_product() > data.len() as i64 {
+            return Err("block data is too short".into());
+        }
+        let mut result = Self::new(size);
+
+        let mut blocks = HashMap::new();
+        for (id, index) in palette {
+            let index = index.as_int().ok_or("invalid block palette")?;
+            blocks.insert(index, mapping.sponge_block(id));
+        }
+
+        let mut reader = ByteReader(data);
+        for block in result.blocks.iter_mut() {
+            let index = read_varint(&mut reader).ok_or("block data is too short")?;
+            *block = *blocks
+                .get(&index)
+                .ok_or_else(|| format!("block data uses {index}, which isn't in the palette"))?;
+        }
+        Ok(result)
+    }
+
+    fn to_sponge(&self, mapping: &BlockMapping) -> Result<Vec<u8>, String> {
+        if self.size.max_element() > u16::MAX as i32 {
+            return Err("schematics can't be larger than 65535 blocks on any axis".into());
+        }
+
+        let mut palette = HashMap::new();
+        let mut data = Vec::new();
+        for block in &self.blocks {
+            let id = block.map_or(STRUCTURE_VOID, |block| mapping.sponge_id(block));
+            let next = palette.len() as i64;
+            write_varint(&mut data, *palette.entry(id).or_insert(next));
+        }
+        let palette = palette
+            .into_iter()
+            .map(|(id, index)| (id.to_string(), Tag::Int(index as i32)))
+            .collect();
+
+        let blocks = Tag::Compound(BTreeMap::from([
+            ("Palette".into(), Tag::Compound(palette)),
+            ("Data".into(), Tag::ByteArray(data)),
+            ("BlockEntities".into(), Tag::List(Vec::new())),
+        ]));
+        let schematic = Tag::Compound(BTreeMap::from([
+            ("Version".into(), Tag::Int(3)),
+            ("DataVersion".into(), Tag::Int(DATA_VERSION)),
+            ("Width".into(), Tag::Short(self.size.x as u16 as i16)),
+            ("Height".into(), Tag::Short(self.size.y as u16 as i16)),
+            ("Length".into(), Tag::Short(self.size.z as u16 as i16)),
+            ("Offset".into(), Tag::IntArray(vec![0; 3])),
+            ("Blocks".into(), blocks),
+        ]));
+        nbt::write(
+            "",
+            &Tag::Compound(BTreeMap::from([("Schematic".into(), schematic)])),
+        )
+    }
+
+    // magicavoxel, little endian: "VOX " | version i32 | MAIN chunk, a chunk is
+    // id [u8; 4] | content length i32 | children length i32 | content | children
+    // MAIN holds SIZE (x, y, z i32), XYZI (count i32, then x, y, z, color u8 each) and RGBA
+    // (256 colors, color n is entry n - 1). Only the first model is read.
+    // z is up in magicavoxel, y is up here
+    fn from_vox(bytes: &[u8], mapping: &BlockMapping) -> Result<Self, String> {
+        let corrupted = || "corrupted vox file".to_string();
+        let mut reader = ByteReader(bytes);
+        if reader.take(4).ok_or_else(corrupted)? != b"VOX " {
+            return Err(corrupted());
+        }
+        reader.u32().ok_or_else(corrupted)?;
+
+        let mut size = None;
+        let mut voxels = None;
+        let mut palette = None;
+        while !reader.0.is_empty() {
+            let id = reader.take(4).ok_or_else(corrupted)?;
+            let content_len = reader.u32().ok_or_else(corrupted)? as usize;
+            // MAIN's children are read as if they came after it, they're the only chunks
+            reader.u32().ok_or_else(corrupted)?;
+            let mut content = ByteReader(reader.take(content_len).ok_or_else(corrupted)?);
+            match id {
+                b"SIZE" if size.is_none() => {
+                    let axes = (0..3)
+                        .map(|_| content.u32().map(|axis| axis as i32))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(corrupted)?;
+                    size = Some(IVec3::from_slice(&axes));
+                }
+                b"XYZI" if voxels.is_none() => {
+                    let count = content.u32().ok_or_else(corrupted)? as usize;
+                    voxels = Some(content.take(count * 4).ok_or_else(corrupted)?);
+                }
+                b"RGBA" => palette = Some(content.take(256 * 4).ok_or_else(corrupted)?),
+                _ => {}
+            }
+        }
+
+        let vox_size = size.ok_or("vox file without a model")?;
+        if vox_size.min_element() < 1 || vox_size.max_element() > MAX_VOX_SIZE {
+            return Err(corrupted());
+        }
+        let mut result = Self::new(vox_size.xzy());
+        let mut blocks = HashMap::new();
+        for voxel in voxels.ok_or_else(corrupted)?.chunks_exact(4) {
+            let pos = ivec3(voxel[0] as i32, voxel[1] as i32, voxel[2] as i32);
+            if pos.cmpge(vox_size).any() || voxel[3] == 0 {
+                continue;
+            }
+            let color = voxel[3] as usize;
+            let block = *blocks.entry(color).or_insert_with(|| match palette {
+                Some(palette) => {
+                    let rgb = &palette[(color - 1) * 4..][..3];
+                    mapping.vox_block([rgb[0], rgb[1], rgb[2]])
+                }
+                // files without a palette use the default one, whose colors aren't worth
+                // shipping, so the index is taken as a block id instead
+                None => mapping.block(color as u8).unwrap_or(mapping.fallback),
+            });
+            result.set(ivec3(pos.x, pos.z, vox_size.y - 1 - pos.y), Some(block));
+        }
+        Ok(result)
+    }
+
+    fn to_vox(&self, mapping: &BlockMapping) -> Result<Vec<u8>, String> {
+        if self.size.max_element() > MAX_VOX_SIZE {
+            return Err(format!(
+                "vox models can't be larger than {MAX_VOX_SIZE} blocks on any axis"
+            ));
+        }
+
+        // every block is written with its id as the color index, air isn't written at all
+        let mut xyzi = Vec::new();
+        for (pos, block) in self.iter().filter(|(_, block)| !block.is_air()) {
+            xyzi.extend_from_slice(&[
+                pos.x as u8,
+                (self.size.z - 1 - pos.z) as u8,
+                pos.y as u8,
+                block.0,
+            ]);
+        }
+        let mut rgba = vec![255; 256 * 4];
+        for id in 1..=255 {
+            let rgb = mapping
+                .block(id)
+                .map_or(GRAY, |block| mapping.vox_color(block));
+            rgba[(id as usize - 1) * 4..][..3].copy_from_slice(&rgb);
+        }
+
+        let mut children = Vec::new();
+        let vox_size = self.size.xzy();
+        write_vox_chunk(
+            &mut children,
+            b"SIZE",
+            &[vox_size.x, vox_size.y, vox_size.z]
+                .map(|axis| (axis as u32).to_le_bytes())
+                .concat(),
+        );
+        let count = (xyzi.len() as u32 / 4).to_le_bytes();
+        write_vox_chunk(&mut children, b"XYZI", &[&count[..], &xyzi].concat());
+        write_vox_chunk(&mut children, b"RGBA", &rgba);
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150u32.to_le_bytes());
+        bytes.extend_from_slice(b"MAIN");
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&(children.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&children);
+        Ok(bytes)
+    }
+}
+
+fn write_vox_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(content);
+}
+
+fn read_varint(reader: &mut ByteReader) -> Option<i64> {
+    let mut value = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = reader.take(1)?[0];
+        value |= ((byte & 0x7f) as i64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            data.push(byte);
+            return;
+        }
+        data.push(byte | 0x80);
+    }
+}
+
+/// How the blocks of other tools' palettes map to ours, loaded from
+/// [`BLOCK_MAPPING_PATH`].
+#[derive(Resource)]
+pub struct BlockMapping {
+    // whatever can't be mapped turns into this
+    fallback: Block,
+    sponge: HashMap<String, Block>,
+    // by block id
+    sponge_ids: Vec<Option<String>>,
+    vox_colors: Vec<Option<[u8; 3]>>,
+}
+
+impl BlockMapping {
+    /// maps nothing, everything turns into stone, for `block_count` blocks
+    pub fn empty(block_count: usize) -> Self {
+        Self {
+            fallback: Block::STONE,
+            sponge: HashMap::new(),
+            sponge_ids: vec![None; block_count],
+            vox_colors: vec![None; block_count],
+        }
+    }
+
+    pub fn load(path: &str, registry: &BlockRegistry) -> Result<Self, String> {
+        let doc = std::fs::read_to_string(path)
+            .map_err(|_| format!("could not read {path}"))?
+            .parse::<DocumentMut>()
+            .map_err(|e| format!("could not parse {path}: {e}"))?;
+        let block = |name: &str| {
+            registry
+                .by_name(name)
+                .ok_or_else(|| format!("{path}: unknown block {name}"))
+        };
+
+        let mut mapping = Self {
+            fallback: block(
+                doc.get("fallback")
+                    .and_then(Item::as_str)
+                    .ok_or_else(|| format!("{path}: fallback"))?,
+            )?,
+            ..Self::empty(registry.blocks.len())
+        };
+
+        if let Some(ids) = doc
+            .get("sponge")
+            .and_then(|sponge| sponge.get("ids"))
+            .and_then(Item::as_table_like)
+        {
+            for (name, ids) in ids.iter() {
+                let block = block(name)?;
+                let ids = ids
+                    .as_array()
+                    .and_then(|ids| ids.iter().map(|id| id.as_str()).collect::<Option<Vec<_>>>())
+                    .ok_or_else(|| format!("{path}: sponge.ids.{name} isn't a list of ids"))?;
+                for id in &ids {
+                    if let Some(other) = mapping.sponge.insert(id.to_string(), block)
+                        && other != block
+                    {
+                        return Err(format!("{path}: {id} is mapped to two blocks"));
+                    }
+                }
+                mapping.sponge_ids[block.0 as usize] = ids.first().map(|id| id.to_string());
+            }
+        }
+
+        if let Some(colors) = doc
+            .get("vox")
+            .and_then(|vox| vox.get("colors"))
+            .and_then(Item::as_table_like)
+        {
+            for (name, color) in colors.iter() {
+                let block = block(name)?;
+                let color = color
+                    .as_str()
+                    .filter(|color| color.len() == 6)
+                    .and_then(|color| u32::from_str_radix(color, 16).ok())
+                    .ok_or_else(|| format!("{path}: vox.colors.{name} isn't a rrggbb color"))?;
+                let [_, r, g, b] = color.to_be_bytes();
+                mapping.vox_colors[block.0 as usize] = Some([r, g, b]);
+            }
+        }
+
+        Ok(mapping)
+    }
+
+    // the block with that id if the registry the mapping was loaded for has it
+    fn block(&self, id: u8) -> Option<Block> {
+        ((id as usize) < self.sponge_ids.len()).then_some(Block(id))
+    }
+
+    // block states ("minecraft:oak_log[axis=y]") don't matter here
+    fn sponge_block(&self, id: &str) -> Option<Block> {
+        let id = id.split('[').next().unwrap_or(id);
+        if id == STRUCTURE_VOID {
+            return None;
+        }
+        Some(self.sponge.get(id).copied().unwrap_or(self.fallback))
+    }
+
+    fn sponge_id(&self, block: Block) -> &str {
+        self.sponge_ids[block.0 as usize]
+            .as_deref()
+            .or_else(|| self.sponge_ids[self.fallback.0 as usize].as_deref())
+            .unwrap_or("minecraft:stone")
+    }
+
+    fn vox_block(&self, rgb: [u8; 3]) -> Block {
+        let distance = |color: [u8; 3]| {
+            (0..3)
+                .map(|i| (color[i] as i32 - rgb[i] as i32).pow(2))
+                .sum::<i32>()
+        };
+        self.vox_colors
+            .iter()
+            .enumerate()
+            .filter_map(|(id, color)| Some((Block(id as u8), distance((*color)?))))
+            .min_by_key(|(_, distance)| *distance)
+            .map_or(self.fallback, |(block, _)| block)
+    }
+
+    fn vox_color(&self, block: Block) -> [u8; 3] {
+        self.vox_colors[block.0 as usize].unwrap_or(GRAY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 3 wide, 2 high and 2 long, with a different block (or none) everywhere
+    fn sample() -> Schematic {
+        let mut schematic = Schematic::new(ivec3(3, 2, 2));
+        for (i, block) in schematic.blocks.iter_mut().enumerate() {
+            *block = (i != 4).then_some(Block(i as u8 + 1));
+        }
+        schematic
+    }
+
+    fn same(a: &Schematic, b: &Schematic) -> bool {
+        a.size == b.size && a.blocks == b.blocks
+    }
+
+    // stone and dirt both ways, anything else turns into stone
+    fn mapping() -> BlockMapping {
+        let mut mapping = BlockMapping::empty(16);
+        for (block, id, color) in [
+            (Block::STONE, "minecraft:stone", [120, 120, 120]),
+            (Block::DIRT, "minecraft:dirt", [130, 90, 60]),
+        ] {
+            mapping.sponge.insert(id.to_string(), block);
+            mapping.sponge_ids[block.0 as usize] = Some(id.to_string());
+            mapping.vox_colors[block.0 as usize] = Some(color);
+        }
+        mapping
+    }
+
+    // stone and dirt only, the formats can't tell every block apart with a small mapping
+    fn mapped_sample() -> Schematic {
+        let mut schematic = sample();
+        for block in schematic.blocks.iter_mut().flatten() {
+            *block = if block.0 % 2 == 0 {
+                Block::DIRT
+            } else {
+                Block::STONE
+            };
+        }
+        schematic
+    }
+
+    #[test]
+    fn quarter_turns_move_corners_clockwise() {
+        let schematic = sample();
+        let turned = schematic.rotated(1);
+        assert_eq!(turned.size, ivec3(2, 2, 3));
+        // x goes to z and z to -x
+        assert_eq!(turned.get(ivec3(1, 0, 0)), schematic.get(ivec3(0, 0, 0)));
+        assert_eq!(turned.get(ivec3(1, 0, 2)), schematic.get(ivec3(2, 0, 0)));
+        assert_eq!(turned.get(ivec3(0, 1, 2)), schematic.get(ivec3(2, 1, 1)));
+        // blocks that aren't part of it stay that way
+        assert_eq!(
+            turned.blocks.iter().filter(|block| block.is_none()).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn turns_add_up() {
+        let schematic = sample();
+        assert!(same(&schematic.rotated(0), &schematic));
+        assert!(same(&schematic.rotated(4), &schematic));
+        assert!(same(
+            &schematic.rotated(1).rotated(1),
+            &schematic.rotated(2)
+        ));
+        assert!(same(&schematic.rotated(3).rotated(1), &schematic));
+        assert!(same(&schematic.rotated(7), &schematic.rotated(3)));
+    }
+
+    #[test]
+    fn sponge_round_trip() {
+        let mapping = mapping();
+        for turns in 0..4 {
+            let schematic = mapped_sample().rotated(turns);
+            let bytes = schematic.to_sponge(&mapping).unwrap();
+            let loaded = Schematic::from_sponge(&bytes, &mapping).unwrap();
+            assert!(same(&loaded, &schematic));
+        }
+    }
+
+    #[test]
+    fn vox_round_trip() {
+        let mapping = mapping();
+        for turns in 0..4 {
+            let schematic = mapped_sample().rotated(turns);
+            let bytes = schematic.to_vox(&mapping).unwrap();
+            let loaded = Schematic::from_vox(&bytes, &mapping).unwrap();
+            assert!(same(&loaded, &schematic));
+        }
+    }
+
+    // magicavoxel's y goes the other way than our z
+    #[test]
+    fn vox_y_is_flipped_into_z() {
+        let mut children = Vec::new();
+        write_vox_chunk(
+            &mut children,
+            b"SIZE",
+            &[2u32, 3, 1].map(u32::to_le_bytes).concat(),
+        );
+        write_vox_chunk(
+            &mut children,
+            b"XYZI",
+            &[&1u32.to_le_bytes()[..], &[1, 0, 0, Block::DIRT.0]].concat(),
+        );
+        let bytes = [b"VOX ".as_slice(), &150u32.to_le_bytes(), &children].concat();
+
+        let loaded = Schematic::from_vox(&bytes, &mapping()).unwrap();
+        assert_eq!(loaded.size, ivec3(2, 1, 3));
+        assert_eq!(loaded.get(ivec3(1, 0, 2)), Some(Block::DIRT));
+        assert_eq!(loaded.iter().count(), 1);
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let mapping = mapping();
+        let sponge = mapped_sample().to_sponge(&mapping).unwrap();
+        let vox = mapped_sample().to_vox(&mapping).unwrap();
+        for len in 0..sponge.len() {
+            assert!(Schematic::from_sponge(&sponge[..len], &mapping).is_err());
+        }
+        // the palette is optional, cutting it off right before it leaves a valid file
+        let palette_start = vox.len() - 12 - 256 * 4;
+        for len in (0..vox.len()).filter(|&len| len != palette_start) {
+            assert!(Schematic::from_vox(&vox[..len], &mapping).is_err());
+        }
+        assert!(Schematic::from_vox(&vox[..palette_start], &mapping).is_ok());
+    }
+
+    #[test]
+    fn oversized_headers_are_errors() {
+        let mapping = mapping();
+
+        // 65535^3 blocks with a few bytes of data
+        let schematic = Tag::Compound(BTreeMap::from([
+            ("Version".into(), Tag::Int(2)),
+            ("Width".into(), Tag::Short(-1)),
+            ("Height".into(), Tag::Short(-1)),
+            ("Length".into(), Tag::Short(-1)),
+            ("Palette".into(), Tag::Compound(BTreeMap::new())),
+            ("BlockData".into(), Tag::ByteArray(vec![0; 8])),
+        ]));
+        let bytes = nbt::write("Schematic", &schematic).unwrap();
+        assert!(Schematic::from_sponge(&bytes, &mapping).is_err());
+
+        let vox = |size: [u32; 3], count: u32| {
+            let mut children = Vec::new();
+            write_vox_chunk(&mut children, b"SIZE", &size.map(u32::to_le_bytes).concat());
+            write_vox_chunk(&mut children, b"XYZI", &count.to_le_bytes());
+            [b"VOX ".as_slice(), &150u32.to_le_bytes(), &children].concat()
+        };
+        assert!(Schematic::from_vox(&vox([1000, 1, 1], 0), &mapping).is_err());
+        assert!(Schematic::from_vox(&vox([0, 1, 1], 0), &mapping).is_err());
+        assert!(Schematic::from_vox(&vox([4, 4, 4], u32::MAX), &mapping).is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use std::sync::Arc;
+
+use noise::{Fbm, MultiFractal, Simplex};
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::{
+    CHUNK_SIZE, SEA_LEVEL,
+    ecs::*,
+    world::{NoiseFunctions, biome::Biome, block::Block},
+};
+
+pub const PRESETS_DIR: &str = "assets/presets";
+pub const DEFAULT_PRESET: &str = "default";
+
+#[derive(Clone, Copy, Debug)]
+pub struct NoiseSettings {
+    pub frequency: f64,
+    pub octaves: usize,
+    pub persistence: f64,
+    pub lacunarity: f64,
+}
+
+impl NoiseSettings {
+    const fn new(frequency: f64, octaves: usize) -> Self {
+        Self {
+            frequency,
+            octaves,
+            persistence: 0.5,
+            lacunarity: 2.0,
+        }
+    }
+
+    pub fn build(&self, seed: u32) -> Fbm<Simplex> {
+        Fbm::<Simplex>::new(seed)
+            .set_frequency(self.frequency)
+            .set_persistence(self.persistence)
+            .set_octaves(self.octaves)
+            .set_lacunarity(self.lacunarity)
+    }
+}
+
+/// the range a biome's surface can be in, relative to the sea level
+#[derive(Clone, Copy, Debug)]
+pub struct HeightProfile {
+    pub min_height: f32,
+    pub max_height: f32,
+    pub flattening_exp: f32,
+}
+
+impl HeightProfile {
+    const fn new(min_height: f32, max_height: f32, flattening_exp: f32) -> Self {
+        Self {
+            min_height,
+            max_height,
+            flattening_exp,
+        }
+    }
+}
+
+/// Everything world generation can be tuned with. Loaded from `assets/presets/<name>.toml`,
+/// any value a preset leaves out keeps its default.
+#[derive(Resource, Clone, Debug)]
+pub struct WorldGenSettings {
+    pub preset: String,
+    pub seed: u32,
+    pub terrain: NoiseSettings,
+    pub continentalness: NoiseSettings,
+    pub temperature: NoiseSettings,
+    pub humidity: NoiseSettings,
+    pub cave: NoiseSettings,
+    pub worm: NoiseSettings,
+    pub overhang: NoiseSettings,
+    pub ravine: NoiseSettings,
+    pub heights: [HeightProfile; Biome::ALL.len()], // indexed by Biome
+    // stretches everything above the sea level
+    pub height_scale: f32,
+    pub flat_height: Option<i32>,
+    // the bedrock floor and the build limit (exclusive), None leaves that side unbounded
+    pub min_y: Option<i32>,
+    pub max_y: Option<i32>,
+    pub only_biome: Option<Biome>,
+    pub caves: bool,
+    pub structures: bool,
+}
+
+impl Default for WorldGenSettings {
+    fn default() -> Self {
+        Self {
+            preset: DEFAULT_PRESET.to_string(),
+            seed: 1337,
+            terrain: NoiseSettings::new(0.002, 4),
+            continentalness: NoiseSettings {
+                persistence: 0.6,
+                ..NoiseSettings::new(0.0001, 3)
+            },
+            temperature: NoiseSettings::new(0.0005, 2),
+            humidity: NoiseSettings::new(0.0005, 2),
+            cave: NoiseSettings::new(0.015, 2),
+            worm: NoiseSettings::new(0.01, 1),
+            overhang: NoiseSettings::new(0.02, 2),
+            ravine: NoiseSettings::new(0.003, 1),
+            heights: [
+                HeightProfile::new(-40.0, 5.0, 4.0),  // ocean
+                HeightProfile::new(6.0, 30.0, 2.5),   // desert
+                HeightProfile::new(10.0, 40.0, 3.0),  // plains
+                HeightProfile::new(10.0, 55.0, 2.5),  // forest
+                HeightProfile::new(8.0, 35.0, 3.0),   // tundra
+                HeightProfile::new(50.0, 180.0, 1.5), // mountains
+            ],
+            height_scale: 1.0,
+            flat_height: None,
+            // where bedrock always was, so worlds from before the limits generate the same
+            min_y: Some(0),
+            max_y: None,
+            only_biome: None,
+            caves: true,
+            structures: true,
+        }
+    }
+}
+
+impl WorldGenSettings {
+    pub fn load_preset(name: &str) -> Result<Self, String> {
+        let path = format!("{PRESETS_DIR}/{name}.toml");
+        let doc = std::fs::read_to_string(&path)
+            .map_err(|_| format!("could not read preset {path}"))?
+            .parse::<DocumentMut>()
+            .map_err(|e| format!("could not parse {path}: {e}"))?;
+
+        let mut settings = Self {
+            preset: name.to_string(),
+            ..Default::default()
+        };
+
+        if let Some(seed) = doc.get("seed").and_then(Item::as_integer) {
+            settings.seed =
+                u32::try_from(seed).map_err(|_| format!("{path}: seed is out of range"))?;
+        }
+        if let Some(scale) = get_float(doc.as_table(), "height_scale") {
+            settings.height_scale = scale as f32;
+        }
+        if let Some(height) = doc.get("flat_height").and_then(Item::as_integer) {
+            settings.flat_height = Some(height as i32);
+        }
+        if doc.get("unbounded").and_then(Item::as_bool) == Some(true) {
+            settings.min_y = None;
+            settings.max_y = None;
+        }
+        if let Some(min_y) = doc.get("min_y").and_then(Item::as_integer) {
+            settings.min_y = Some(min_y as i32);
+        }
+        if let Some(max_y) = doc.get("max_y").and_then(Item::as_integer) {
+            settings.max_y = Some(max_y as i32);
+        }
+        if let (Some(min_y), Some(max_y)) = (settings.min_y, settings.max_y)
+            && min_y >= max_y
+        {
+            return Err(format!("{path}: min_y has to be below max_y"));
+        }
+        if let Some(biome) = doc.get("only_biome").and_then(Item::as_str) {
+            settings.only_biome = Some(
+                Biome::from_name(biome).ok_or_else(|| format!("{path}: unknown biome {biome}"))?,
+            );
+        }
+        if let Some(caves) = doc.get("caves").and_then(Item::as_bool) {
+            settings.caves = caves;
+        }
+        if let Some(structures) = doc.get("structures").and_then(Item::as_bool) {
+            settings.structures = structures;
+        }
+
+        if let Some(noise) = doc.get("noise").and_then(Item::as_table) {
+            for (key, noise_settings) in [
+                ("terrain", &mut settings.terrain),
+                ("continentalness", &mut settings.continentalness),
+                ("temperature", &mut settings.temperature),
+                ("humidity", &mut settings.humidity),
+                ("cave", &mut settings.cave),
+                ("worm", &mut settings.worm),
+                ("overhang", &mut settings.overhang),
+                ("ravine", &mut settings.ravine),
+            ] {
+                let Some(table) = noise.get(key).and_then(Item::as_table) else {
+                    continue;
+                };
+                if let Some(frequency) = get_float(table, "frequency") {
+                    noise_settings.frequency = frequency;
+                }
+                if let Some(octaves) = table.get("octaves").and_then(Item::as_integer) {
+                    if !(1..=Fbm::<Simplex>::MAX_OCTAVES as i64).contains(&octaves) {
+                        return Err(format!("{path}: noise.{key}.octaves is out of range"));
+                    }
+                    noise_settings.octaves = octaves as usize;
+                }
+                if let Some(persistence) = get_float(table, "persistence") {
+                    noise_settings.persistence = persistence;
+                }
+                if let Some(lacunarity) = get_float(table, "lacunarity") {
+                    noise_settings.lacunarity = lacunarity;
+                }
+            }
+        }
+
+        if let Some(biomes) = doc.get("biomes").and_then(Item::as_table) {
+            for (name, table) in biomes.iter() {
+                let biome = Biome::from_name(name)
+                    .ok_or_else(|| format!("{path}: unknown biome {name}"))?;
+                let table = table
+                    .as_table()
+                    .ok_or_else(|| format!("{path}: biomes.{name} has to be a table"))?;
+                let profile = &mut settings.heights[biome as usize];
+                if let Some(min) = get_float(table, "min_height") {
+                    profile.min_height = min as f32;
+                }
+                if let Some(max) = get_float(table, "max_height") {
+                    profile.max_height = max as f32;
+                }
+                if let Some(exp) = get_float(table, "flattening_exp") {
+                    profile.flattening_exp = exp as f32;
+                }
+            }
+        }
+
+        Ok(settings)
+    }
+
+    pub fn build_noises(&self) -> NoiseFunctions {
+        let seed = self.seed;
+        NoiseFunctions {
+            seed,
+            terrain: self.terrain.build(seed),
+            biome: self.continentalness.build(seed.wrapping_add(1)),
+            cave: self.cave.build(seed.wrapping_add(2)),
+            worm_a: self.worm.build(seed.wrapping_add(3)),
+            worm_b: self.worm.build(seed.wrapping_add(4)),
+            overhang: self.overhang.build(seed.wrapping_add(5)),
+            ravine: self.ravine.build(seed.wrapping_add(6)),
+            temperature: self.temperature.build(seed.wrapping_add(7)),
+            humidity: self.humidity.build(seed.wrapping_add(8)),
+            settings: Arc::new(self.clone()),
+        }
+    }
+
+    #[inline]
+    pub fn contains_y(&self, y: i32) -> bool {
+        self.min_y.is_none_or(|min_y| y >= min_y) && self.max_y.is_none_or(|max_y| y < max_y)
+    }
+
+    /// the chunk layers that have anything in them
+    #[inline]
+    pub fn chunk_y_range(&self) -> (i32, i32) {
+        (
+            self.min_y.map_or(i32::MIN, |y| y.div_euclid(CHUNK_SIZE)),
+            self.max_y
+                .map_or(i32::MAX, |y| (y - 1).div_euclid(CHUNK_SIZE)),
+        )
+    }
+
+    /// bedrock at the bottom of the world and nothing outside of it
+    #[inline]
+    pub fn clip_to_bounds(&self, y: i32, block: Block) -> Block {
+        if !self.contains_y(y) {
+            Block::AIR
+        } else if self.min_y == Some(y) {
+            Block::BEDROCK
+        } else {
+            block
+        }
+    }
+
+    /// the surface height of a biome for a 0-1 terrain noise value
+    #[inline]
+    pub fn height(&self, biome: Biome, terrain: f32) -> f32 {
+        let profile = self.heights[biome as usize];
+        let height = profile.min_height
+            + terrain.powf(profile.flattening_exp) * (profile.max_height - profile.min_height);
+        let height = if height > 0.0 {
+            height * self.height_scale
+        } else {
+            height
+        };
+        SEA_LEVEL as f32 + height
+    }
+}
+
+// integers are accepted too so `frequency = 1` doesn't fail to load
+fn get_float(table: &Table, key: &str) -> Option<f64> {
+    let item = table.get(key)?;
+    item.as_float()
+        .or_else(|| item.as_integer().map(|i| i as f64))
+}
@@ -0,0 +1,235 @@
+use glam::{UVec2, UVec3, uvec3};
+use toml_edit::{Item, Table};
+
+use crate::world::{block::Block, mesher::Direction};
+
+// shapes are made of boxes on a grid of sixteenths of a block
+pub const SHAPE_GRID: u32 = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ShapeBox {
+    pub min: UVec3,
+    pub max: UVec3,
+}
+
+impl ShapeBox {
+    pub const FULL: ShapeBox = ShapeBox::new(UVec3::ZERO, UVec3::splat(SHAPE_GRID));
+
+    #[inline]
+    pub const fn new(min: UVec3, max: UVec3) -> Self {
+        Self { min, max }
+    }
+
+    /// whether the box reaches the side of the block in `dir`
+    #[inline]
+    pub fn touches(&self, dir: Direction) -> bool {
+        let axis = dir as usize / 2;
+        if dir as usize % 2 == 1 {
+            self.max[axis] == SHAPE_GRID
+        } else {
+            self.min[axis] == 0
+        }
+    }
+
+    /// the box's face in `dir` as (min, max) on that side's axes, see [`face_axes`]
+    #[inline]
+    pub fn face_rect(&self, dir: Direction) -> (UVec2, UVec2) {
+        let [_, u, v] = face_axes(dir as usize);
+        (
+            UVec2::new(self.min[u], self.min[v]),
+            UVec2::new(self.max[u], self.max[v]),
+        )
+    }
+}
+
+/// How a block is meshed, set with `shape` in blocks.toml.
+#[derive(Clone, PartialEq, Default, Debug)]
+pub enum BlockShape {
+    #[default]
+    Cube,
+    /// slabs, stairs or anything else made of a few boxes
+    Boxes(Vec<ShapeBox>),
+    /// two crossing planes, for plants
+    Cross,
+    /// a post with rails to the neighbouring fences and full blocks
+    Fence,
+    /// a thin wall reaching to the neighbouring panes and full blocks
+    Pane,
+}
+
+impl BlockShape {
+    /// `shape = "cube" | "slab" | "stairs" | "cross" | "fence" | "pane"`, or
+    /// `boxes = [[x0, y0, z0, x1, y1, z1], ...]` in sixteenths of a block
+    pub fn from_entry(entry: &Table) -> Result<Self, String> {
+        if let Some(boxes) = entry.get("boxes") {
+            let boxes = boxes
+                .as_array()
+                .filter(|boxes| !boxes.is_empty())
+                .ok_or("boxes has to be a list of [x0, y0, z0, x1, y1, z1]")?
+                .iter()
+                .map(|shape_box| {
+                    let bounds = shape_box
+                        .as_array()
+                        .filter(|bounds| bounds.len() == 6)
+                        .and_then(|bounds| {
+                            bounds
+                                .iter()
+                                .map(|value| value.as_integer())
+                                .collect::<Option<Vec<_>>>()
+                        })
+                        .filter(|bounds| {
+                            bounds
+                                .iter()
+                                .all(|value| (0..=SHAPE_GRID as i64).contains(value))
+                        })
+                        .ok_or("boxes have to be [x0, y0, z0, x1, y1, z1] between 0 and 16")?;
+                    let [x0, y0, z0, x1, y1, z1] = [0, 1, 2, 3, 4, 5].map(|i| bounds[i] as u32);
+                    if x0 >= x1 || y0 >= y1 || z0 >= z1 {
+                        return Err("boxes can't be empty");
+                    }
+                    Ok(ShapeBox::new(uvec3(x0, y0, z0), uvec3(x1, y1, z1)))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(BlockShape::Boxes(boxes));
+        }
+
+        let Some(shape) = entry.get("shape").and_then(Item::as_str) else {
+            return Ok(BlockShape::Cube);
+        };
+        Ok(match shape {
+            "cube" => BlockShape::Cube,
+            "slab" => BlockShape::Boxes(vec![ShapeBox::new(UVec3::ZERO, uvec3(16, 8, 16))]),
+            // the step is at the front, the high side at the back
+            "stairs" => BlockShape::Boxes(vec![
+                ShapeBox::new(UVec3::ZERO, uvec3(16, 8, 16)),
+                ShapeBox::new(uvec3(0, 8, 0), uvec3(16, 16, 8)),
+            ]),
+            "cross" => BlockShape::Cross,
+            "fence" => BlockShape::Fence,
+            "pane" => BlockShape::Pane,
+            _ => return Err(format!("unknown shape {shape}")),
+        })
+    }
+
+    #[inline]
+    pub fn is_cube(&self) -> bool {
+        *self == BlockShape::Cube
+    }
+
+    /// fences connect to fences, panes to panes and both to solid full blocks
+    pub fn connects_to(&self, neighbor: Block, dir: Direction) -> bool {
+        let info = neighbor.info();
+        info.shape == *self
+            || (info.solid && info.coverage[dir.opposite() as usize].contains(FULL_RECT))
+    }
+
+    /// the boxes for a block whose horizontal neighbours `connected` says it connects to,
+    /// nothing for crosses
+    pub fn boxes(&self, connected: impl Fn(Direction) -> bool) -> Vec<ShapeBox> {
+        let (post, arms): (ShapeBox, &[(u32, u32)]) = match self {
+            BlockShape::Cube => return vec![ShapeBox::FULL],
+            BlockShape::Boxes(boxes) => return boxes.clone(),
+            BlockShape::Cross => return Vec::new(),
+            BlockShape::Fence => (
+                ShapeBox::new(uvec3(6, 0, 6), uvec3(10, 16, 10)),
+                &[(6, 9), (12, 15)],
+            ),
+            BlockShape::Pane => (ShapeBox::new(uvec3(7, 0, 7), uvec3(9, 16, 9)), &[(0, 16)]),
+        };
+        // fence rails are thinner than the post, panes are the same thickness all the way
+        let thickness = match self {
+            BlockShape::Fence => (7, 9),
+            _ => (post.min.x, post.max.x),
+        };
+
+        let mut boxes = vec![post];
+        for dir in [
+            Direction::Left,
+            Direction::Right,
+            Direction::Back,
+            Direction::Front,
+        ] {
+            if !connected(dir) {
+                continue;
+            }
+            // from the post out to the side of the block
+            let axis = dir as usize / 2;
+            let across = 2 - axis;
+            let (start, end) = if dir as usize % 2 == 1 {
+                (post.max[axis], SHAPE_GRID)
+            } else {
+                (0, post.min[axis])
+            };
+            for &(bottom, top) in arms {
+                let (mut min, mut max) = (uvec3(0, bottom, 0), uvec3(0, top, 0));
+                (min[axis], max[axis]) = (start, end);
+                (min[across], max[across]) = thickness;
+                boxes.push(ShapeBox::new(min, max));
+            }
+        }
+        boxes
+    }
+
+    /// what the shape covers of each side of the block, for culling its neighbours' faces.
+    /// only fixed boxes count, connections depend on the neighbours
+    pub fn coverage(&self) -> [FaceCoverage; 6] {
+        let boxes = match self {
+            BlockShape::Cube => &[ShapeBox::FULL][..],
+            BlockShape::Boxes(boxes) => boxes,
+            BlockShape::Cross | BlockShape::Fence | BlockShape::Pane => &[],
+        };
+        Direction::ALL.map(|dir| {
+            let mut coverage = FaceCoverage::default();
+            for shape_box in boxes.iter().filter(|shape_box| shape_box.touches(dir)) {
+                coverage.add(shape_box.face_rect(dir));
+            }
+            coverage
+        })
+    }
+}
+
+pub const FULL_RECT: (UVec2, UVec2) = (UVec2::ZERO, UVec2::splat(SHAPE_GRID));
+
+/// The part of one side of a block that's covered, a bit per sixteenth by sixteenth.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct FaceCoverage([u16; SHAPE_GRID as usize]);
+
+impl FaceCoverage {
+    #[inline]
+    fn row_mask(min: u32, max: u32) -> u16 {
+        (((1u32 << max) - 1) & !((1u32 << min) - 1)) as u16
+    }
+
+    pub fn add(&mut self, (min, max): (UVec2, UVec2)) {
+        let mask = Self::row_mask(min.x, max.x);
+        for row in &mut self.0[min.y as usize..max.y as usize] {
+            *row |= mask;
+        }
+    }
+
+    pub fn contains(&self, (min, max): (UVec2, UVec2)) -> bool {
+        let mask = Self::row_mask(min.x, max.x);
+        self.0[min.y as usize..max.y as usize]
+            .iter()
+            .all(|row| row & mask == mask)
+    }
+}
+
+/// Whether `neighbor` hides the part of `block`'s face in `rect` where it touches `neighbor`'s
+/// `side`. Only opaque blocks and more of the same block hide anything, so the ground under
+/// water and water against glass are still drawn.
+#[inline]
+pub fn hides_face(neighbor: Block, block: Block, side: Direction, rect: (UVec2, UVec2)) -> bool {
+    let info = neighbor.info();
+    (!info.transparent || neighbor == block) && info.coverage[side as usize].contains(rect)
+}
+
+// the axis a face points along, then the two it spans
+#[inline]
+pub fn face_axes(dir: usize) -> [usize; 3] {
+    match dir / 2 {
+        0 => [0, 2, 1],
+        1 => [1, 0, 2],
+        _ => [2, 0, 1],
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use glam::*;
+
+use crate::{
+    CHUNK_SIZE, SEA_LEVEL,
+    utils::hash_2d,
+    world::{
+        NoiseFunctions, biome::terrain_noise, block::Block, caves::terrain_block_at,
+        coords::LocalPos, mesher::Chunk,
+    },
+};
+
+// structures are rolled once per cell, at most one per cell
+pub const STRUCTURE_CELL_SIZE: i32 = 8;
+// how far (in blocks) a structure can reach from its origin column
+pub const MAX_STRUCTURE_RADIUS: i32 = 4;
+const MAX_SURFACE_HEIGHT: i32 = 140;
+const MAX_STRUCTURE_HEIGHT: i32 = MAX_SURFACE_HEIGHT + 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StructureKind {
+    Tree,
+    Boulder,
+    Ruin,
+}
+
+/// A structure whose origin and shape only depend on the seed and its cell,
+/// so every chunk it overlaps can rebuild exactly the same blocks on its own.
+#[derive(Clone, Copy, Debug)]
+pub struct PlacedStructure {
+    pub kind: StructureKind,
+    pub origin: IVec3, // the first block above the surface
+    pub variant: u64,
+}
+
+impl PlacedStructure {
+    /// rolls the structure of a cell, if any
+    pub fn in_cell(cell: IVec2, noises: &NoiseFunctions) -> Option<Self> {
+        let hash = hash_2d(noises.seed, cell);
+        let roll = (hash & 0xffff) as f32 / 65535.0;
+
+        let offset = ivec2(
+            ((hash >> 16) % STRUCTURE_CELL_SIZE as u64) as i32,
+            ((hash >> 24) % STRUCTURE_CELL_SIZE as u64) as i32,
+        );
+        let column = cell * STRUCTURE_CELL_SIZE + offset;
+        let (max_y, biome) = terrain_noise(column.as_vec2(), noises);
+
+        // nothing grows underwater or on the snowy/rocky peaks
+        if max_y <= SEA_LEVEL + 1 || max_y > MAX_SURFACE_HEIGHT {
+            return None;
+        }
+
+        let trees = biome.info().tree_chance;
+        let kind = match roll {
+            _ if roll < trees => StructureKind::Tree,
+            _ if roll < trees + 0.04 => StructureKind::Boulder,
+            _ if roll < trees + 0.045 => StructureKind::Ruin,
+            _ => return None,
+        };
+
+        let origin = ivec3(column.x, max_y, column.y);
+        // caves, ravines or overhangs may have taken the ground away
+        if !terrain_block_at(origin - IVec3::Y, noises).is_solid()
+            || !terrain_block_at(origin, noises).is_air()
+        {
+            return None;
+        }
+
+        Some(Self {
+            kind,
+            origin,
+            variant: hash >> 32,
+        })
+    }
+
+    /// every block of the structure in world space
+    pub fn blocks(&self) -> Vec<(IVec3, Block)> {
+        let mut blocks = Vec::new();
+        let v = self.variant;
+
+        match self.kind {
+            StructureKind::Tree => {
+                let height = 4 + (v % 3) as i32;
+                for y in height - 2..=height + 1 {
+                    let radius: i32 = if y >= height { 1 } else { 2 };
+                    for z in -radius..=radius {
+                        for x in -radius..=radius {
+                            // randomly round off the corners
+                            let corner = x.abs() == radius && z.abs() == radius;
+                            if corner
+                                && (y == height + 1
+                                    || (v >> (x + z * 3 + y * 5).rem_euclid(32)) & 1 == 0)
+                            {
+                                continue;
+                            }
+                            blocks.push((ivec3(x, y, z), Block::LEAF));
+                        }
+                    }
+                }
+                // trunk goes last so it replaces the leaves around it
+                for y in 0..height {
+                    blocks.push((ivec3(0, y, 0), Block::WOOD));
+                }
+            }
+            StructureKind::Boulder => {
+                let radius = 1 + (v % 2) as i32;
+                for y in -radius..=radius {
+                    for z in -radius..=radius {
+                        for x in -radius..=radius {
+                            if x * x + y * y + z * z <= radius * radius + 1 {
+                                blocks.push((ivec3(x, y, z), Block::STONE));
+                            }
+                        }
+                    }
+                }
+            }
+            StructureKind::Ruin => {
+                let half = MAX_STRUCTURE_RADIUS - 1;
+                let door = (v % 4) as i32;
+                for z in -half..=half {
+                    for x in -half..=half {
+                        if x.abs() != half && z.abs() != half {
+                            continue;
+                        }
+                        let side = match (x, z) {
+                            (_, z) if z == -half => 0,
+                            (x, _) if x == half => 1,
+                            (_, z) if z == half => 2,
+                            _ => 3,
+                        };
+                        if side == door && (x == 0 || z == 0) {
+                            continue;
+                        }
+                        // crumbled walls of uneven height
+                        let height = (v >> ((x + half) * 2 + (z + half) * 5).rem_euclid(31)) % 4;
+                        for y in 0..height as i32 {
+                            let block = if y == 2 { Block::PLANK } else { Block::STONE };
+                            blocks.push((ivec3(x, y, z), block));
+                        }
+                    }
+                }
+            }
+        }
+
+        blocks
+            .into_iter()
+            .map(|(pos, block)| (self.origin + pos, block))
+            .collect()
+    }
+}
+
+/// every structure that could reach into the given block columns (inclusive)
+pub fn structures_near(min: IVec2, max: IVec2, noises: &NoiseFunctions) -> Vec<PlacedStructure> {
+    let min_cell = (min - MAX_STRUCTURE_RADIUS).div_euclid(IVec2::splat(STRUCTURE_CELL_SIZE));
+    let max_cell = (max + MAX_STRUCTURE_RADIUS).div_euclid(IVec2::splat(STRUCTURE_CELL_SIZE));
+
+    let mut structures = Vec::new();
+    if !noises.settings.structures {
+        return structures;
+    }
+    for cell_z in min_cell.y..=max_cell.y {
+        for cell_x in min_cell.x..=max_cell.x {
+            if let Some(structure) = PlacedStructure::in_cell(ivec2(cell_x, cell_z), noises) {
+                structures.push(structure);
+            }
+        }
+    }
+    structures
+}
+
+/// writes the parts of nearby structures that fall inside this chunk, never touching neighbours
+pub fn place_structures(chunk: &mut Chunk, noises: &NoiseFunctions) {
+    let min = *chunk.pos.origin();
+    let max = min + CHUNK_SIZE - 1;
+
+    for structure in structures_near(min.xz(), max.xz(), noises) {
+        for (pos, block) in structure.blocks() {
+            if pos.cmplt(min).any() || pos.cmpgt(max).any() {
+                continue;
+            }
+            let local = LocalPos::new(pos - min);
+            let current = chunk.blocks.get(local);
+            if current.is_air() || current == Block::LEAF {
+                chunk.blocks.set(local, block);
+            }
+        }
+    }
+}
+
+/// The structure blocks inside a box, for looking into chunks that aren't generated yet
+/// without rolling and building the structures again for every block.
+pub struct StructureBlocks(HashMap<IVec3, Vec<Block>>);
+
+impl StructureBlocks {
+    /// for the blocks from `min` to `max` (inclusive)
+    pub fn near(min: IVec3, max: IVec3, noises: &NoiseFunctions) -> Self {
+        let mut blocks = HashMap::<IVec3, Vec<Block>>::new();
+        // structures only ever spawn between the sea and the peaks
+        if max.y < SEA_LEVEL - MAX_STRUCTURE_RADIUS || min.y > MAX_STRUCTURE_HEIGHT {
+            return Self(blocks);
+        }
+        for structure in structures_near(min.xz(), max.xz(), noises) {
+            for (pos, block) in structure.blocks() {
+                if pos.cmpge(min).all() && pos.cmple(max).all() {
+                    blocks.entry(pos).or_default().push(block);
+                }
+            }
+        }
+        Self(blocks)
+    }
+
+    /// the block at a world position once structures are placed on top of `terrain`, the
+    /// same way [`place_structures`] does it
+    pub fn block_at(&self, pos: IVec3, terrain: Block) -> Block {
+        self.0.get(&pos).map_or(terrain, |blocks| {
+            blocks.iter().fold(terrain, |block, &structure_block| {
+                if block.is_air() || block == Block::LEAF {
+                    structure_block
+                } else {
+                    block
+                }
+            })
+        })
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use rand::{Rng, rngs::ThreadRng};
+
+use crate::{
+    CHUNK_SIZE,
+    ecs::*,
+    world::{
+        ChunkMarker, WorldData,
+        block::{Block, MAX_BLOCKS},
+        coords::{BlockPos, ChunkPos, LocalPos},
+        events::{BlockChangeCause, BlockChanged},
+        interaction::{affected_chunks, update_chunks},
+        light::{Light, LightEngine},
+        mesher::{Chunk, Direction},
+        palette::PalettedStorage,
+    },
+};
+
+// caps on the work done in one fixed update, the rest carries over to the next one
+const MAX_SCHEDULED_PER_TICK: usize = 1024;
+const MAX_NEIGHBOR_UPDATES_PER_TICK: usize = 4096;
+// blocks picked at random in every loaded chunk per fixed update, at 64 per second every block
+// gets a random tick about once a minute
+const RANDOM_TICKS_PER_CHUNK: usize = 8;
+
+/// called with the block at `pos`, which is always loaded
+pub type TickFn = fn(&mut TickContext, BlockPos, Block);
+
+/// What a block does when the world around it changes. Every hook is optional,
+/// blocks without any of them never tick.
+#[derive(Clone, Copy, Default)]
+pub struct BlockBehavior {
+    // the block itself or one of its 6 neighbours changed
+    pub neighbor_update: Option<TickFn>,
+    // a tick the block asked for with `TickContext::schedule` is due
+    pub scheduled_tick: Option<TickFn>,
+    // picked at random, RANDOM_TICKS_PER_CHUNK times per loaded chunk and fixed update
+    pub random_tick: Option<TickFn>,
+}
+
+/// behaviours indexed by block id
+#[derive(Resource)]
+pub struct BlockBehaviors(Vec<BlockBehavior>);
+
+impl Default for BlockBehaviors {
+    fn default() -> Self {
+        Self(vec![BlockBehavior::default(); MAX_BLOCKS])
+    }
+}
+
+impl BlockBehaviors {
+    pub fn register(&mut self, block: Block, behavior: BlockBehavior) -> &mut Self {
+        self.0[block.0 as usize] = behavior;
+        self
+    }
+
+    #[inline]
+    pub fn get(&self, block: Block) -> &BlockBehavior {
+        &self.0[block.0 as usize]
+    }
+}
+
+/// Everything waiting to happen to the loaded blocks: neighbour updates, delayed ticks and
+/// chunks that have to be remeshed once the frame's changes are done.
+#[derive(Resource, Default)]
+pub struct BlockUpdates {
+    tick: u64,
+    neighbor_updates: VecDeque<BlockPos>,
+    queued_neighbor_updates: HashSet<BlockPos>,
+    // by the tick they're due on
+    scheduled: BTreeMap<u64, Vec<BlockPos>>,
+    queued_scheduled: HashSet<BlockPos>,
+    remesh: HashSet<ChunkPos>,
+}
+
+impl BlockUpdates {
+    /// queues a neighbour update for `pos` and the 6 blocks next to it
+    pub fn update_neighbors(&mut self, pos: BlockPos) {
+        for pos in [pos].into_iter().chain(
+            Direction::NORMALS
+                .into_iter()
+                .map(|normal| pos.offset(IVec3::from(normal))),
+        ) {
+            if self.queued_neighbor_updates.insert(pos) {
+                self.neighbor_updates.push_back(pos);
+            }
+        }
+    }
+
+    /// a scheduled tick `delay` fixed updates from now, unless one is already pending there
+    pub fn schedule(&mut self, pos: BlockPos, delay: u64) {
+        if self.queued_scheduled.insert(pos) {
+            self.scheduled
+                .entry(self.tick + delay.max(1))
+                .or_default()
+                .push(pos);
+        }
+    }
+
+    /// remeshes the chunks at the end of the frame, however often they changed until then
+    pub fn remesh(&mut self, chunks: impl IntoIterator<Item = ChunkPos>) {
+        self.remesh.extend(chunks);
+    }
+}
+
+/// What behaviours get to work with. Blocks set through it queue the usual neighbour updates,
+/// remeshes and relighting (once the tick is done).
+pub struct TickContext<'a> {
+    pub chunks: &'a mut HashMap<ChunkPos, Chunk>,
+    pub updates: &'a mut BlockUpdates,
+    pub rng: ThreadRng,
+    changed: Vec<BlockPos>,
+    events: Vec<BlockChanged>,
+}
+
+impl TickContext<'_> {
+    #[inline]
+    pub fn block(&self, pos: BlockPos) -> Option<Block> {
+        let (chunk_pos, local) = pos.split();
+        Some(self.chunks.get(&chunk_pos)?.blocks.get(local))
+    }
+
+    /// the block and its fluid level
+    #[inline]
+    pub fn fluid(&self, pos: BlockPos) -> Option<(Block, u8)> {
+        let (chunk_pos, local) = pos.split();
+        let chunk = self.chunks.get(&chunk_pos)?;
+        Some((chunk.blocks.get(local), chunk.fluid.get(local)))
+    }
+
+    #[inline]
+    pub fn light(&self, pos: BlockPos) -> Option<Light> {
+        let (chunk_pos, local) = pos.split();
+        Some(self.chunks.get(&chunk_pos)?.light.get(local))
+    }
+
+    /// false if the chunk isn't loaded or the block was already set
+    pub fn set_block(&mut self, pos: BlockPos, block: Block) -> bool {
+        self.set_fluid(pos, block, 0)
+    }
+
+    pub fn set_fluid(&mut self, pos: BlockPos, block: Block, level: u8) -> bool {
+        let (chunk_pos, local) = pos.split();
+        let Some(chunk) = self.chunks.get_mut(&chunk_pos) else {
+            return false;
+        };
+        let (old, old_fluid) = (chunk.blocks.get(local), chunk.fluid.get(local));
+        // otherwise the chunk would be saved and its neighbours updated for nothing
+        if (old, old_fluid) == (block, level) {
+            return false;
+        }
+        chunk.blocks.set(local, block);
+        chunk.fluid.set(local, level);
+        chunk.modified = true;
+
+        self.updates.remesh(affected_chunks(chunk_pos, local));
+        self.updates.update_neighbors(pos);
+        self.changed.push(pos);
+        if old != block {
+            self.events.push(BlockChanged {
+                pos,
+                old,
+                old_fluid,
+                new: block,
+                cause: BlockChangeCause::BlockUpdate,
+            });
+        }
+        true
+    }
+
+    #[inline]
+    pub fn schedule(&mut self, pos: BlockPos, delay: u64) {
+        self.updates.schedule(pos, delay);
+    }
+}
+
+pub fn tick_blocks(
+    mut updates: ResMut<BlockUpdates>,
+    behaviors: Res<BlockBehaviors>,
+    world_data: Res<WorldData>,
+    mut block_changed: EventWriter<BlockChanged>,
+) {
+    updates.tick += 1;
+    let tick = updates.tick;
+
+    let mut chunks = world_data.chunks.write().unwrap();
+    let mut ctx = TickContext {
+        chunks: &mut chunks,
+        updates: &mut updates,
+        rng: rand::rng(),
+        changed: Vec::new(),
+        events: Vec::new(),
+    };
+
+    let mut due = Vec::new();
+    while due.len() < MAX_SCHEDULED_PER_TICK
+        && let Some(mut entry) = ctx.updates.scheduled.first_entry()
+        && *entry.key() <= tick
+    {
+        let positions = entry.get_mut();
+        let rest = positions
+            .len()
+            .saturating_sub(MAX_SCHEDULED_PER_TICK - due.len());
+        due.extend(positions.drain(rest..));
+        if positions.is_empty() {
+            entry.remove();
+        }
+    }
+    for pos in due {
+        ctx.updates.queued_scheduled.remove(&pos);
+        if let Some(block) = ctx.block(pos)
+            && let Some(scheduled_tick) = behaviors.get(block).scheduled_tick
+        {
+            scheduled_tick(&mut ctx, pos, block);
+        }
+    }
+
+    // only what was queued before this tick, the updates these cause run on the next one
+    let neighbor_updates = ctx.updates.neighbor_updates.len();
+    for _ in 0..neighbor_updates.min(MAX_NEIGHBOR_UPDATES_PER_TICK) {
+        let pos = ctx.updates.neighbor_updates.pop_front().unwrap();
+        ctx.updates.queued_neighbor_updates.remove(&pos);
+        if let Some(block) = ctx.block(pos)
+            && let Some(neighbor_update) = behaviors.get(block).neighbor_update
+        {
+            neighbor_update(&mut ctx, pos, block);
+        }
+    }
+
+    let mut random_ticks = Vec::new();
+    for chunk in ctx.chunks.values() {
+        // uniform chunks (air, stone) are most of the world and usually have nothing to tick
+        if let PalettedStorage::Single(block) = chunk.blocks
+            && behaviors.get(block).random_tick.is_none()
+        {
+            continue;
+        }
+        for _ in 0..RANDOM_TICKS_PER_CHUNK {
+            let index = ctx
+                .rng
+                .random_range(0..(CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize);
+            let block = chunk.blocks.get_index(index);
+            if behaviors.get(block).random_tick.is_some() {
+                random_ticks.push(chunk.pos.block(LocalPos::from_index(index)));
+            }
+        }
+    }
+    for pos in random_ticks {
+        // an earlier tick may have changed it already
+        if let Some(block) = ctx.block(pos)
+            && let Some(random_tick) = behaviors.get(block).random_tick
+        {
+            random_tick(&mut ctx, pos, block);
+        }
+    }
+
+    let changed = std::mem::take(&mut ctx.changed);
+    let events = std::mem::take(&mut ctx.events);
+    if !changed.is_empty() {
+        let mut light = LightEngine::new(&mut chunks);
+        light.update_blocks(changed);
+        updates.remesh(light.changed);
+    }
+    // sent once the light is up to date, like place_block does
+    block_changed.write_batch(events);
+}
+
+pub fn remesh_changed_chunks(
+    mut commands: Commands,
+    mut updates: ResMut<BlockUpdates>,
+    chunks: Query<(Entity, &Transform), With<ChunkMarker>>,
+) {
+    if updates.remesh.is_empty() {
+        return;
+    }
+    update_chunks(
+        &mut commands,
+        chunks.iter().collect(),
+        updates.remesh.drain().collect(),
+    );
+}